@@ -0,0 +1,152 @@
+// src/audio/acid.rs
+// アシッドベース（TB-303 風）。モノフォニックで、24dB のローパスをディケイで閉じていく
+//
+// アクセントの付いたステップは音量・フィルターの開き・レゾナンスが強くなり、ディケイが短くなる。
+// スライドの付いたステップは次のステップまで音を伸ばし、次のノートへ滑らかに移る
+// （エンベロープはかけ直さない）。
+use crate::sequencer::pattern::{FilterMode, InstrumentType};
+
+use super::filter::{Coefs, Svf};
+use super::osc::{Oscillator, Wave};
+use super::voice::{Hit, Instrument, ParamSpec, Registry, Voice};
+
+pub fn register(registry: &mut Registry) {
+    registry.register(InstrumentType::Acid, Box::new(Acid));
+}
+
+// ゲートが閉じてから音が消えるまで
+const RELEASE_TIME: f32 = 0.008;
+// アクセントなしの音量（アクセント付きで 1.0）
+const NORMAL_GAIN: f32 = 0.6;
+
+struct Acid;
+
+impl Instrument for Acid {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Wave", unit: "wave", min: 0.0, max: 1.0, default: 0.0 },
+            ParamSpec { name: "Cutoff", unit: "Hz", min: 40.0, max: 4000.0, default: 320.0 },
+            ParamSpec { name: "Resonance", unit: "", min: 0.0, max: 1.0, default: 0.75 },
+            ParamSpec { name: "Env Mod", unit: "oct", min: 0.0, max: 6.0, default: 3.0 },
+            ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 2.0, default: 0.4 },
+            ParamSpec { name: "Accent", unit: "", min: 0.0, max: 1.0, default: 0.6 },
+            ParamSpec { name: "Slide", unit: "s", min: 0.01, max: 0.3, default: 0.06 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let mut voice = AcidVoice {
+            osc: Oscillator::new(0.0),
+            filters: [Svf::default(), Svf::default()],
+            wave: Wave::from_param(params[0]),
+            cutoff: params[1],
+            resonance: params[2],
+            env_mod: params[3],
+            accent_amount: params[5],
+            slide_time: params[6],
+            freq: hit.freq(),
+            target_freq: hit.freq(),
+            filter_env: 1.0,
+            filter_coef: 0.0,
+            accent: 0.0,
+            amp: 0.0,
+            gain: 0.0,
+            time: 0.0,
+            gate: hit.gate,
+            sample_rate,
+            decay: params[4] * hit.decay,
+        };
+        voice.start(hit);
+        Box::new(voice)
+    }
+
+    fn polyphony(&self) -> usize {
+        1
+    }
+}
+
+struct AcidVoice {
+    osc: Oscillator,
+    filters: [Svf; 2], // 12dB を2段重ねて 24dB
+    wave: Wave,
+    cutoff: f32,
+    resonance: f32,
+    env_mod: f32, // フィルターエンベロープの深さ（オクターブ）
+    accent_amount: f32,
+    slide_time: f32,
+    freq: f32,
+    target_freq: f32,
+    filter_env: f32,
+    filter_coef: f32, // 1サンプルごとに filter_env にかける値
+    accent: f32,      // アクセントの強さ（アクセントなしなら 0）
+    amp: f32,
+    gain: f32,
+    time: f32,
+    gate: f32, // この時刻でゲートを閉じる
+    sample_rate: f32,
+    decay: f32,
+}
+
+impl AcidVoice {
+    // アクセントに合わせてエンベロープの深さと長さを決める
+    fn start(&mut self, hit: Hit) {
+        self.accent = if hit.accent { self.accent_amount } else { 0.0 };
+        // アクセント付きはディケイを短く（303 ではいちばん短くなる）
+        let decay = self.decay * (1.0 - 0.7 * self.accent);
+        self.filter_coef = (-5.0 / (decay.max(0.01) * self.sample_rate)).exp();
+        self.gain = hit.gain * (NORMAL_GAIN + (1.0 - NORMAL_GAIN) * self.accent);
+    }
+}
+
+impl Voice for AcidVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        let dt = 1.0 / self.sample_rate;
+        let release = (-1.0 / (RELEASE_TIME * self.sample_rate)).exp();
+        let slide = 1.0 - (-1.0 / (self.slide_time * self.sample_rate)).exp();
+        for sample in out {
+            // ゲート中は素早く立ち上げ、閉じたら短く消す
+            if self.time < self.gate {
+                self.amp += (1.0 - self.amp) * 0.05;
+            } else {
+                self.amp *= release;
+                if self.amp < 1e-4 {
+                    return false;
+                }
+            }
+            // スライド中は対数的に目標のノートへ近づく
+            self.freq *= (self.target_freq / self.freq).powf(slide);
+
+            let env = self.filter_env;
+            self.filter_env *= self.filter_coef;
+            let octaves = (self.env_mod + 2.0 * self.accent) * env;
+            let cutoff = (self.cutoff * 2.0_f32.powf(octaves)).min(self.sample_rate * 0.45);
+            let resonance = (self.resonance + 0.15 * self.accent).min(0.98);
+
+            // レゾナンスは1段目だけ（両方にかけると山が鋭くなりすぎる）
+            let coefs = [
+                Coefs::new(cutoff, resonance, self.sample_rate),
+                Coefs::new(cutoff, 0.0, self.sample_rate),
+            ];
+            let mut value = self.osc.next(self.wave, self.freq, self.sample_rate, 0.5);
+            for (filter, coefs) in self.filters.iter_mut().zip(&coefs) {
+                value = filter.process(value, coefs, FilterMode::LowPass);
+            }
+            *sample += (value * 0.5).clamp(-1.0, 1.0) * self.amp * self.gain;
+            self.time += dt;
+        }
+        true
+    }
+
+    // 鳴っている音のまま次のノートへ移る（フィルターのエンベロープはかけ直さない）
+    fn slide(&mut self, hit: Hit) -> bool {
+        // ゲートを閉じて消えかけている音は生き返らせない
+        if self.time >= self.gate {
+            return false;
+        }
+        self.target_freq = hit.freq();
+        self.gate = self.time + hit.gate;
+        self.accent = if hit.accent { self.accent_amount } else { 0.0 };
+        self.gain = hit.gain * (NORMAL_GAIN + (1.0 - NORMAL_GAIN) * self.accent);
+        true
+    }
+}
@@ -0,0 +1,167 @@
+// src/audio/compressor.rs
+// サイドチェインのかけられるコンプレッサー
+//
+// 検出には自分の入力のほか、別のトラックの音（フィルターの後、インサートとフェーダーの前）か、
+// そのトラックの発音（ゴーストサイドチェイン）を使える。ミュートしたトラックでもキーになる。
+// 減衰量は dB で追いかけ、UI のメーター用に直近のブロックの最大値を残す。
+use crate::sequencer::pattern::MAX_TRACKS;
+
+use super::effects::{Context, Effect};
+use super::voice::ParamSpec;
+
+// ゴーストサイドチェインで発音1回をキーの音とみなす長さ（秒）
+const GHOST_HOLD: f32 = 0.02;
+
+// Key パラメータの値からキーにするトラック（None は自分の入力）
+pub fn key_track(value: f32) -> Option<usize> {
+    (value.round() as usize).checked_sub(1)
+}
+
+// 並びは Threshold, Ratio, Attack, Release, Makeup, Key, Ghost
+pub static PARAMS: [ParamSpec; 7] = [
+    ParamSpec { name: "Threshold", unit: "dB", min: -48.0, max: 0.0, default: -18.0 },
+    ParamSpec { name: "Ratio", unit: ":1", min: 1.0, max: 20.0, default: 4.0 },
+    ParamSpec { name: "Attack", unit: "ms", min: 0.1, max: 100.0, default: 5.0 },
+    ParamSpec { name: "Release", unit: "ms", min: 10.0, max: 1000.0, default: 150.0 },
+    ParamSpec { name: "Makeup", unit: "dB", min: 0.0, max: 24.0, default: 0.0 },
+    ParamSpec { name: "Key", unit: "key", min: 0.0, max: MAX_TRACKS as f32, default: 0.0 },
+    ParamSpec { name: "Ghost", unit: "switch", min: 0.0, max: 1.0, default: 0.0 },
+];
+
+pub struct Compressor {
+    reduction: f32, // 今の減衰量（dB、正の値）
+    ghost: usize,   // ゴーストのキーを鳴らしている残りのサンプル数
+    ghost_level: f32,
+    meter: f32, // 直近のブロックでの最大の減衰量
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self {
+            reduction: 0.0,
+            ghost: 0,
+            ghost_level: 0.0,
+            meter: 0.0,
+        }
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        let (threshold, ratio, makeup) = (params[0], params[1], params[4]);
+        let coef = |ms: f32| 1.0 - (-1.0 / (ms / 1000.0 * ctx.sample_rate)).exp();
+        let (attack, release) = (coef(params[2]), coef(params[3]));
+        // キーのトラックがなければ（消したときなど）何も来ないものとする
+        let key = key_track(params[5]).map(|track| ctx.keys.get(track));
+        let ghost = params[6] >= 0.5;
+        let hold = (GHOST_HOLD * ctx.sample_rate) as usize;
+        let triggers = match key {
+            Some(Some(key)) if ghost => &key.triggers[..],
+            _ => &[],
+        };
+        let mut triggers = triggers.iter().peekable();
+
+        self.meter = 0.0;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let level = match key {
+                // 発音の位置から GHOST_HOLD の間だけ、強さの分の音が来たことにする
+                Some(Some(_)) if ghost => {
+                    while let Some((_, gain)) = triggers.next_if(|(at, _)| *at <= i) {
+                        self.ghost = hold;
+                        self.ghost_level = *gain;
+                    }
+                    if self.ghost > 0 {
+                        self.ghost -= 1;
+                        self.ghost_level
+                    } else {
+                        0.0
+                    }
+                }
+                Some(Some(key)) => key.level.get(i).copied().unwrap_or(0.0),
+                Some(None) => 0.0,
+                None => l.abs().max(r.abs()),
+            };
+            let over = (20.0 * level.max(1e-6).log10() - threshold).max(0.0);
+            let target = over * (1.0 - 1.0 / ratio);
+            let speed = if target > self.reduction { attack } else { release };
+            self.reduction += (target - self.reduction) * speed;
+            self.meter = self.meter.max(self.reduction);
+
+            let gain = 10.0_f32.powf((makeup - self.reduction) / 20.0);
+            *l *= gain;
+            *r *= gain;
+        }
+    }
+
+    fn meter(&self) -> Option<f32> {
+        Some(self.meter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::effects::Key;
+
+    const RATE: f32 = 44100.0;
+    const LEN: usize = 4410;
+
+    // 1ms アタック・100ms リリース、-18dB で 4:1。key は Key パラメータ、ghost は Ghost
+    fn params(key: f32, ghost: bool) -> [f32; 7] {
+        [-18.0, 4.0, 1.0, 100.0, 0.0, key, ghost as u8 as f32]
+    }
+
+    // 一定の大きさの入力を通した後の左チャンネル
+    fn process(input: f32, params: &[f32], keys: &[Key]) -> (Vec<f32>, f32) {
+        let ctx = Context { sample_rate: RATE, samples_per_step: 5512.5, keys };
+        let mut compressor = Compressor::new();
+        let (mut left, mut right) = (vec![input; LEN], vec![input; LEN]);
+        compressor.process(&mut left, &mut right, params, &ctx);
+        (left, compressor.meter().unwrap())
+    }
+
+    fn db(value: f32) -> f32 {
+        20.0 * value.log10()
+    }
+
+    #[test]
+    fn loud_input_is_reduced_by_the_ratio() {
+        // 0dB の入力は 18dB 超えているので 13.5dB 下げる
+        let (out, meter) = process(1.0, &params(0.0, false), &[]);
+        assert!((db(out[LEN - 1]) + 13.5).abs() < 0.1, "{}", db(out[LEN - 1]));
+        assert!((meter - 13.5).abs() < 0.1);
+
+        // しきい値より小さければそのまま
+        let (out, meter) = process(0.1, &params(0.0, false), &[]);
+        assert_eq!(out[LEN - 1], 0.1);
+        assert_eq!(meter, 0.0);
+    }
+
+    #[test]
+    fn key_track_ducks_the_input() {
+        let keys = [
+            Key { level: vec![0.0; LEN], triggers: Vec::new() },
+            Key { level: vec![1.0; LEN], triggers: Vec::new() },
+        ];
+        // 小さな入力でも、キーのトラック 2 が大きければ下がる
+        let (out, _) = process(0.1, &params(2.0, false), &keys);
+        assert!((db(out[LEN - 1] / 0.1) + 13.5).abs() < 0.1);
+        // 静かなキーと、いなくなったキーでは下がらない
+        assert_eq!(process(1.0, &params(1.0, false), &keys).0[LEN - 1], 1.0);
+        assert_eq!(process(1.0, &params(5.0, false), &keys).0[LEN - 1], 1.0);
+    }
+
+    #[test]
+    fn ghost_key_follows_the_hits() {
+        // 音は出ていないが、1000 サンプル目で鳴らしたトラック
+        let keys = [Key { level: vec![0.0; LEN], triggers: vec![(1000, 1.0)] }];
+        let (out, meter) = process(0.1, &params(1.0, true), &keys);
+        assert_eq!(out[999], 0.1);
+        assert!(out[1000 + (GHOST_HOLD * RATE) as usize] < 0.05);
+        assert!((meter - 13.5).abs() < 1.0);
+        // リリースで戻っていく
+        assert!(out[LEN - 1] > out[1500]);
+        // ゴーストでなければキーの音（無音）を見る
+        assert_eq!(process(0.1, &params(1.0, false), &keys).1, 0.0);
+    }
+}
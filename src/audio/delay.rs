@@ -0,0 +1,151 @@
+// src/audio/delay.rs
+// テンポ同期のステレオディレイ
+//
+// 遅れは音符の長さで指定し、BPM（ソングのランプも含む）に合わせて伸び縮みする。
+// フィードバックのループにローパスを入れて、繰り返すたびに音がこもっていくようにする。
+use crate::sequencer::song::MIN_BPM;
+
+use super::effects::{Context, Effect};
+use super::voice::ParamSpec;
+
+// 遅れの長さ（名前, ステップ数）。T は3連、D は付点
+pub const DELAY_TIMES: [(&str, f32); 12] = [
+    ("1/32", 0.5),
+    ("1/16T", 2.0 / 3.0),
+    ("1/16", 1.0),
+    ("1/8T", 4.0 / 3.0),
+    ("1/16D", 1.5),
+    ("1/8", 2.0),
+    ("1/4T", 8.0 / 3.0),
+    ("1/8D", 3.0),
+    ("1/4", 4.0),
+    ("1/4D", 6.0),
+    ("1/2", 8.0),
+    ("1 bar", 16.0),
+];
+
+// Time パラメータの値から遅れの長さを選ぶ
+pub fn delay_time(value: f32) -> (&'static str, f32) {
+    DELAY_TIMES[(value.round().max(0.0) as usize).min(DELAY_TIMES.len() - 1)]
+}
+
+// BPM が変わったとき遅れを寄せていく時間（テープのように音程が少し揺れる）
+const GLIDE_TIME: f32 = 0.05;
+// 余韻の上限（フィードバックが深いと計算上はいくらでも長くなる）
+const MAX_TAIL: f32 = 20.0;
+
+// 並びは Time, Feedback, Tone, Ping-Pong, Mix
+pub static PARAMS: [ParamSpec; 5] = [
+    ParamSpec { name: "Time", unit: "note", min: 0.0, max: 11.0, default: 7.0 },
+    ParamSpec { name: "Feedback", unit: "", min: 0.0, max: 0.95, default: 0.4 },
+    ParamSpec { name: "Tone", unit: "Hz", min: 500.0, max: 16000.0, default: 5000.0 },
+    ParamSpec { name: "Ping-Pong", unit: "switch", min: 0.0, max: 1.0, default: 0.0 },
+    ParamSpec { name: "Mix", unit: "", min: 0.0, max: 1.0, default: 0.3 },
+];
+
+pub struct Delay {
+    buffers: [Vec<f32>; 2], // 左右のリングバッファ
+    pos: usize,
+    delay: Option<f32>, // 今の遅れ（サンプル数）
+    damp: [f32; 2],     // フィードバックのローパスの状態
+}
+
+impl Delay {
+    // いちばん遅いテンポでいちばん長い音符の分を先に確保する（テンポを変えても作り直さない）
+    pub fn new(sample_rate: f32) -> Self {
+        let longest = DELAY_TIMES[DELAY_TIMES.len() - 1].1 * 60.0 / MIN_BPM / 4.0 * sample_rate;
+        let len = longest as usize + 2;
+        Self {
+            buffers: [vec![0.0; len], vec![0.0; len]],
+            pos: 0,
+            delay: None,
+            damp: [0.0; 2],
+        }
+    }
+
+    // バッファの delay サンプル前の値（線形補間）
+    fn read(&self, channel: usize, delay: f32) -> f32 {
+        let buffer = &self.buffers[channel];
+        let len = buffer.len();
+        let back = delay.max(1.0);
+        let whole = back as usize;
+        let frac = back - whole as f32;
+        let a = buffer[(self.pos + len - whole) % len];
+        let b = buffer[(self.pos + len - whole - 1) % len];
+        a + (b - a) * frac
+    }
+}
+
+impl Effect for Delay {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        // 遅れは確保したバッファに収まる分まで
+        let longest = (self.buffers[0].len() - 2) as f32;
+        let target = (delay_time(params[0]).1 * ctx.samples_per_step).min(longest);
+        let (feedback, ping_pong, mix) = (params[1], params[3] >= 0.5, params[4]);
+        let damp = 1.0 - (-2.0 * std::f32::consts::PI * params[2] / ctx.sample_rate).exp();
+        let glide = 1.0 - (-1.0 / (GLIDE_TIME * ctx.sample_rate)).exp();
+
+        let mut delay = self.delay.unwrap_or(target);
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            delay += (target - delay) * glide;
+            let wet = [self.read(0, delay), self.read(1, delay)];
+            for (state, w) in self.damp.iter_mut().zip(wet) {
+                *state += (w - *state) * damp;
+            }
+            // ピンポンでは入力を左に入れ、左右のフィードバックを入れ替える
+            let (in_l, in_r) = if ping_pong {
+                ((*l + *r) * 0.5 + self.damp[1] * feedback, self.damp[0] * feedback)
+            } else {
+                (*l + self.damp[0] * feedback, *r + self.damp[1] * feedback)
+            };
+            self.buffers[0][self.pos] = in_l;
+            self.buffers[1][self.pos] = in_r;
+            self.pos = (self.pos + 1) % self.buffers[0].len();
+
+            *l = *l * (1.0 - mix) + wet[0] * mix;
+            *r = *r * (1.0 - mix) + wet[1] * mix;
+        }
+        self.delay = Some(delay);
+    }
+
+    // 繰り返しが -60dB まで小さくなるまで
+    fn tail(&self, params: &[f32], ctx: &Context) -> f32 {
+        let time = delay_time(params[0]).1 * ctx.samples_per_step / ctx.sample_rate;
+        let feedback = params[1].max(0.001);
+        (time * (0.001_f32.ln() / feedback.ln()).max(1.0)).min(MAX_TAIL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 最初のサンプルだけのインパルスを流し、最初のこだまの位置を返す
+    fn first_echo(delay: &mut Delay, time: f32, samples_per_step: f32, len: usize) -> Option<usize> {
+        let ctx = Context { sample_rate: 44100.0, samples_per_step, keys: &[] };
+        let params = [time, 0.0, 16000.0, 0.0, 1.0];
+        let (mut left, mut right) = (vec![0.0; len], vec![0.0; len]);
+        left[0] = 1.0;
+        right[0] = 1.0;
+        delay.process(&mut left, &mut right, &params, &ctx);
+        left.iter().position(|s| s.abs() > 0.1)
+    }
+
+    #[test]
+    fn echo_follows_the_note_length() {
+        // 120 BPM の 1/8D は 3 ステップ
+        let mut delay = Delay::new(44100.0);
+        assert_eq!(first_echo(&mut delay, 7.0, 5512.5, 20000), Some(16537));
+    }
+
+    #[test]
+    fn slowest_tempo_fits_the_buffer() {
+        let mut delay = Delay::new(44100.0);
+        let len = delay.buffers[0].len();
+        // MIN_BPM の 1 小節
+        let samples_per_step = 60.0 / MIN_BPM / 4.0 * 44100.0;
+        assert_eq!(first_echo(&mut delay, 11.0, samples_per_step, len), Some(len - 2));
+        assert_eq!(delay.buffers[0].len(), len);
+    }
+}
@@ -0,0 +1,243 @@
+// src/audio/distortion.rs
+// 歪み系のエフェクト（ディストーション、ビットクラッシャー、デシメーター）
+//
+// ディストーションは波形を曲げるので高い倍音が出てエイリアスになりやすい。オーバーサンプリングを
+// 選ぶと、線形補間で N 倍に上げてから歪ませ、ローパスで元のナイキストより上を落としてから間引く。
+use crate::sequencer::pattern::FilterMode;
+
+use super::effects::{Context, Effect};
+use super::filter::{Coefs, Svf};
+use super::voice::ParamSpec;
+
+// 歪みのカーブの名前（Curve パラメータの値の順）
+pub const CURVES: [&str; 3] = ["Tanh", "Fold", "Clip"];
+// オーバーサンプリングの倍率（Oversample パラメータの値の順）
+pub const OVERSAMPLING: [usize; 3] = [1, 2, 4];
+
+// 間引く前のローパス（4次のバターワースになるよう2段のレゾナンスを選ぶ）
+const AA_CUTOFF: f32 = 0.45; // 元のサンプルレートに対する比
+const AA_RESONANCE: [f32; 2] = [0.078, 0.63];
+
+pub fn curve(value: f32) -> usize {
+    (value.round().max(0.0) as usize).min(CURVES.len() - 1)
+}
+
+pub fn oversampling(value: f32) -> usize {
+    OVERSAMPLING[(value.round().max(0.0) as usize).min(OVERSAMPLING.len() - 1)]
+}
+
+fn shape(curve: usize, x: f32) -> f32 {
+    match curve {
+        0 => x.tanh(),
+        // 折り返し：±1 を超えた分を逆向きに返す（三角波で包む）
+        1 => {
+            let t = (x + 1.0).rem_euclid(4.0);
+            (if t < 2.0 { t } else { 4.0 - t }) - 1.0
+        }
+        _ => x.clamp(-1.0, 1.0),
+    }
+}
+
+// 並びは Curve, Drive, Oversample, Output, Mix
+pub static DISTORTION: [ParamSpec; 5] = [
+    ParamSpec { name: "Curve", unit: "curve", min: 0.0, max: 2.0, default: 0.0 },
+    ParamSpec { name: "Drive", unit: "dB", min: 0.0, max: 36.0, default: 12.0 },
+    ParamSpec { name: "Oversample", unit: "os", min: 0.0, max: 2.0, default: 0.0 },
+    ParamSpec { name: "Output", unit: "dB", min: -24.0, max: 0.0, default: -6.0 },
+    ParamSpec { name: "Mix", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+];
+
+// 1チャンネル分のオーバーサンプリングの状態
+#[derive(Default)]
+struct Channel {
+    last: f32, // 直前の入力（補間用）
+    filters: [Svf; 2],
+}
+
+impl Channel {
+    fn process(&mut self, input: f32, curve: usize, drive: f32, factor: usize, coefs: &[Coefs; 2]) -> f32 {
+        if factor == 1 {
+            return shape(curve, input * drive);
+        }
+        let mut out = 0.0;
+        for k in 1..=factor {
+            let x = self.last + (input - self.last) * k as f32 / factor as f32;
+            out = shape(curve, x * drive);
+            for (filter, c) in self.filters.iter_mut().zip(coefs) {
+                out = filter.process(out, c, FilterMode::LowPass);
+            }
+        }
+        self.last = input;
+        out
+    }
+}
+
+pub struct Distortion {
+    channels: [Channel; 2],
+}
+
+impl Distortion {
+    pub fn new() -> Self {
+        Self { channels: Default::default() }
+    }
+}
+
+impl Effect for Distortion {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        let curve = curve(params[0]);
+        let drive = 10.0_f32.powf(params[1] / 20.0);
+        let factor = oversampling(params[2]);
+        let output = 10.0_f32.powf(params[3] / 20.0);
+        let mix = params[4];
+        let rate = ctx.sample_rate * factor as f32;
+        let coefs = AA_RESONANCE.map(|r| Coefs::new(ctx.sample_rate * AA_CUTOFF, r, rate));
+
+        for (channel, samples) in self.channels.iter_mut().zip([left, right]) {
+            for s in samples.iter_mut() {
+                let wet = channel.process(*s, curve, drive, factor, &coefs) * output;
+                *s = *s * (1.0 - mix) + wet * mix;
+            }
+        }
+    }
+}
+
+// 並びは Bits, Mix
+pub static BITCRUSHER: [ParamSpec; 2] = [
+    ParamSpec { name: "Bits", unit: "bits", min: 1.0, max: 16.0, default: 8.0 },
+    ParamSpec { name: "Mix", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+];
+
+// 振幅を 2^(Bits-1) 段階に丸める
+pub struct Bitcrusher;
+
+impl Effect for Bitcrusher {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], _ctx: &Context) {
+        let levels = 2.0_f32.powf(params[0].round() - 1.0);
+        let mix = params[1];
+        for s in left.iter_mut().chain(right.iter_mut()) {
+            let wet = (*s * levels).round() / levels;
+            *s = *s * (1.0 - mix) + wet * mix;
+        }
+    }
+}
+
+// 並びは Rate, Mix
+pub static DECIMATOR: [ParamSpec; 2] = [
+    ParamSpec { name: "Rate", unit: "Hz", min: 500.0, max: 44100.0, default: 8000.0 },
+    ParamSpec { name: "Mix", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+];
+
+// Rate の速さで拾った値を次に拾うまで保つ（わざとエイリアスを出す）
+pub struct Decimator {
+    phase: f32,
+    held: [f32; 2],
+}
+
+impl Decimator {
+    pub fn new() -> Self {
+        Self { phase: 1.0, held: [0.0; 2] }
+    }
+}
+
+impl Effect for Decimator {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        let step = (params[0] / ctx.sample_rate).min(1.0);
+        let mix = params[1];
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if self.phase >= 1.0 {
+                self.phase -= 1.0;
+                self.held = [*l, *r];
+            }
+            self.phase += step;
+            *l = *l * (1.0 - mix) + self.held[0] * mix;
+            *r = *r * (1.0 - mix) + self.held[1] * mix;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const RATE: f32 = 44100.0;
+
+    fn ctx() -> Context<'static> {
+        Context { sample_rate: RATE, samples_per_step: 5512.5, keys: &[] }
+    }
+
+    // 左右同じ入力を通した左チャンネル
+    fn process(effect: &mut dyn Effect, input: &[f32], params: &[f32]) -> Vec<f32> {
+        let (mut left, mut right) = (input.to_vec(), input.to_vec());
+        effect.process(&mut left, &mut right, params, &ctx());
+        left
+    }
+
+    fn sine(freq: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|i| (2.0 * PI * freq * i as f32 / RATE).sin()).collect()
+    }
+
+    fn peak(block: &[f32]) -> f32 {
+        block.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn curves_stay_within_the_output_level() {
+        let input = sine(440.0, 4410);
+        for (curve, name) in CURVES.iter().enumerate() {
+            for (factor, times) in OVERSAMPLING.iter().enumerate() {
+                // 36dB 上げても Output の -6dB を大きく超えない（オーバーサンプリングのローパスで少し跳ねる）
+                let params = [curve as f32, 36.0, factor as f32, -6.0, 1.0];
+                let out = process(&mut Distortion::new(), &input, &params);
+                assert!(peak(&out) < 0.7, "{} x{} peak {}", name, times, peak(&out));
+                assert!(peak(&out) > 0.3);
+            }
+        }
+        // 折り返しは ±1 を超えた分を戻す
+        assert_eq!(shape(1, 1.5), 0.5);
+        assert_eq!(shape(1, -3.0), 1.0);
+        assert_eq!(shape(2, 1.5), 1.0);
+    }
+
+    #[test]
+    fn oversampling_removes_aliasing() {
+        // 5kHz を強く歪ませたときの、倍音（5kHz の整数倍）以外の成分の大きさ。
+        // 頭の 10ms を飛ばし、10Hz 刻みがちょうど直交する 0.1 秒分を調べる
+        let alias = |factor: f32| {
+            let out = process(&mut Distortion::new(), &sine(5000.0, 4851), &[0.0, 24.0, factor, 0.0, 1.0]);
+            let out = &out[441..];
+            (1..2205)
+                .map(|bin| bin as f32 * 10.0)
+                .filter(|freq| (freq / 5000.0 - (freq / 5000.0).round()).abs() * 5000.0 > 20.0)
+                .map(|freq| {
+                    let (re, im) = out.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, s)| {
+                        let angle = 2.0 * PI * (freq * (i + 441) as f32 / RATE).fract();
+                        (re + s * angle.cos(), im + s * angle.sin())
+                    });
+                    re * re + im * im
+                })
+                .sum::<f32>()
+        };
+        let (none, twice, four) = (alias(0.0), alias(1.0), alias(2.0));
+        assert!(twice < none / 8.0 && four < twice, "{} {} {}", none, twice, four);
+    }
+
+    #[test]
+    fn bitcrusher_rounds_to_the_levels() {
+        let input = [0.1, 0.3, -0.6, 0.9];
+        // 2bit は 0.5 刻み
+        assert_eq!(process(&mut Bitcrusher, &input, &[2.0, 1.0]), [0.0, 0.5, -0.5, 1.0]);
+        // Mix 0.5 は元の音と半々
+        assert_eq!(process(&mut Bitcrusher, &input, &[2.0, 0.5]), [0.05, 0.4, -0.55, 0.95]);
+    }
+
+    #[test]
+    fn decimator_holds_each_pick() {
+        let input: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        // サンプルレートの 1/4 では 4 サンプルずつ同じ値
+        let out = process(&mut Decimator::new(), &input, &[RATE / 4.0, 1.0]);
+        assert_eq!(out, [0.0, 0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0]);
+        let out = process(&mut Decimator::new(), &input, &[RATE, 1.0]);
+        assert_eq!(out, input);
+    }
+}
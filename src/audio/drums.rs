@@ -0,0 +1,559 @@
+// src/audio/drums.rs
+// ドラムとベースの合成音色（808/909 風）
+use std::f32::consts::PI;
+
+use crate::sequencer::pattern::InstrumentType;
+
+use super::osc::{Oscillator, Wave};
+use super::voice::{Hit, Instrument, ParamSpec, Registry, Voice};
+
+pub fn register(registry: &mut Registry) {
+    registry.register(InstrumentType::Kick, Box::new(Kick));
+    registry.register(InstrumentType::Snare, Box::new(Drum(SNARE)));
+    registry.register(InstrumentType::Rim, Box::new(Drum(RIM)));
+    registry.register(InstrumentType::LowTom, Box::new(Drum(LOW_TOM)));
+    registry.register(InstrumentType::MidTom, Box::new(Drum(MID_TOM)));
+    registry.register(InstrumentType::HighTom, Box::new(Drum(HIGH_TOM)));
+    registry.register(InstrumentType::HiHat, Box::new(HiHat(CLOSED_HAT)));
+    registry.register(InstrumentType::OpenHat, Box::new(HiHat(OPEN_HAT)));
+    registry.register(InstrumentType::Clap, Box::new(Clap));
+    registry.register(InstrumentType::Cowbell, Box::new(Cowbell));
+    registry.register(InstrumentType::Crash, Box::new(Cymbal(CRASH)));
+    registry.register(InstrumentType::Ride, Box::new(Cymbal(RIDE)));
+    registry.register(InstrumentType::Bass, Box::new(Bass));
+}
+
+// ボイスごとのノイズ源（xorshift）。ライブ再生とエクスポートの Player で状態を共有しない
+struct Noise(u32);
+
+impl Noise {
+    // 発音の値から種を決める（同じ発音なら毎回同じノイズになる）
+    fn new(hit: &Hit) -> Self {
+        let seed = [hit.note, hit.gain, hit.pitch, hit.decay]
+            .iter()
+            .fold(123456789_u32, |seed, v| (seed ^ v.to_bits()).wrapping_mul(0x9e37_79b1));
+        Self(seed.max(1))
+    }
+
+    // -0.5〜0.5 のノイズ
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 % 1000) as f32 / 1000.0 - 0.5
+    }
+}
+
+// 位相 (0.0〜1.0) の矩形波
+fn square(phase: f32) -> f32 {
+    if phase < 0.5 { 1.0 } else { -1.0 }
+}
+
+// 発音からの経過時間と、減衰の終わりまでの進み具合
+struct Decay {
+    time: f32,
+    dt: f32,
+    duration: f32,
+}
+
+impl Decay {
+    fn new(sample_rate: f32, duration: f32) -> Self {
+        Self {
+            time: 0.0,
+            dt: 1.0 / sample_rate,
+            duration,
+        }
+    }
+
+    // (経過時間, 進み具合 0.0〜1.0)。終わったら None
+    fn next(&mut self) -> Option<(f32, f32)> {
+        if self.time >= self.duration {
+            return None;
+        }
+        let now = (self.time, self.time / self.duration);
+        self.time += self.dt;
+        Some(now)
+    }
+}
+
+// キックドラム（低周波数のサイン波 + ピッチダウン）
+struct Kick;
+
+struct KickVoice {
+    decay: Decay,
+    freq_start: f32,
+    freq_end: f32,
+    curve: f32,
+    gain: f32,
+}
+
+impl Instrument for Kick {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Pitch", unit: "Hz", min: 20.0, max: 200.0, default: 40.0 },
+            ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 400.0, default: 110.0 },
+            ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 2.0, default: 0.3 },
+            ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 2.0 },
+        ]
+    }
+
+    // Pitch に向かって Pitch + Sweep から下がる
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(KickVoice {
+            decay: Decay::new(sample_rate, params[2] * hit.decay),
+            freq_start: (params[0] + params[1]) * hit.pitch_ratio(),
+            freq_end: params[0] * hit.pitch_ratio(),
+            curve: params[3],
+            gain: hit.gain,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        1
+    }
+}
+
+impl Voice for KickVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((time, t)) = self.decay.next() else { return false };
+            let freq = self.freq_start + (self.freq_end - self.freq_start) * t;
+            let phase = 2.0 * PI * freq * time;
+            let envelope = (1.0 - t).powf(self.curve);
+            *sample += (phase.sin() * envelope * 0.5).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// スネア・タム・リム（ノイズ + ピッチの下がるサイン波）。
+// 音色ごとにパラメータの並びは同じで、範囲と既定値だけが違う
+struct Drum(&'static [ParamSpec; 5]);
+
+const SNARE: &[ParamSpec; 5] = &[
+    ParamSpec { name: "Pitch", unit: "Hz", min: 80.0, max: 500.0, default: 180.0 },
+    ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 400.0, default: 0.0 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.03, max: 1.0, default: 0.15 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.7 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 1.5 },
+];
+
+const RIM: &[ParamSpec; 5] = &[
+    ParamSpec { name: "Pitch", unit: "Hz", min: 200.0, max: 2000.0, default: 820.0 },
+    ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 1000.0, default: 200.0 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.01, max: 0.2, default: 0.04 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.25 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 3.0 },
+];
+
+const LOW_TOM: &[ParamSpec; 5] = &[
+    ParamSpec { name: "Pitch", unit: "Hz", min: 40.0, max: 200.0, default: 90.0 },
+    ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 200.0, default: 40.0 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 1.5, default: 0.45 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.1 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 2.0 },
+];
+
+const MID_TOM: &[ParamSpec; 5] = &[
+    ParamSpec { name: "Pitch", unit: "Hz", min: 60.0, max: 300.0, default: 130.0 },
+    ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 200.0, default: 50.0 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 1.5, default: 0.35 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.1 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 2.0 },
+];
+
+const HIGH_TOM: &[ParamSpec; 5] = &[
+    ParamSpec { name: "Pitch", unit: "Hz", min: 80.0, max: 400.0, default: 190.0 },
+    ParamSpec { name: "Sweep", unit: "Hz", min: 0.0, max: 200.0, default: 60.0 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 1.5, default: 0.3 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.1 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 2.0 },
+];
+
+struct DrumVoice {
+    decay: Decay,
+    freq_start: f32,
+    freq_end: f32,
+    noise: f32, // ノイズの割合（残りがトーン）
+    curve: f32,
+    gain: f32,
+    rng: Noise,
+}
+
+impl Instrument for Drum {
+    fn params(&self) -> &'static [ParamSpec] {
+        self.0
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(DrumVoice {
+            decay: Decay::new(sample_rate, params[2] * hit.decay),
+            freq_start: (params[0] + params[1]) * hit.pitch_ratio(),
+            freq_end: params[0] * hit.pitch_ratio(),
+            noise: params[3],
+            curve: params[4],
+            gain: hit.gain,
+            rng: Noise::new(&hit),
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        2
+    }
+}
+
+impl Voice for DrumVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((time, t)) = self.decay.next() else { return false };
+            let envelope = (1.0 - t).powf(self.curve);
+            let freq = self.freq_start + (self.freq_end - self.freq_start) * t;
+            let tone = (2.0 * PI * freq * time).sin();
+            let mix = self.rng.next() * self.noise + tone * (1.0 - self.noise);
+            *sample += (mix * envelope * 0.4).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// ハイハット（高周波ノイズ）。オープンは減衰が長いだけ
+struct HiHat(&'static [ParamSpec; 2]);
+
+const CLOSED_HAT: &[ParamSpec; 2] = &[
+    ParamSpec { name: "Decay", unit: "s", min: 0.01, max: 0.5, default: 0.05 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 3.0 },
+];
+
+const OPEN_HAT: &[ParamSpec; 2] = &[
+    ParamSpec { name: "Decay", unit: "s", min: 0.1, max: 2.0, default: 0.45 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 1.5 },
+];
+
+struct HiHatVoice {
+    decay: Decay,
+    curve: f32,
+    gain: f32,
+    rng: Noise,
+}
+
+impl Instrument for HiHat {
+    fn params(&self) -> &'static [ParamSpec] {
+        self.0
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(HiHatVoice {
+            decay: Decay::new(sample_rate, params[0] * hit.decay),
+            curve: params[1],
+            gain: hit.gain,
+            rng: Noise::new(&hit),
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        1
+    }
+}
+
+impl Voice for HiHatVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((_, t)) = self.decay.next() else { return false };
+            let envelope = (1.0 - t).powf(self.curve);
+            *sample += (self.rng.next() * envelope * 0.2).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// クラップ（間隔をあけたノイズの連打 + 余韻）
+struct Clap;
+
+const CLAP_BURSTS: usize = 3;
+
+struct ClapVoice {
+    decay: Decay,
+    spread: f32, // 連打の間隔 (秒)
+    tail: f32,   // 余韻の長さ (秒)
+    curve: f32,
+    low: f32,    // ノイズを帯域に絞るフィルターの状態
+    lower: f32,
+    gain: f32,
+    rng: Noise,
+}
+
+impl Instrument for Clap {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Spread", unit: "s", min: 0.003, max: 0.03, default: 0.011 },
+            ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 1.0, default: 0.2 },
+            ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 2.0 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let spread = params[0] / hit.pitch_ratio();
+        let tail = params[1] * hit.decay;
+        Box::new(ClapVoice {
+            decay: Decay::new(sample_rate, spread * CLAP_BURSTS as f32 + tail),
+            spread,
+            tail,
+            curve: params[2],
+            low: 0.0,
+            lower: 0.0,
+            gain: hit.gain,
+            rng: Noise::new(&hit),
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        2
+    }
+}
+
+impl Voice for ClapVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((time, _)) = self.decay.next() else { return false };
+            let bursts = self.spread * CLAP_BURSTS as f32;
+            let envelope = if time < bursts {
+                // 連打：ひとつずつ鋭く減衰
+                1.0 - (time % self.spread) / self.spread
+            } else {
+                (1.0 - (time - bursts) / self.tail).max(0.0).powf(self.curve) * 0.7
+            };
+            // ローパスの差分でおおまかなバンドパス（1kHz 付近）
+            self.low += (self.rng.next() - self.low) * 0.3;
+            self.lower += (self.low - self.lower) * 0.05;
+            let band = (self.low - self.lower) * 2.0;
+            *sample += (band * envelope * 0.5).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// カウベル（少しずらした2つの矩形波）
+struct Cowbell;
+
+struct CowbellVoice {
+    decay: Decay,
+    freqs: [f32; 2],
+    phases: [f32; 2],
+    dt: f32,
+    curve: f32,
+    gain: f32,
+}
+
+impl Instrument for Cowbell {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Pitch", unit: "Hz", min: 200.0, max: 1200.0, default: 540.0 },
+            ParamSpec { name: "Pitch 2", unit: "Hz", min: 200.0, max: 1600.0, default: 800.0 },
+            ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 1.5, default: 0.35 },
+            ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 3.0 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(CowbellVoice {
+            decay: Decay::new(sample_rate, params[2] * hit.decay),
+            freqs: [params[0] * hit.pitch_ratio(), params[1] * hit.pitch_ratio()],
+            phases: [0.0; 2],
+            dt: 1.0 / sample_rate,
+            curve: params[3],
+            gain: hit.gain,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        2
+    }
+}
+
+impl Voice for CowbellVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((_, t)) = self.decay.next() else { return false };
+            let mut tone = 0.0;
+            for (phase, freq) in self.phases.iter_mut().zip(self.freqs) {
+                tone += square(*phase);
+                *phase = (*phase + freq * self.dt).fract();
+            }
+            let envelope = (1.0 - t).powf(self.curve);
+            *sample += (tone * envelope * 0.1).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// シンバル（6つの矩形波の金属的な響き + ノイズ）
+struct Cymbal(&'static [ParamSpec; 4]);
+
+// 808 のシンバル/ハットで使われる周波数比
+const METAL_RATIOS: [f32; 6] = [1.0, 1.342, 1.2312, 1.6532, 1.9523, 2.1523];
+
+const CRASH: &[ParamSpec; 4] = &[
+    ParamSpec { name: "Tone", unit: "Hz", min: 200.0, max: 800.0, default: 400.0 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.7 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.3, max: 4.0, default: 1.6 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 1.5 },
+];
+
+const RIDE: &[ParamSpec; 4] = &[
+    ParamSpec { name: "Tone", unit: "Hz", min: 200.0, max: 800.0, default: 520.0 },
+    ParamSpec { name: "Noise", unit: "", min: 0.0, max: 1.0, default: 0.3 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.3, max: 4.0, default: 1.2 },
+    ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 1.2 },
+];
+
+struct CymbalVoice {
+    decay: Decay,
+    freq: f32,
+    phases: [f32; 6],
+    dt: f32,
+    noise: f32,
+    curve: f32,
+    low: f32, // ハイパス用のローパスの状態
+    gain: f32,
+    rng: Noise,
+}
+
+impl Instrument for Cymbal {
+    fn params(&self) -> &'static [ParamSpec] {
+        self.0
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(CymbalVoice {
+            decay: Decay::new(sample_rate, params[2] * hit.decay),
+            freq: params[0] * hit.pitch_ratio(),
+            phases: [0.0; 6],
+            dt: 1.0 / sample_rate,
+            noise: params[1],
+            curve: params[3],
+            low: 0.0,
+            gain: hit.gain,
+            rng: Noise::new(&hit),
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        3
+    }
+}
+
+impl Voice for CymbalVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((_, t)) = self.decay.next() else { return false };
+            let mut metal = 0.0;
+            for (phase, ratio) in self.phases.iter_mut().zip(METAL_RATIOS) {
+                metal += square(*phase);
+                *phase = (*phase + self.freq * ratio * self.dt).fract();
+            }
+            let mix = self.rng.next() * 2.0 * self.noise + metal / 6.0 * (1.0 - self.noise);
+            // 低域を削ってシャリっとさせる
+            self.low += (mix - self.low) * 0.2;
+            let high = mix - self.low;
+            let envelope = (1.0 - t).powf(self.curve);
+            *sample += (high * envelope * 0.3).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+// ベース（既定は低音サイン波。波形を選べる）
+struct Bass;
+
+struct BassVoice {
+    decay: Decay,
+    osc: Oscillator,
+    wave: Wave,
+    freq: f32,
+    curve: f32,
+    gain: f32,
+    sample_rate: f32,
+}
+
+impl Instrument for Bass {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Decay", unit: "s", min: 0.05, max: 2.0, default: 0.2 },
+            ParamSpec { name: "Curve", unit: "", min: 0.2, max: 6.0, default: 0.5 },
+            ParamSpec { name: "Wave", unit: "wave", min: 0.0, max: 4.0, default: 3.0 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        Box::new(BassVoice {
+            decay: Decay::new(sample_rate, params[0] * hit.decay),
+            osc: Oscillator::new(0.0),
+            wave: Wave::from_param(params[2]),
+            freq: hit.freq(),
+            curve: params[1],
+            gain: hit.gain,
+            sample_rate,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        1
+    }
+}
+
+impl Voice for BassVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let Some((_, t)) = self.decay.next() else { return false };
+            let value = self.osc.next(self.wave, self.freq, self.sample_rate, 0.5);
+            let envelope = (1.0 - t).powf(self.curve);
+            *sample += (value * envelope * 0.3).clamp(-1.0, 1.0) * self.gain;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit() -> Hit {
+        Hit { note: 60.0, gain: 1.0, pitch: 0.0, decay: 1.0, gate: 0.1, glide_from: None, accent: false }
+    }
+
+    // 鳴り終わるまで鳴らして (ピーク, 長さ秒) を返す
+    fn ring_out(kind: InstrumentType) -> (f32, f32) {
+        let registry = Registry::new();
+        let instrument = registry.get(kind).unwrap();
+        let mut voice = instrument.note_on(44100.0, hit(), &instrument.values(&[]));
+        let (mut peak, mut blocks) = (0.0_f32, 0);
+        let mut block = [0.0; 441];
+        loop {
+            block.fill(0.0);
+            let alive = voice.render(&mut block);
+            peak = block.iter().fold(peak, |peak, s| peak.max(s.abs()));
+            blocks += 1;
+            if !alive || blocks > 1000 {
+                break;
+            }
+        }
+        (peak, blocks as f32 / 100.0)
+    }
+
+    #[test]
+    fn every_drum_voice_sounds_and_ends() {
+        use InstrumentType::*;
+        for kind in [Kick, Snare, Rim, LowTom, MidTom, HighTom, HiHat, OpenHat, Clap, Cowbell, Crash, Ride] {
+            let (peak, length) = ring_out(kind);
+            assert!(peak > 0.05 && peak < 2.0, "{:?} peak {}", kind, peak);
+            assert!(length < 10.0, "{:?} never ends", kind);
+        }
+    }
+
+    #[test]
+    fn open_hat_and_crash_ring_longer() {
+        use InstrumentType::*;
+        assert!(ring_out(OpenHat).1 > ring_out(HiHat).1);
+        assert!(ring_out(Crash).1 > ring_out(Rim).1);
+    }
+}
@@ -1 +1,260 @@
+// src/audio/effects.rs
 // エフェクトチェーン
+//
+// エフェクトはステレオのブロックをその場で書き換える。トラックごとのインサート（フィルターの後）と、
+// トラックから送った音を受けるセンドバス、全トラックを混ぜた後のマスターにチェーンがあり、上から順にかける。
+// 状態（ディレイのバッファなど）はオーディオスレッドの Chain がスロットごとに持ち、
+// 並べ替えても引き継ぐ。新しく挿したスロットと種類を替えたスロットの分だけ UIスレッドで作って渡し、
+// 外したスロットの分は UIスレッドへ返して捨てる（オーディオスレッドでは確保も解放もしない）。
+use crate::sequencer::pattern::{EffectSlot, EffectType};
+
+use super::compressor::{self, Compressor};
+use super::delay::{self, Delay};
+use super::distortion::{self, Bitcrusher, Decimator, Distortion};
+use super::reverb::{self, Reverb};
+use super::voice::ParamSpec;
+
+// エクスポートで書き足す余韻の上限（秒）
+pub const MAX_EXPORT_TAIL: f32 = 30.0;
+
+// process に渡す再生中の情報
+pub struct Context<'a> {
+    pub sample_rate: f32,
+    pub samples_per_step: f32, // テンポ同期用（16分音符1つ分）
+    pub keys: &'a [Key],       // トラックごとのサイドチェインのキー
+}
+
+// サイドチェインのキー（トラックのフィルターの後、インサートとフェーダーの前の音）
+#[derive(Default)]
+pub struct Key {
+    pub level: Vec<f32>,             // サンプルごとの左右の大きい方の絶対値
+    pub triggers: Vec<(usize, f32)>, // このブロックで鳴らした位置と強さ（ゴーストサイドチェイン用）
+}
+
+pub trait Effect: Send {
+    // left と right をその場で処理する。params は effects::params() と同じ並びの値
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context);
+
+    // 入力が止まってから音が残る長さ（秒）。エクスポートの最後にこの分だけ書き足す
+    fn tail(&self, _params: &[f32], _ctx: &Context) -> f32 {
+        0.0
+    }
+
+    // UI に出すゲインリダクション（dB）。メーターのないエフェクトは None
+    fn meter(&self) -> Option<f32> {
+        None
+    }
+}
+
+// エフェクトの合成パラメータの定義（UI はインスタンスを作らずにこれを見る）
+pub fn params(kind: EffectType) -> &'static [ParamSpec] {
+    match kind {
+        EffectType::Utility => &UTILITY,
+        EffectType::Delay => &delay::PARAMS,
+        EffectType::Reverb => &reverb::PARAMS,
+        EffectType::Compressor => &compressor::PARAMS,
+        EffectType::Distortion => &distortion::DISTORTION,
+        EffectType::Bitcrusher => &distortion::BITCRUSHER,
+        EffectType::Decimator => &distortion::DECIMATOR,
+    }
+}
+
+// バッファもここで確保する（UIスレッドで呼ぶ）
+pub fn create(kind: EffectType, sample_rate: f32) -> Box<dyn Effect> {
+    match kind {
+        EffectType::Utility => Box::new(Utility { gain: None, width: 1.0 }),
+        EffectType::Delay => Box::new(Delay::new(sample_rate)),
+        EffectType::Reverb => Box::new(Reverb::new(sample_rate)),
+        EffectType::Compressor => Box::new(Compressor::new()),
+        EffectType::Distortion => Box::new(Distortion::new()),
+        EffectType::Bitcrusher => Box::new(Bitcrusher),
+        EffectType::Decimator => Box::new(Decimator::new()),
+    }
+}
+
+// センドバスに最初から挿しておくリバーブ（センドなのでウェットだけ）
+pub fn default_reverb() -> EffectSlot {
+    let mut values = values(&reverb::PARAMS, &[]);
+    values[reverb::MIX] = 1.0;
+    EffectSlot { values, ..EffectSlot::new(EffectType::Reverb) }
+}
+
+// スロットに保存された値。保存されていなければ既定値
+pub fn values(specs: &[ParamSpec], saved: &[f32]) -> Vec<f32> {
+    if saved.len() == specs.len() {
+        saved.to_vec()
+    } else {
+        specs.iter().map(|p| p.default).collect()
+    }
+}
+
+// スロットに対応するエフェクトのインスタンス
+struct Instance {
+    id: u32, // EffectSlot::id
+    effect: Box<dyn Effect>,
+}
+
+// オーディオスレッドのチェーンに入っているスロット（UIスレッドで控えておき、差し替えを作る）
+#[derive(Clone, Default)]
+pub struct Layout(Vec<(u32, EffectType)>);
+
+impl Layout {
+    // slots に合わせる差し替えを作る。新しいスロットのインスタンスはここで作る
+    pub fn update(&mut self, slots: &[EffectSlot], sample_rate: f32) -> ChainUpdate {
+        let fresh = slots
+            .iter()
+            .map(|slot| {
+                let kept = self.0.contains(&(slot.id, slot.kind));
+                let create = || Instance { id: slot.id, effect: create(slot.kind, sample_rate) };
+                (slot.id, (!kept).then(create))
+            })
+            .collect();
+        let current = slots
+            .iter()
+            .map(|slot| Slot { values: values(params(slot.kind), &slot.values), bypass: slot.bypass })
+            .collect();
+        self.0 = slots.iter().map(|slot| (slot.id, slot.kind)).collect();
+        ChainUpdate { fresh, instances: Vec::with_capacity(slots.len()), slots: current }
+    }
+}
+
+// チェーンの差し替え（UIスレッドで確保しておき、オーディオスレッドでは入れ替えるだけ）
+pub struct ChainUpdate {
+    fresh: Vec<(u32, Option<Instance>)>, // スロットの並び。新しいスロットには作ったインスタンスがある
+    instances: Vec<Instance>,            // 入れ替え先（容量だけ確保）。適用後は外したインスタンスが入る
+    slots: Vec<Slot>,                    // 既定値で埋めた値。適用後は前の値が入る
+}
+
+// オーディオスレッドで使うスロットの値
+struct Slot {
+    values: Vec<f32>, // params() と同じ並び
+    bypass: bool,
+}
+
+// オーディオスレッド側のチェーン（スロットごとのエフェクトと値）
+#[derive(Default)]
+pub struct Chain {
+    instances: Vec<Instance>, // slots と同じ並び
+    slots: Vec<Slot>,
+}
+
+impl Chain {
+    // UI で編集したスロットに合わせる。同じスロットのインスタンスは状態ごと使い続け、
+    // 外したスロットの分は update に残して UIスレッドへ返す
+    pub fn apply(&mut self, update: &mut ChainUpdate) {
+        for (id, fresh) in &mut update.fresh {
+            let instance = match fresh.take() {
+                Some(instance) => instance,
+                None => {
+                    // Layout が控えているので必ずある
+                    let pos = self.instances.iter().position(|i| i.id == *id).expect("effect instance");
+                    self.instances.swap_remove(pos)
+                }
+            };
+            update.instances.push(instance);
+        }
+        std::mem::swap(&mut self.instances, &mut update.instances);
+        std::mem::swap(&mut self.slots, &mut update.slots);
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32], ctx: &Context) {
+        for (instance, slot) in self.instances.iter_mut().zip(&self.slots) {
+            if !slot.bypass {
+                instance.effect.process(left, right, &slot.values, ctx);
+            }
+        }
+    }
+
+    // スロットごとのメーター（UI へ送る out を使い回す）
+    pub fn meters(&self, out: &mut Vec<Option<f32>>) {
+        out.clear();
+        out.extend(self.instances.iter().map(|instance| instance.effect.meter()));
+    }
+
+    // チェーン全体の余韻（直列なので足し合わせる）
+    pub fn tail(&self, ctx: &Context) -> f32 {
+        self.instances
+            .iter()
+            .zip(&self.slots)
+            .filter(|(_, slot)| !slot.bypass)
+            .map(|(instance, slot)| instance.effect.tail(&slot.values, ctx))
+            .sum()
+    }
+}
+
+// 音量とステレオ幅（1.0 でそのまま、0.0 でモノラル、2.0 で左右の差を倍に）
+static UTILITY: [ParamSpec; 2] = [
+    ParamSpec { name: "Gain", unit: "dB", min: -24.0, max: 24.0, default: 0.0 },
+    ParamSpec { name: "Width", unit: "", min: 0.0, max: 2.0, default: 1.0 },
+];
+
+// 値を急に変えたときのプツッという音を防ぐため、10ms ほどかけて目標の値に寄せる
+const SMOOTH_TIME: f32 = 0.01;
+
+struct Utility {
+    gain: Option<f32>, // 最初のブロックは目標の値から始める
+    width: f32,
+}
+
+impl Effect for Utility {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        let target_gain = 10.0_f32.powf(params[0] / 20.0);
+        let target_width = params[1];
+        let coef = 1.0 - (-1.0 / (SMOOTH_TIME * ctx.sample_rate)).exp();
+        let mut gain = self.gain.unwrap_or(target_gain);
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            gain += (target_gain - gain) * coef;
+            self.width += (target_width - self.width) * coef;
+            let mid = (*l + *r) * 0.5;
+            let side = (*l - *r) * 0.5 * self.width;
+            *l = (mid + side) * gain;
+            *r = (mid - side) * gain;
+        }
+        self.gain = Some(gain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // インスタンスの場所（同じインスタンスを使い続けているか調べる）
+    fn addresses(chain: &Chain) -> Vec<*const ()> {
+        chain.instances.iter().map(|i| &*i.effect as *const dyn Effect as *const ()).collect()
+    }
+
+    // UIスレッドで差し替えを作ってチェーンに渡し、外したインスタンスの数を返す
+    fn sync(chain: &mut Chain, layout: &mut Layout, slots: &[EffectSlot]) -> usize {
+        let mut update = layout.update(slots, 44100.0);
+        chain.apply(&mut update);
+        update.instances.len()
+    }
+
+    #[test]
+    fn sync_keeps_instances_of_unchanged_slots() {
+        let delay = EffectSlot::new(EffectType::Delay);
+        let reverb = EffectSlot::new(EffectType::Reverb);
+        let (mut chain, mut layout) = (Chain::default(), Layout::default());
+        sync(&mut chain, &mut layout, &[delay.clone(), reverb.clone()]);
+        let before = addresses(&chain);
+
+        // 値を変えても並べ替えても作り直さない
+        let mut changed = delay.clone();
+        changed.values = vec![3.0, 0.5, 4000.0, 1.0, 0.2];
+        assert_eq!(sync(&mut chain, &mut layout, &[reverb.clone(), changed.clone()]), 0);
+        assert_eq!(addresses(&chain), [before[1], before[0]]);
+        assert_eq!(chain.slots[1].values, changed.values);
+
+        // 新しく挿した分だけ作る（種類を替えたスロットも新しいスロットになる）
+        let crusher = EffectSlot::new(EffectType::Bitcrusher);
+        sync(&mut chain, &mut layout, &[reverb.clone(), crusher, changed.clone()]);
+        let after = addresses(&chain);
+        assert_eq!((after[0], after[2]), (before[1], before[0]));
+
+        // 外したインスタンスは差し替えに入れて返す
+        let retyped = EffectSlot::new(EffectType::Compressor);
+        assert_eq!(sync(&mut chain, &mut layout, &[retyped.clone(), changed]), 2);
+        assert_eq!(addresses(&chain)[1], before[0]);
+        assert_eq!(chain.instances[0].id, retyped.id);
+    }
+}
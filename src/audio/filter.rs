@@ -0,0 +1,190 @@
+// src/audio/filter.rs
+// トラックごとのレゾナンスフィルター（ステートバリアブルフィルター）
+//
+// カットオフとレゾナンスは 5ms かけて目標値に近づけ、ジッパーノイズを防ぐ。目標値はトラックの値に
+// ブロックごとに合わせるので、鳴っている間にノブを回しても効く（ロックした音はロックの値のまま）。
+// 発音のたびにエンベロープを 1 に戻し、Env（オクターブ）の分だけカットオフを動かす。
+// LFO などのモジュレーションはサンプルごとのずれとして受け取る。
+use std::f32::consts::PI;
+
+use crate::sequencer::pattern::FilterMode;
+
+const SMOOTH_TIME: f32 = 0.005;
+const MIN_CUTOFF: f32 = 20.0;
+const MAX_CUTOFF: f32 = 20000.0;
+
+// 1 チャンネル分の状態（Simper の SVF）
+#[derive(Default)]
+pub struct Svf {
+    ic1: f32,
+    ic2: f32,
+}
+
+// サンプルごとに計算する係数（左右で共用）
+pub struct Coefs {
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl Coefs {
+    pub fn new(cutoff: f32, resonance: f32, sample_rate: f32) -> Self {
+        let g = (PI * cutoff.min(sample_rate * 0.45) / sample_rate).tan();
+        // レゾナンス 0 で Q = 0.5、1 でほぼ自己発振
+        let k = 2.0 - 1.96 * resonance;
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        Self { k, a1, a2, a3: g * a2 }
+    }
+}
+
+impl Svf {
+    pub fn process(&mut self, input: f32, c: &Coefs, mode: FilterMode) -> f32 {
+        let v3 = input - self.ic2;
+        let v1 = c.a1 * self.ic1 + c.a2 * v3;
+        let v2 = self.ic2 + c.a2 * self.ic1 + c.a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        let high = input - c.k * v1 - v2;
+        match mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => high,
+            FilterMode::BandPass => v1,
+            FilterMode::Notch => v2 + high,
+        }
+    }
+}
+
+pub struct TrackFilter {
+    svf: [Svf; 2],
+    cutoff: f32, // 平滑化した現在値（log2 Hz）
+    resonance: f32,
+    target_cutoff: f32,
+    target_resonance: f32,
+    track: (f32, f32),               // トラックのカットオフとレゾナンス
+    lock: (Option<f32>, Option<f32>), // 鳴っている音のロック（次に発音するまで）
+    env_amount: f32, // オクターブ
+    env: f32,        // エンベロープの現在値 1.0〜0.0
+}
+
+impl TrackFilter {
+    pub fn new() -> Self {
+        Self {
+            svf: [Svf::default(), Svf::default()],
+            cutoff: MAX_CUTOFF.log2(),
+            resonance: 0.0,
+            target_cutoff: MAX_CUTOFF.log2(),
+            target_resonance: 0.0,
+            track: (MAX_CUTOFF, 0.0),
+            lock: (None, None),
+            env_amount: 0.0,
+            env: 0.0,
+        }
+    }
+
+    // トラックの今の値に合わせる（ブロックごとに呼ぶ）
+    pub fn set_target(&mut self, cutoff: f32, resonance: f32) {
+        self.track = (cutoff, resonance);
+        self.retarget();
+    }
+
+    // 発音した音のロックに切り替えてエンベロープを始める
+    pub fn hit(&mut self, cutoff: Option<f32>, resonance: Option<f32>, env_amount: f32) {
+        self.lock = (cutoff, resonance);
+        self.retarget();
+        self.env_amount = env_amount;
+        self.env = 1.0;
+    }
+
+    fn retarget(&mut self) {
+        let cutoff = self.lock.0.unwrap_or(self.track.0);
+        self.target_cutoff = cutoff.clamp(MIN_CUTOFF, MAX_CUTOFF).log2();
+        self.target_resonance = self.lock.1.unwrap_or(self.track.1).clamp(0.0, 1.0);
+    }
+
+    // 全開のローパスで動きもなければ素通しにする（既定の音を変えない）
+    fn bypassed(&self, mode: FilterMode) -> bool {
+        let open = MAX_CUTOFF.log2() - 0.001;
+        mode == FilterMode::LowPass
+            && self.target_cutoff >= open
+            && self.cutoff >= open
+            && (self.env_amount == 0.0 || self.env < 1e-4)
+    }
+
+    // decay はエンベロープがほぼ 0 になるまでの秒数。
+    // modulation はサンプルごとの (カットオフのずれ（オクターブ）, レゾナンスのずれ)。空ならずれなし
+    pub fn process(
+        &mut self,
+        mode: FilterMode,
+        decay: f32,
+        sample_rate: f32,
+        left: &mut [f32],
+        right: &mut [f32],
+        modulation: &[(f32, f32)],
+    ) {
+        if modulation.is_empty() && self.bypassed(mode) {
+            self.svf = [Svf::default(), Svf::default()];
+            self.cutoff = self.target_cutoff;
+            self.resonance = self.target_resonance;
+            return;
+        }
+        let smooth = 1.0 - (-1.0 / (SMOOTH_TIME * sample_rate)).exp();
+        let env_coef = (-5.0 / (decay.max(0.001) * sample_rate)).exp();
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            self.cutoff += (self.target_cutoff - self.cutoff) * smooth;
+            self.resonance += (self.target_resonance - self.resonance) * smooth;
+            let (octaves, resonance) = modulation.get(i).copied().unwrap_or_default();
+            let cutoff = 2.0_f32
+                .powf(self.cutoff + self.env_amount * self.env + octaves)
+                .clamp(MIN_CUTOFF, MAX_CUTOFF);
+            self.env *= env_coef;
+
+            let resonance = (self.resonance + resonance).clamp(0.0, 1.0);
+            let coefs = Coefs::new(cutoff, resonance, sample_rate);
+            *l = self.svf[0].process(*l, &coefs, mode);
+            *r = self.svf[1].process(*r, &coefs, mode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1ブロック分（無音）を通す
+    fn run(filter: &mut TrackFilter, frames: usize) {
+        let (mut left, mut right) = (vec![0.0; frames], vec![0.0; frames]);
+        filter.process(FilterMode::LowPass, 0.2, 44100.0, &mut left, &mut right, &[]);
+    }
+
+    #[test]
+    fn cutoff_change_glides_while_the_note_plays() {
+        let mut filter = TrackFilter::new();
+        filter.set_target(1000.0, 0.2);
+        filter.hit(None, None, 0.0);
+        run(&mut filter, 4410);
+        assert!((filter.cutoff - 1000.0_f32.log2()).abs() < 0.01);
+
+        // 鳴っている途中でノブを回すと、次のブロックから新しい値へ滑らかに寄せる
+        filter.set_target(250.0, 0.2);
+        run(&mut filter, 32);
+        assert!(filter.cutoff < 1000.0_f32.log2() && filter.cutoff > 250.0_f32.log2() + 0.5);
+        run(&mut filter, 4410);
+        assert!((filter.cutoff - 250.0_f32.log2()).abs() < 0.01);
+    }
+
+    #[test]
+    fn locked_note_keeps_its_cutoff() {
+        let mut filter = TrackFilter::new();
+        filter.set_target(1000.0, 0.2);
+        filter.hit(Some(500.0), None, 0.0);
+        filter.set_target(4000.0, 0.5);
+        assert_eq!(filter.target_cutoff, 500.0_f32.log2());
+        assert_eq!(filter.target_resonance, 0.5);
+
+        // 次の音がロックなしならトラックの値に戻る
+        filter.hit(None, None, 0.0);
+        assert_eq!(filter.target_cutoff, 4000.0_f32.log2());
+    }
+}
@@ -0,0 +1,214 @@
+// src/audio/fm.rs
+// 4オペレーターの FM 音源（2オペレーターのアルゴリズムも選べる）
+//
+// 各オペレーターはサイン波で、比率・レベル・ディケイを持つ。アルゴリズムでどのオペレーターが
+// どれを変調するか（とどれが音として出るか）を決める。金属的な打楽器からベースまで作れる。
+use std::f32::consts::PI;
+
+use crate::sequencer::pattern::InstrumentType;
+
+use super::voice::{Hit, Instrument, ParamSpec, Preset, Registry, Voice};
+
+pub fn register(registry: &mut Registry) {
+    registry.register(InstrumentType::Fm, Box::new(Fm));
+}
+
+// 変調する側のレベル 1.0 での変調指数
+const MAX_INDEX: f32 = 6.0;
+
+pub struct Algorithm {
+    pub name: &'static str,
+    edges: &'static [(usize, usize)], // (変調する側, される側)。必ず番号の大きい方から小さい方へ
+    carriers: &'static [usize],       // 音として出すオペレーター
+    feedback: usize,                  // 自分自身を変調するオペレーター
+    operators: usize,                 // 使うオペレーターの数
+}
+
+const ALGORITHMS: [Algorithm; 7] = [
+    Algorithm { name: "2op", edges: &[(1, 0)], carriers: &[0], feedback: 1, operators: 2 },
+    Algorithm { name: "2x2op", edges: &[(1, 0), (3, 2)], carriers: &[0, 2], feedback: 1, operators: 4 },
+    Algorithm { name: "Stack", edges: &[(3, 2), (2, 1), (1, 0)], carriers: &[0], feedback: 3, operators: 4 },
+    Algorithm { name: "Y", edges: &[(3, 1), (2, 1), (1, 0)], carriers: &[0], feedback: 3, operators: 4 },
+    Algorithm { name: "Branch", edges: &[(3, 2), (2, 0), (1, 0)], carriers: &[0], feedback: 3, operators: 4 },
+    Algorithm { name: "3 Carrier", edges: &[(3, 0), (3, 1), (3, 2)], carriers: &[0, 1, 2], feedback: 3, operators: 4 },
+    Algorithm { name: "Additive", edges: &[], carriers: &[0, 1, 2, 3], feedback: 3, operators: 4 },
+];
+
+// Algorithm パラメータの値からアルゴリズムを選ぶ
+pub fn algorithm(value: f32) -> &'static Algorithm {
+    &ALGORITHMS[(value.round().max(0.0) as usize).min(ALGORITHMS.len() - 1)]
+}
+
+// 並びは Algorithm, Feedback, その後オペレーター 1〜4 の Ratio, Level, Decay
+static PARAMS: [ParamSpec; 14] = [
+    ParamSpec { name: "Algorithm", unit: "alg", min: 0.0, max: 6.0, default: 0.0 },
+    ParamSpec { name: "Feedback", unit: "", min: 0.0, max: 1.0, default: 0.0 },
+    ParamSpec { name: "1 Ratio", unit: "ratio", min: 0.5, max: 16.0, default: 1.0 },
+    ParamSpec { name: "1 Level", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+    ParamSpec { name: "1 Decay", unit: "s", min: 0.01, max: 4.0, default: 0.8 },
+    ParamSpec { name: "2 Ratio", unit: "ratio", min: 0.5, max: 16.0, default: 2.0 },
+    ParamSpec { name: "2 Level", unit: "", min: 0.0, max: 1.0, default: 0.4 },
+    ParamSpec { name: "2 Decay", unit: "s", min: 0.01, max: 4.0, default: 0.4 },
+    ParamSpec { name: "3 Ratio", unit: "ratio", min: 0.5, max: 16.0, default: 3.0 },
+    ParamSpec { name: "3 Level", unit: "", min: 0.0, max: 1.0, default: 0.0 },
+    ParamSpec { name: "3 Decay", unit: "s", min: 0.01, max: 4.0, default: 0.5 },
+    ParamSpec { name: "4 Ratio", unit: "ratio", min: 0.5, max: 16.0, default: 4.0 },
+    ParamSpec { name: "4 Level", unit: "", min: 0.0, max: 1.0, default: 0.0 },
+    ParamSpec { name: "4 Decay", unit: "s", min: 0.01, max: 4.0, default: 0.5 },
+];
+
+static PRESETS: [Preset; 5] = [
+    ("Bell", &[1.0, 0.0, 1.0, 1.0, 2.5, 3.5, 0.5, 1.5, 2.0, 0.5, 1.8, 5.0, 0.4, 0.8]),
+    ("FM Hat", &[2.0, 0.9, 12.0, 1.0, 0.06, 15.5, 1.0, 0.05, 7.25, 0.8, 0.05, 3.0, 0.8, 0.04]),
+    ("FM Snare", &[4.0, 1.0, 0.75, 1.0, 0.18, 1.0, 0.5, 0.06, 8.0, 0.7, 0.1, 11.0, 1.0, 0.12]),
+    ("FM Bass", &[0.0, 0.2, 1.0, 1.0, 1.2, 1.0, 0.45, 0.35, 3.0, 0.0, 0.5, 4.0, 0.0, 0.5]),
+    ("E.Piano", &[1.0, 0.0, 1.0, 1.0, 1.5, 14.0, 0.15, 0.3, 1.0, 0.6, 1.2, 1.0, 0.3, 1.0]),
+];
+
+struct Fm;
+
+impl Instrument for Fm {
+    fn params(&self) -> &'static [ParamSpec] {
+        &PARAMS
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let algorithm = algorithm(params[0]);
+        let freq = hit.freq();
+        let operators = std::array::from_fn(|i| {
+            let (ratio, level, decay) = (params[2 + i * 3], params[3 + i * 3], params[4 + i * 3]);
+            Operator {
+                phase: 0.0,
+                step: freq * ratio / sample_rate,
+                level,
+                env: 1.0,
+                coef: (-5.0 / (decay * hit.decay * sample_rate)).exp(),
+                out: 0.0,
+            }
+        });
+        Box::new(FmVoice {
+            algorithm,
+            operators,
+            feedback: params[1],
+            last: [0.0; 2],
+            gain: hit.gain / algorithm.carriers.len() as f32,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        4
+    }
+
+    fn presets(&self) -> &'static [Preset] {
+        &PRESETS
+    }
+}
+
+struct Operator {
+    phase: f32, // 0.0〜1.0
+    step: f32,
+    level: f32,
+    env: f32,
+    coef: f32, // 1サンプルごとに env にかける値
+    out: f32,
+}
+
+struct FmVoice {
+    algorithm: &'static Algorithm,
+    operators: [Operator; 4],
+    feedback: f32,
+    last: [f32; 2], // フィードバックするオペレーターの直前の2サンプル
+    gain: f32,
+}
+
+impl Voice for FmVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        let algorithm = self.algorithm;
+        for sample in out {
+            // 番号の大きいオペレーターから順に計算する（変調は大きい方から小さい方へ）
+            for i in (0..algorithm.operators).rev() {
+                let mut modulation: f32 = algorithm
+                    .edges
+                    .iter()
+                    .filter(|(_, to)| *to == i)
+                    .map(|(from, _)| self.operators[*from].out * MAX_INDEX)
+                    .sum();
+                if i == algorithm.feedback {
+                    modulation += (self.last[0] + self.last[1]) * 0.5 * self.feedback * PI;
+                }
+                let op = &mut self.operators[i];
+                op.out = (2.0 * PI * op.phase + modulation).sin() * op.env * op.level;
+                op.phase = (op.phase + op.step).fract();
+                op.env *= op.coef;
+            }
+            let fed_back = self.operators[algorithm.feedback].out;
+            self.last = [self.last[1], fed_back];
+
+            let value: f32 = algorithm.carriers.iter().map(|&c| self.operators[c].out).sum();
+            *sample += value * self.gain * 0.5;
+        }
+        // 音として出るオペレーターがすべて減衰しきったら終わり
+        algorithm.carriers.iter().any(|&c| self.operators[c].env > 1e-4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 44100.0;
+
+    fn hit() -> Hit {
+        Hit { note: 69.0, gain: 1.0, pitch: 0.0, decay: 1.0, gate: 0.1, glide_from: None, accent: false }
+    }
+
+    // 頭の n サンプルと、鳴り終わったかどうか
+    fn render(params: &[f32], n: usize) -> (Vec<f32>, bool) {
+        let mut voice = Fm.note_on(RATE, hit(), params);
+        let mut out = vec![0.0; n];
+        let alive = voice.render(&mut out);
+        (out, alive)
+    }
+
+    fn defaults() -> Vec<f32> {
+        Fm.values(&[])
+    }
+
+    #[test]
+    fn unmodulated_carrier_is_a_decaying_sine() {
+        let mut params = defaults();
+        params[6] = 0.0; // 2 Level
+        let (out, _) = render(&params, 512);
+        let coef = (-5.0 / (params[4] * RATE)).exp();
+        for (i, s) in out.iter().enumerate() {
+            let expected = (2.0 * PI * (440.0 * i as f32 / RATE).fract()).sin() * coef.powi(i as i32) * 0.5;
+            assert!((s - expected).abs() < 1e-3, "sample {}: {} vs {}", i, s, expected);
+        }
+    }
+
+    #[test]
+    fn algorithm_and_ratio_shape_the_sound() {
+        let base = render(&defaults(), 1024).0;
+        let differs = |edit: &dyn Fn(&mut Vec<f32>)| {
+            let mut params = defaults();
+            edit(&mut params);
+            let out = render(&params, 1024).0;
+            out.iter().zip(&base).map(|(a, b)| (a - b).abs()).sum::<f32>() > 1.0
+        };
+        assert!(differs(&|p| p[5] = 3.0)); // 2 Ratio
+        assert!(differs(&|p| p[1] = 1.0)); // Feedback
+        // Additive では 2 は変調せず、音として足される
+        assert!(differs(&|p| p[0] = 6.0));
+    }
+
+    #[test]
+    fn presets_stay_in_range_and_end() {
+        for (name, values) in PRESETS.iter() {
+            // 一番長い Bell でも 5 秒もかからず消える
+            let (out, alive) = render(values, RATE as usize * 6);
+            let peak = out.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            assert!(peak > 0.05 && peak <= 1.0, "{} peak {}", name, peak);
+            assert!(!alive, "{} never ends", name);
+        }
+    }
+}
@@ -1,3 +1,14 @@
-pub mod engine;
-pub mod synth;
+// engine.rs は組み込んでいない（その役割は main.rs の Player が持つ）
+pub mod acid;
+pub mod compressor;
+pub mod delay;
+pub mod distortion;
+pub mod drums;
 pub mod effects;
+pub mod filter;
+pub mod fm;
+pub mod osc;
+pub mod reverb;
+pub mod sampler;
+pub mod synth;
+pub mod voice;
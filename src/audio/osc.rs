@@ -0,0 +1,156 @@
+// src/audio/osc.rs
+// 帯域制限したオシレーター（PolyBLEP）。高い音でも折り返しノイズが出にくい
+//
+// のこぎり波・矩形波・パルス波は段差を PolyBLEP で、三角波は角を PolyBLAMP で丸める。
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wave {
+    Saw,
+    Square,
+    Triangle,
+    Sine,
+    Pulse,
+}
+
+impl Wave {
+    // Wave パラメータの値の並び（保存したプロジェクトと合わせるため順番は変えない）
+    pub const ALL: [Wave; 5] = [Wave::Saw, Wave::Square, Wave::Triangle, Wave::Sine, Wave::Pulse];
+
+    pub fn from_param(value: f32) -> Wave {
+        Wave::ALL[(value.round().max(0.0) as usize).min(Wave::ALL.len() - 1)]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wave::Saw => "Saw",
+            Wave::Square => "Square",
+            Wave::Triangle => "Tri",
+            Wave::Sine => "Sine",
+            Wave::Pulse => "Pulse",
+        }
+    }
+}
+
+// 段差の前後 1 サンプルにかける補正（t は位相、dt は 1 サンプルで進む位相）
+fn blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+// 傾きが変わる角の前後 1 サンプルにかける補正（blep を積分したもの）
+fn blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = 1.0 - t / dt;
+        x * x * x / 6.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 6.0
+    } else {
+        0.0
+    }
+}
+
+pub struct Oscillator {
+    phase: f32, // 0.0〜1.0
+}
+
+impl Oscillator {
+    pub fn new(phase: f32) -> Self {
+        Self { phase: phase.rem_euclid(1.0) }
+    }
+
+    // 1 サンプル分の値を返して位相を進める。width はパルス波のデューティ比
+    pub fn next(&mut self, wave: Wave, freq: f32, sample_rate: f32, width: f32) -> f32 {
+        let t = self.phase;
+        let dt = (freq / sample_rate).clamp(0.0, 0.5);
+        let value = match wave {
+            Wave::Saw => 2.0 * t - 1.0 - blep(t, dt),
+            Wave::Square => pulse(t, dt, 0.5),
+            Wave::Pulse => pulse(t, dt, width.clamp(0.05, 0.95)),
+            Wave::Triangle => {
+                // 0 で 1、0.5 で -1 の三角波。角では傾きが ±8dt 変わる
+                let naive = 2.0 * (2.0 * t - 1.0).abs() - 1.0;
+                let half = (t + 0.5).fract();
+                naive - 8.0 * dt * blamp(t, dt) + 8.0 * dt * blamp(half, dt)
+            }
+            Wave::Sine => (2.0 * PI * t).sin(),
+        };
+        self.phase = (t + dt).fract();
+        value
+    }
+}
+
+// 幅が 0.5 以外だと直流分が出るので差し引く
+fn pulse(t: f32, dt: f32, width: f32) -> f32 {
+    let naive = if t < width { 1.0 } else { -1.0 };
+    naive + blep(t, dt) - blep((t - width).rem_euclid(1.0), dt) - (2.0 * width - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 44100.0;
+    const FREQ: f32 = 2900.0; // 周期が整数サンプルにならず、折り返しが倍音の間に落ちる
+
+    // 0.1 秒分の波形
+    fn render(mut next: impl FnMut() -> f32) -> Vec<f32> {
+        (0..RATE as usize / 10).map(|_| next()).collect()
+    }
+
+    // 倍音（FREQ の整数倍）から外れた成分のエネルギーの割合。10Hz 刻みで調べる
+    fn alias_ratio(signal: &[f32]) -> f32 {
+        let (mut alias, mut total) = (0.0, 0.0);
+        for bin in 1..(RATE as usize / 20) {
+            let freq = bin as f32 * 10.0;
+            let (mut re, mut im) = (0.0_f32, 0.0_f32);
+            for (i, s) in signal.iter().enumerate() {
+                let angle = 2.0 * PI * (freq * i as f32 / RATE).fract();
+                re += s * angle.cos();
+                im += s * angle.sin();
+            }
+            let power = re * re + im * im;
+            total += power;
+            let harmonic = (freq / FREQ).round() * FREQ;
+            if (freq - harmonic).abs() > 20.0 {
+                alias += power;
+            }
+        }
+        alias / total
+    }
+
+    #[test]
+    fn waves_stay_in_range() {
+        for wave in Wave::ALL {
+            for width in [0.05, 0.5, 0.95] {
+                let mut osc = Oscillator::new(0.0);
+                let signal = render(|| osc.next(wave, FREQ, RATE, width));
+                let peak = signal.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+                let mean = signal.iter().sum::<f32>() / signal.len() as f32;
+                assert!(peak <= 2.0, "{:?} peak {}", wave, peak);
+                assert!(mean.abs() < 0.01, "{:?} {} mean {}", wave, width, mean);
+            }
+        }
+    }
+
+    #[test]
+    fn saw_has_less_aliasing_than_naive() {
+        let mut osc = Oscillator::new(0.0);
+        let blep = alias_ratio(&render(|| osc.next(Wave::Saw, FREQ, RATE, 0.5)));
+        let mut phase = 0.0_f32;
+        let naive = alias_ratio(&render(|| {
+            let value = 2.0 * phase - 1.0;
+            phase = (phase + FREQ / RATE).fract();
+            value
+        }));
+        assert!(blep < naive / 10.0, "{} vs {}", blep, naive);
+    }
+}
@@ -0,0 +1,196 @@
+// src/audio/reverb.rs
+// アルゴリズムリバーブ（Freeverb 方式）
+//
+// 左右それぞれ8本のコムフィルターを並べて響きを作り、4本のオールパスで拡散させる。
+// 右チャンネルは遅れを少しずらして左右の響きを変え、広がりを出す。
+// ふつうはセンドバスに挿してトラックから送る（その場合は Mix を 1.0 にする）。
+use super::effects::{Context, Effect};
+use super::voice::ParamSpec;
+
+// 44.1kHz での遅れ（サンプル数）。他のサンプルレートでは比例させる
+const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const INPUT_GAIN: f32 = 0.015;
+const MAX_PRE_DELAY: f32 = 0.2; // 秒
+const MAX_TAIL: f32 = 20.0;
+
+// Mix パラメータの位置（センドバスの既定のリバーブはここを 1.0 にする）
+pub const MIX: usize = 4;
+
+// 並びは Size, Damping, Pre-Delay, Width, Mix
+pub static PARAMS: [ParamSpec; 5] = [
+    ParamSpec { name: "Size", unit: "", min: 0.0, max: 1.0, default: 0.6 },
+    ParamSpec { name: "Damping", unit: "", min: 0.0, max: 1.0, default: 0.5 },
+    ParamSpec { name: "Pre-Delay", unit: "ms", min: 0.0, max: 200.0, default: 10.0 },
+    ParamSpec { name: "Width", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+    ParamSpec { name: "Mix", unit: "", min: 0.0, max: 1.0, default: 0.3 },
+];
+
+// Size からコムのフィードバック（Freeverb の 0.7〜0.98）
+fn feedback(size: f32) -> f32 {
+    0.7 + 0.28 * size
+}
+
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    damp: f32, // フィードバックのローパスの状態
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.buffer[self.pos];
+        self.damp = out * (1.0 - damping) + self.damp * damping;
+        self.buffer[self.pos] = input + self.damp * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+// 左右のコムとオールパス、プリディレイ（長さはサンプルレートに合わせる）
+struct Tank {
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    pre_delay: Vec<f32>,
+    pos: usize,
+}
+
+impl Tank {
+    fn new(sample_rate: f32) -> Self {
+        let scale = |len: usize| ((len as f32 * sample_rate / 44100.0) as usize).max(1);
+        let combs = |spread: usize| {
+            COMBS
+                .iter()
+                .map(|&len| Comb { buffer: vec![0.0; scale(len + spread)], pos: 0, damp: 0.0 })
+                .collect()
+        };
+        let allpasses = |spread: usize| {
+            ALLPASSES
+                .iter()
+                .map(|&len| Allpass { buffer: vec![0.0; scale(len + spread)], pos: 0 })
+                .collect()
+        };
+        Self {
+            combs: [combs(0), combs(STEREO_SPREAD)],
+            allpasses: [allpasses(0), allpasses(STEREO_SPREAD)],
+            pre_delay: vec![0.0; (MAX_PRE_DELAY * sample_rate) as usize + 1],
+            pos: 0,
+        }
+    }
+}
+
+pub struct Reverb {
+    tank: Tank,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        Self { tank: Tank::new(sample_rate) }
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &[f32], ctx: &Context) {
+        let tank = &mut self.tank;
+        let feedback = feedback(params[0]);
+        let damping = params[1] * 0.4;
+        let pre_delay = ((params[2] / 1000.0 * ctx.sample_rate) as usize).min(tank.pre_delay.len() - 1);
+        let (width, mix) = (params[3], params[MIX]);
+        // 幅 1.0 で左右の響きをそのまま、0.0 で両方を混ぜてモノラルに
+        let (wet1, wet2) = ((width / 2.0 + 0.5) * mix, (1.0 - width) / 2.0 * mix);
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let len = tank.pre_delay.len();
+            tank.pre_delay[tank.pos] = (*l + *r) * INPUT_GAIN;
+            let input = tank.pre_delay[(tank.pos + len - pre_delay) % len];
+            tank.pos = (tank.pos + 1) % len;
+
+            let mut out = [0.0; 2];
+            for (channel, out) in out.iter_mut().enumerate() {
+                let mut sum: f32 = tank.combs[channel]
+                    .iter_mut()
+                    .map(|comb| comb.process(input, feedback, damping))
+                    .sum();
+                for allpass in &mut tank.allpasses[channel] {
+                    sum = allpass.process(sum);
+                }
+                *out = sum;
+            }
+
+            *l = *l * (1.0 - mix) + out[0] * wet1 + out[1] * wet2;
+            *r = *r * (1.0 - mix) + out[1] * wet1 + out[0] * wet2;
+        }
+    }
+
+    // いちばん長いコムが -60dB まで減衰するまで（ダンピングは考えないので長めになる）
+    fn tail(&self, params: &[f32], _ctx: &Context) -> f32 {
+        let longest = COMBS[COMBS.len() - 1] as f32 / 44100.0;
+        let decay = longest * 0.001_f32.ln() / feedback(params[0]).ln();
+        (params[2] / 1000.0 + decay).min(MAX_TAIL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 44100.0;
+
+    // 頭のインパルスに対する左チャンネルの 0.1 秒ごとのエネルギー
+    fn impulse_energy(params: &[f32], seconds: usize) -> Vec<f32> {
+        let ctx = Context { sample_rate: RATE, samples_per_step: 5512.5, keys: &[] };
+        let mut reverb = Reverb::new(RATE);
+        let len = RATE as usize * seconds;
+        let (mut left, mut right) = (vec![0.0; len], vec![0.0; len]);
+        left[0] = 1.0;
+        right[0] = 1.0;
+        reverb.process(&mut left, &mut right, params, &ctx);
+        left.chunks(RATE as usize / 10).map(|c| c.iter().map(|s| s * s).sum()).collect()
+    }
+
+    fn params(size: f32, mix: f32) -> [f32; 5] {
+        [size, 0.5, 0.0, 1.0, mix]
+    }
+
+    #[test]
+    fn impulse_leaves_a_decaying_tail() {
+        let energy = impulse_energy(&params(0.6, 1.0), 3);
+        assert!(energy[1] > 1e-4, "{:?}", energy);
+        assert!(energy[5] < energy[1] && energy[29] < energy[5] / 100.0, "{:?}", energy);
+
+        // Mix 0 は素通し
+        let dry = impulse_energy(&params(0.6, 0.0), 1);
+        assert_eq!(dry[0], 1.0);
+        assert!(dry[1..].iter().all(|&e| e == 0.0));
+    }
+
+    #[test]
+    fn larger_rooms_ring_longer() {
+        let (small, large) = (impulse_energy(&params(0.1, 1.0), 2), impulse_energy(&params(0.9, 1.0), 2));
+        assert!(large[10] > small[10] * 10.0);
+
+        let ctx = Context { sample_rate: RATE, samples_per_step: 5512.5, keys: &[] };
+        let reverb = Reverb::new(RATE);
+        let tail = |p: &[f32]| reverb.tail(p, &ctx);
+        assert!(tail(&params(0.9, 1.0)) > tail(&params(0.1, 1.0)));
+        // プリディレイの分だけ長くなる
+        let mut delayed = params(0.6, 1.0);
+        delayed[2] = 100.0;
+        assert!((tail(&delayed) - tail(&params(0.6, 1.0)) - 0.1).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,356 @@
+// src/audio/sampler.rs
+// WAV ファイルを鳴らすサンプラー
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavReader};
+
+use crate::sequencer::pattern::InstrumentType;
+
+use super::voice::{Hit, Instrument, ParamSpec, Registry, Voice};
+
+pub fn register(registry: &mut Registry) {
+    // サンプルなしの楽器（パラメータの定義用）。実際の音はトラックのサンプルで鳴らす
+    registry.register(InstrumentType::Sampler, Box::new(Sampler(None)));
+    registry.register(InstrumentType::Slicer, Box::new(Slicer(None)));
+}
+
+// スライス数の上限（Slices パラメータの最大値）
+pub const MAX_SLICES: usize = 32;
+
+// 読み込んだ音声（モノラルにまとめたもの）
+pub struct Sample {
+    pub rate: f32,
+    pub data: Vec<f32>,
+    // スライス数と切り方ごとの開始位置（読み込んだときに全部求めておき、発音や描画では引くだけ）
+    slices: Vec<Vec<usize>>,
+}
+
+impl Sample {
+    pub fn new(rate: f32, data: Vec<f32>) -> Self {
+        let onsets = detect_onsets(&data, rate);
+        let slices = (1..=MAX_SLICES)
+            .flat_map(|count| [false, true].map(|transient| slice_points(data.len(), &onsets, count, transient)))
+            .collect();
+        Self { rate, data, slices }
+    }
+
+    // スライスの開始位置（先頭は必ず 0）。transient なら強い立ち上がりから順に使うので、
+    // 立ち上がりが足りなければ count より少なくなる
+    pub fn slices(&self, count: usize, transient: bool) -> &[usize] {
+        &self.slices[(count.clamp(1, MAX_SLICES) - 1) * 2 + transient as usize]
+    }
+}
+
+pub fn load(path: &Path) -> Result<Sample, Box<dyn Error>> {
+    let reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let data: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    // 複数チャンネルは平均してモノラルに
+    let channels = spec.channels.max(1) as usize;
+    let data = data
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect::<Vec<f32>>();
+    Ok(Sample::new(spec.sample_rate as f32, data))
+}
+
+// 立ち上がりを探す。5ms ごとの音量（対数）が直前 20ms の平均からどれだけ増えたかを強さとし、
+// 前後 50ms で一番強いところを拾う
+fn detect_onsets(data: &[f32], rate: f32) -> Vec<usize> {
+    const THRESHOLD: f32 = 1.5; // 約 6.5dB の増加
+    let hop = ((rate * 0.005) as usize).max(1);
+    let gap = 10;
+    let energy: Vec<f32> = data
+        .chunks(hop)
+        .map(|c| (c.iter().map(|s| s * s).sum::<f32>() / c.len() as f32 + 1e-9).ln())
+        .collect();
+    let strength: Vec<f32> = (0..energy.len())
+        .map(|i| {
+            let past = &energy[i.saturating_sub(4)..i];
+            if past.is_empty() {
+                return 0.0;
+            }
+            (energy[i] - past.iter().sum::<f32>() / past.len() as f32).max(0.0)
+        })
+        .collect();
+
+    let mut onsets: Vec<(usize, f32)> = (gap..strength.len())
+        .filter(|&i| {
+            let around = &strength[i.saturating_sub(gap)..(i + gap + 1).min(strength.len())];
+            strength[i] >= THRESHOLD && around.iter().all(|&s| s <= strength[i])
+        })
+        .map(|i| (i * hop, strength[i]))
+        .collect();
+    onsets.sort_by(|a, b| b.1.total_cmp(&a.1));
+    onsets.into_iter().map(|(pos, _)| pos).collect()
+}
+
+// len サンプルを count 個に切ったときの開始位置（onsets は立ち上がりの位置を強い順に）
+fn slice_points(len: usize, onsets: &[usize], count: usize, transient: bool) -> Vec<usize> {
+    if !transient {
+        return (0..count).map(|i| i * len / count).collect();
+    }
+    let mut points: Vec<usize> = std::iter::once(0)
+        .chain(onsets.iter().copied().take(count - 1))
+        .collect();
+    points.sort_unstable();
+    points
+}
+
+// ループ全体を beats 拍とみなしたときの元のテンポ
+pub fn loop_bpm(sample: &Sample, beats: f32) -> f32 {
+    beats * 60.0 * sample.rate / sample.data.len().max(1) as f32
+}
+
+// ノート 60 で元の音程
+const ROOT_NOTE: f32 = 60.0;
+
+// 区間の終わりのフェードアウト（クリック防止）
+const END_FADE: f32 = 0.002;
+
+pub struct Sampler(pub Option<Arc<Sample>>);
+
+impl Instrument for Sampler {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Start", unit: "", min: 0.0, max: 1.0, default: 0.0 },
+            ParamSpec { name: "End", unit: "", min: 0.0, max: 1.0, default: 1.0 },
+            ParamSpec { name: "Reverse", unit: "switch", min: 0.0, max: 1.0, default: 0.0 },
+            ParamSpec { name: "Pitch", unit: "st", min: -24.0, max: 24.0, default: 0.0 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let Some(sample) = &self.0 else { return Box::new(Silence) };
+        let len = sample.data.len() as f32;
+        let (start, end) = (params[0].min(params[1]) * len, params[0].max(params[1]) * len);
+        let reverse = params[2] >= 0.5;
+
+        // ステップのノートとピッチで速さを変え、エンジンのサンプルレートに合わせる
+        let semitones = hit.note - ROOT_NOTE + hit.pitch + params[3];
+        let speed = 2.0_f32.powf(semitones / 12.0) * sample.rate / sample_rate;
+
+        Box::new(SamplerVoice {
+            sample: sample.clone(),
+            position: if reverse { end - 1.0 } else { start },
+            step: if reverse { -speed } else { speed },
+            start,
+            end,
+            fade: END_FADE * sample.rate,
+            gain: hit.gain,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        4
+    }
+}
+
+struct SamplerVoice {
+    sample: Arc<Sample>,
+    position: f32, // 元のサンプル上の位置
+    step: f32,     // 1サンプルで進む量（逆再生なら負）
+    start: f32,
+    end: f32,
+    fade: f32, // 区間の端からフェードアウトを始める距離（元のサンプル数）
+    gain: f32,
+}
+
+impl Voice for SamplerVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        let data = &self.sample.data;
+        for sample in out {
+            if self.position < self.start || self.position > self.end - 1.0 {
+                return false;
+            }
+            // 線形補間で読み出す
+            let idx = self.position as usize;
+            let frac = self.position - idx as f32;
+            let next = data[(idx + 1).min(data.len() - 1)];
+            let value = data[idx] + (next - data[idx]) * frac;
+
+            let remaining = if self.step > 0.0 {
+                self.end - self.position
+            } else {
+                self.position - self.start
+            };
+            let envelope = (remaining / self.fade).min(1.0);
+            *sample += value * envelope * self.gain;
+            self.position += self.step;
+        }
+        true
+    }
+}
+
+// ループを切り分けて、ステップのノートでスライスを選んで鳴らす（ノート 60 が最初のスライス）
+pub struct Slicer(pub Option<Arc<Sample>>);
+
+impl Slicer {
+    // params の値で切ったときの開始位置
+    pub fn points<'a>(sample: &'a Sample, params: &[f32]) -> &'a [usize] {
+        sample.slices(params[0] as usize, params[1] >= 0.5)
+    }
+}
+
+impl Instrument for Slicer {
+    fn params(&self) -> &'static [ParamSpec] {
+        &[
+            ParamSpec { name: "Slices", unit: "slices", min: 1.0, max: MAX_SLICES as f32, default: 8.0 },
+            ParamSpec { name: "Transient", unit: "switch", min: 0.0, max: 1.0, default: 0.0 },
+            ParamSpec { name: "Beats", unit: "beats", min: 1.0, max: 64.0, default: 4.0 },
+            ParamSpec { name: "Pitch", unit: "st", min: -24.0, max: 24.0, default: 0.0 },
+        ]
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let Some(sample) = &self.0 else { return Box::new(Silence) };
+        let points = Slicer::points(sample, params);
+        // スライス数より上のノートは先頭から繰り返す
+        let idx = (hit.note - ROOT_NOTE).rem_euclid(points.len() as f32) as usize;
+        let start = points[idx] as f32;
+        let end = points.get(idx + 1).map_or(sample.data.len(), |&p| p) as f32;
+
+        let semitones = hit.pitch + params[3];
+        let speed = 2.0_f32.powf(semitones / 12.0) * sample.rate / sample_rate;
+        Box::new(SamplerVoice {
+            sample: sample.clone(),
+            position: start,
+            step: speed,
+            start,
+            end,
+            fade: END_FADE * sample.rate,
+            gain: hit.gain,
+        })
+    }
+
+    // 次のスライスが前のスライスを止める
+    fn polyphony(&self) -> usize {
+        1
+    }
+}
+
+// サンプルが読み込まれていないときの無音
+struct Silence;
+
+impl Voice for Silence {
+    fn render(&mut self, _out: &mut [f32]) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 静かな中に 0.25 秒ごとのクリック（最初はなし）
+    fn clicks() -> Sample {
+        let rate = 8000.0;
+        let mut data = vec![0.001; 8000];
+        for at in [2000, 4000, 6000] {
+            data[at..at + 200].fill(0.8);
+        }
+        Sample::new(rate, data)
+    }
+
+    #[test]
+    fn slices_are_cached_per_count_and_mode() {
+        let sample = clicks();
+        assert_eq!(sample.slices(4, false), [0, 2000, 4000, 6000]);
+        assert_eq!(sample.slices(0, false), [0]);
+        assert_eq!(sample.slices(MAX_SLICES + 8, false).len(), MAX_SLICES);
+
+        // 立ち上がりで切ると、見つかった分だけ（位置は解析の粒度に丸まる）
+        let transient = sample.slices(8, true);
+        assert_eq!(transient.len(), 4);
+        assert_eq!(transient[0], 0);
+        for (point, at) in transient[1..].iter().zip([2000, 4000, 6000]) {
+            assert!(point.abs_diff(at) <= 40, "{} vs {}", point, at);
+        }
+        // 同じ値なら作り直さずに同じものを返す
+        assert!(std::ptr::eq(sample.slices(8, true), transient));
+    }
+
+    fn hit(note: f32) -> Hit {
+        Hit { note, gain: 1.0, pitch: 0.0, decay: 1.0, gate: 0.1, glide_from: None, accent: false }
+    }
+
+    // 鳴り終わるまでの出力
+    fn play(sample: Sample, note: f32, params: &[f32]) -> Vec<f32> {
+        let sampler = Sampler(Some(Arc::new(sample)));
+        let mut voice = sampler.note_on(1000.0, hit(note), params);
+        let mut out = Vec::new();
+        let mut block = [0.0; 100];
+        loop {
+            block.fill(0.0);
+            let alive = voice.render(&mut block);
+            out.extend_from_slice(&block);
+            if !alive || out.len() > 10000 {
+                break;
+            }
+        }
+        // 最後のブロックの鳴り終わった後の無音は落とす（端のフェードで最後の1サンプルも 0 になりうる）
+        while out.last() == Some(&0.0) {
+            out.pop();
+        }
+        out
+    }
+
+    // 0 から 1 へ上がっていく 1 秒のランプ
+    fn ramp() -> Sample {
+        Sample::new(1000.0, (0..1000).map(|i| i as f32 / 1000.0).collect())
+    }
+
+    #[test]
+    fn sampler_plays_the_chosen_region() {
+        let out = play(ramp(), ROOT_NOTE, &[0.25, 0.5, 0.0, 0.0]);
+        assert!(out.len().abs_diff(250) <= 1);
+        assert_eq!(out[0], 0.25);
+        assert_eq!(out[100], 0.35);
+        assert!(out.windows(2).all(|w| w[1] <= w[0] + 0.0011));
+
+        // 逆再生は終わりから戻る
+        let reversed = play(ramp(), ROOT_NOTE, &[0.25, 0.5, 1.0, 0.0]);
+        assert!(reversed.len().abs_diff(250) <= 1);
+        assert_eq!(reversed[0], 0.499);
+        assert!(reversed[1] < reversed[0]);
+    }
+
+    #[test]
+    fn note_and_pitch_change_the_speed() {
+        // 1 オクターブ上は倍の速さで半分の長さ
+        let octave_up = play(ramp(), ROOT_NOTE + 12.0, &[0.0, 1.0, 0.0, 0.0]);
+        assert!(octave_up.len().abs_diff(500) <= 1);
+        assert_eq!(octave_up[100], 0.2);
+        let pitched = play(ramp(), ROOT_NOTE, &[0.0, 1.0, 0.0, -12.0]);
+        assert!(pitched.len().abs_diff(2000) <= 1);
+    }
+
+    #[test]
+    fn wav_files_are_mixed_down_to_mono() {
+        let path = std::env::temp_dir().join(format!("rhythm-box-sample-{}.wav", std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 22050, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(16384_i16).unwrap();
+            writer.write_sample(0_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let sample = load(&path);
+        let _ = std::fs::remove_file(&path);
+        let sample = sample.unwrap();
+        assert_eq!(sample.rate, 22050.0);
+        assert_eq!(sample.data, vec![0.25; 100]);
+    }
+}
@@ -1,32 +1,153 @@
 // src/audio/synth.rs
-use fundsp::prelude::*;
-
-pub fn kick_synth() -> An<impl AudioUnit> {
-    // シンプルなキック: 低周波サイン波 + エンベロープ
-    let freq = 60.0;
-    let decay = 0.3;
-    
-    (sine_hz(freq) * envelope(|t| exp(-t / decay)))
-        >> lowpass_hz(200.0, 1.0)
+// メロディ用のシンセ（パッドとリード）。少しずらした2つのオシレーターを ADSR で包む
+use std::f32::consts::PI;
+
+use crate::sequencer::pattern::InstrumentType;
+
+use super::osc::{Oscillator, Wave};
+use super::voice::{Hit, Instrument, ParamSpec, Registry, Voice};
+
+pub fn register(registry: &mut Registry) {
+    registry.register(InstrumentType::Pad, Box::new(Synth { params: &PAD, polyphony: 8 }));
+    registry.register(InstrumentType::Lead, Box::new(Synth { params: &LEAD, polyphony: 4 }));
+}
+
+// PWM でパルス幅を揺らす幅
+const PWM_DEPTH: f32 = 0.35;
+
+// 並びは Wave, Width, PWM, Detune, Attack, Decay, Sustain, Release, Glide
+static PAD: [ParamSpec; 9] = [
+    ParamSpec { name: "Wave", unit: "wave", min: 0.0, max: 4.0, default: 0.0 },
+    ParamSpec { name: "Width", unit: "", min: 0.05, max: 0.95, default: 0.5 },
+    ParamSpec { name: "PWM", unit: "Hz", min: 0.0, max: 8.0, default: 0.0 },
+    ParamSpec { name: "Detune", unit: "ct", min: 0.0, max: 50.0, default: 12.0 },
+    ParamSpec { name: "Attack", unit: "s", min: 0.001, max: 4.0, default: 0.4 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.01, max: 4.0, default: 0.6 },
+    ParamSpec { name: "Sustain", unit: "", min: 0.0, max: 1.0, default: 0.8 },
+    ParamSpec { name: "Release", unit: "s", min: 0.01, max: 6.0, default: 1.2 },
+    ParamSpec { name: "Glide", unit: "s", min: 0.0, max: 1.0, default: 0.0 },
+];
+
+static LEAD: [ParamSpec; 9] = [
+    ParamSpec { name: "Wave", unit: "wave", min: 0.0, max: 4.0, default: 1.0 },
+    ParamSpec { name: "Width", unit: "", min: 0.05, max: 0.95, default: 0.5 },
+    ParamSpec { name: "PWM", unit: "Hz", min: 0.0, max: 8.0, default: 0.0 },
+    ParamSpec { name: "Detune", unit: "ct", min: 0.0, max: 50.0, default: 6.0 },
+    ParamSpec { name: "Attack", unit: "s", min: 0.001, max: 2.0, default: 0.005 },
+    ParamSpec { name: "Decay", unit: "s", min: 0.01, max: 2.0, default: 0.25 },
+    ParamSpec { name: "Sustain", unit: "", min: 0.0, max: 1.0, default: 0.7 },
+    ParamSpec { name: "Release", unit: "s", min: 0.01, max: 4.0, default: 0.15 },
+    ParamSpec { name: "Glide", unit: "s", min: 0.0, max: 1.0, default: 0.08 },
+];
+
+// パッドは長い和音を厚く重ね、リードは短めの和音まで（どちらも前の音から滑らせられる）
+pub struct Synth {
+    params: &'static [ParamSpec; 9],
+    polyphony: usize,
 }
 
-pub fn bass_synth(note: f32) -> An<impl AudioUnit> {
-    // ベース: サイン波 + サブオクターブ
-    let freq = midi_to_hz(note);
-    
-    (sine_hz(freq) * 0.7 + sine_hz(freq / 2.0) * 0.3)
-        >> lowpass_hz(800.0, 2.0)
+impl Instrument for Synth {
+    fn params(&self) -> &'static [ParamSpec] {
+        self.params
+    }
+
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice> {
+        let freq = hit.freq();
+        let glide = params[8];
+        // 前の音がなければ滑らせない
+        let from = match hit.glide_from {
+            Some(note) if glide > 0.0 => 440.0 * 2.0_f32.powf((note + hit.pitch - 69.0) / 12.0),
+            _ => freq,
+        };
+        Box::new(SynthVoice {
+            wave: Wave::from_param(params[0]),
+            width: params[1],
+            pwm: params[2],
+            detune: 2.0_f32.powf(params[3] / 1200.0),
+            oscs: [Oscillator::new(0.0), Oscillator::new(0.5)],
+            from,
+            freq,
+            glide,
+            attack: params[4],
+            decay: params[5] * hit.decay,
+            sustain: params[6],
+            release: params[7] * hit.decay,
+            gate: hit.gate,
+            time: 0.0,
+            sample_rate,
+            dt: 1.0 / sample_rate,
+            level: 0.0,
+            gain: hit.gain * 0.5,
+        })
+    }
+
+    fn polyphony(&self) -> usize {
+        self.polyphony
+    }
 }
 
-pub fn pad_synth(note: f32) -> An<impl AudioUnit> {
-    // パッド: 複数のソウ波 + リバーブ風
-    let freq = midi_to_hz(note);
-    
-    (saw_hz(freq) + saw_hz(freq * 1.01) + saw_hz(freq * 0.99))
-        * 0.3
-        >> lowpass_hz(2000.0, 0.5)
+struct SynthVoice {
+    wave: Wave,
+    width: f32,  // パルス幅
+    pwm: f32,    // パルス幅を揺らす速さ（0 で揺らさない）
+    detune: f32, // 2つ目のオシレーターの周波数比（1つ目は逆数）
+    oscs: [Oscillator; 2],
+    from: f32, // グライドの開始周波数
+    freq: f32,
+    glide: f32,
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+    gate: f32, // この時間が過ぎたらリリース
+    time: f32,
+    sample_rate: f32,
+    dt: f32,
+    level: f32, // エンベロープの現在値
+    gain: f32,
 }
 
-fn midi_to_hz(note: f32) -> f32 {
-    440.0 * 2.0_f32.powf((note - 69.0) / 12.0)
-}
\ No newline at end of file
+impl SynthVoice {
+    // ADSR を1サンプル進める。アタックは直線、ディケイとリリースは指数で
+    // （時間の 1/5 を時定数にして、指定した時間でほぼ落ち切るようにする）
+    fn envelope(&mut self) -> f32 {
+        if self.time >= self.gate {
+            self.level *= (-5.0 * self.dt / self.release).exp();
+        } else if self.time < self.attack {
+            self.level = (self.level + self.dt / self.attack).min(1.0);
+        } else {
+            let coef = (-5.0 * self.dt / self.decay).exp();
+            self.level = self.sustain + (self.level - self.sustain) * coef;
+        }
+        self.level
+    }
+
+    fn current_freq(&self) -> f32 {
+        if self.time >= self.glide {
+            return self.freq;
+        }
+        // 周波数を指数的に（音程として等速に）近づける
+        self.from * (self.freq / self.from).powf(self.time / self.glide)
+    }
+}
+
+impl Voice for SynthVoice {
+    fn render(&mut self, out: &mut [f32]) -> bool {
+        for sample in out {
+            let freq = self.current_freq();
+            let level = self.envelope();
+            if self.time >= self.gate && level < 1e-4 {
+                return false;
+            }
+            let width = self.width + (2.0 * PI * self.pwm * self.time).sin() * PWM_DEPTH;
+            let ratios = [1.0 / self.detune, self.detune];
+            let mut value = 0.0;
+            for (osc, ratio) in self.oscs.iter_mut().zip(ratios) {
+                value += osc.next(self.wave, freq * ratio, self.sample_rate, width) * 0.5;
+            }
+            *sample += value * level * self.gain;
+            self.time += self.dt;
+        }
+        true
+    }
+}
@@ -0,0 +1,163 @@
+// src/audio/voice.rs
+// 楽器（音色）とボイス（1回の発音）の共通インターフェースと、楽器の登録表
+use crate::sequencer::pattern::InstrumentType;
+
+use super::osc::Wave;
+use super::{acid, compressor, delay, distortion, drums, fm, sampler, synth};
+
+// 楽器ごとの合成パラメータの定義
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+impl ParamSpec {
+    // 1段階分だけ値を動かす（半音や個数や音符の長さやトラックは1ずつ、比率は 0.25 ずつ、スイッチや波形やカーブは切り替え、それ以外は範囲の 1/40 ずつ）
+    pub fn nudge(&self, value: f32, dir: i32) -> f32 {
+        let step = match self.unit {
+            "st" | "switch" | "wave" | "alg" | "note" | "key" | "curve" | "os" | "slices" | "beats" | "bits"
+            | "ct" => 1.0,
+            "ratio" => 0.25,
+            _ => (self.max - self.min) / 40.0,
+        };
+        (value + step * dir as f32).clamp(self.min, self.max)
+    }
+
+    pub fn format(&self, value: f32) -> String {
+        if self.unit == "switch" {
+            return if value >= 0.5 { "on" } else { "off" }.to_string();
+        }
+        if self.unit == "wave" {
+            return Wave::from_param(value).name().to_string();
+        }
+        if self.unit == "alg" {
+            return fm::algorithm(value).name.to_string();
+        }
+        if self.unit == "note" {
+            return delay::delay_time(value).0.to_string();
+        }
+        if self.unit == "key" {
+            return match compressor::key_track(value) {
+                Some(track) => format!("Track {}", track + 1),
+                None => "Self".to_string(),
+            };
+        }
+        if self.unit == "curve" {
+            return distortion::CURVES[distortion::curve(value)].to_string();
+        }
+        if self.unit == "os" {
+            return format!("{}x", distortion::oversampling(value));
+        }
+        if self.unit == "ratio" {
+            return format!("x{:.2}", value);
+        }
+        if self.unit == "st" {
+            return format!("{:+.0} st", value);
+        }
+        if matches!(self.unit, "slices" | "beats" | "bits" | "ct") {
+            return format!("{:.0} {}", value, self.unit);
+        }
+        if value.abs() >= 100.0 {
+            format!("{:.0} {}", value, self.unit)
+        } else {
+            format!("{:.2} {}", value, self.unit)
+        }
+    }
+}
+
+// 音色のプリセット（名前と params() と同じ並びの値）
+pub type Preset = (&'static str, &'static [f32]);
+
+// 1回の発音に渡す値（パラメータロック適用済み）
+#[derive(Clone, Copy)]
+pub struct Hit {
+    pub note: f32,  // MIDI ノート番号
+    pub gain: f32,
+    pub pitch: f32, // 半音
+    pub decay: f32, // 減衰時間の倍率
+    pub gate: f32,  // 音を伸ばす長さ（秒）。伸ばせる音色だけが使う
+    pub glide_from: Option<f32>, // 同じトラックで直前に鳴らしたノート（グライド用）
+    pub accent: bool,
+}
+
+impl Hit {
+    pub fn pitch_ratio(&self) -> f32 {
+        2.0_f32.powf(self.pitch / 12.0)
+    }
+
+    // ノートとピッチから周波数を求める
+    pub fn freq(&self) -> f32 {
+        440.0 * 2.0_f32.powf((self.note + self.pitch - 69.0) / 12.0)
+    }
+}
+
+// 発音中の音。ブロック単位で生成する
+pub trait Voice: Send {
+    // out に足し込む。鳴り終わったら false を返す
+    fn render(&mut self, out: &mut [f32]) -> bool;
+
+    // 鳴らし直さずに次のノートへ移る（スライド）。できない音色は false を返す
+    fn slide(&mut self, _hit: Hit) -> bool {
+        false
+    }
+}
+
+pub trait Instrument: Send {
+    fn params(&self) -> &'static [ParamSpec];
+
+    // params は params() と同じ並びの値
+    fn note_on(&self, sample_rate: f32, hit: Hit, params: &[f32]) -> Box<dyn Voice>;
+
+    // 1トラックで同時に鳴らせる数。超えたら古い音から止める
+    fn polyphony(&self) -> usize {
+        8
+    }
+
+    // 用意してある音色（なければ空）
+    fn presets(&self) -> &'static [Preset] {
+        &[]
+    }
+
+    // トラックに保存された値。保存されていなければ既定値
+    fn values(&self, saved: &[f32]) -> Vec<f32> {
+        if saved.len() == self.params().len() {
+            saved.to_vec()
+        } else {
+            self.params().iter().map(|p| p.default).collect()
+        }
+    }
+}
+
+// InstrumentType ごとの楽器。登録されていない種類は鳴らない
+pub struct Registry {
+    instruments: Vec<(InstrumentType, Box<dyn Instrument>)>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            instruments: Vec::new(),
+        };
+        drums::register(&mut registry);
+        acid::register(&mut registry);
+        sampler::register(&mut registry);
+        synth::register(&mut registry);
+        fm::register(&mut registry);
+        registry
+    }
+
+    pub fn register(&mut self, kind: InstrumentType, instrument: Box<dyn Instrument>) {
+        self.instruments.retain(|(k, _)| *k != kind);
+        self.instruments.push((kind, instrument));
+    }
+
+    pub fn get(&self, kind: InstrumentType) -> Option<&dyn Instrument> {
+        self.instruments
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, instrument)| instrument.as_ref())
+    }
+}
@@ -0,0 +1,191 @@
+// src/kit.rs
+// ドラムキット：フォルダ内の WAV をトラックに割り当てる
+//
+// トラック名（なければ楽器名）とファイル名を、よくある呼び名でつき合わせる。
+// フォルダに kit.txt があればそちらを優先する:
+//   # トラック名 = ファイル
+//   Kick = BD0010.wav
+//   Hi-Hat = CH.wav
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::sequencer::pattern::Track;
+
+pub const KITS_DIR: &str = "kits";
+const MANIFEST: &str = "kit.txt";
+
+// 同じ音を指す呼び名（正規化済み）
+const ALIASES: &[&[&str]] = &[
+    &["kick", "bd", "bassdrum"],
+    &["snare", "sd"],
+    &["hihat", "hat", "hh", "ch", "chh", "closedhat"],
+    &["openhat", "oh", "ohh"],
+    &["clap", "cp", "handclap"],
+    &["rim", "rs", "rimshot"],
+    &["lowtom", "lt"],
+    &["midtom", "mt"],
+    &["hightom", "ht"],
+    &["cowbell", "cb"],
+    &["crash", "cy", "cymbal"],
+    &["ride", "rd"],
+    &["bass"],
+];
+
+pub struct Kit {
+    pub dir: PathBuf,
+    files: Vec<(String, PathBuf)>, // (正規化した名前, パス)。マニフェストの分が先
+}
+
+// 小文字の英数字だけにする（"Hi-Hat" -> "hihat"）
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// 末尾の番号を除く（"kick01" -> "kick"）
+fn strip_number(key: &str) -> &str {
+    key.trim_end_matches(|c: char| c.is_ascii_digit())
+}
+
+fn aliases(key: &str) -> Option<&'static [&'static str]> {
+    ALIASES.iter().copied().find(|group| group.contains(&strip_number(key)))
+}
+
+pub fn load(dir: &Path) -> Result<Kit, Box<dyn Error>> {
+    let mut files = Vec::new();
+
+    let manifest = dir.join(MANIFEST);
+    if manifest.exists() {
+        for (line_no, line) in fs::read_to_string(&manifest)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, file) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{} line {}: expected name = file", MANIFEST, line_no + 1))?;
+            files.push((normalize(name), dir.join(file.trim())));
+        }
+    }
+
+    let mut wavs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+        })
+        .collect();
+    wavs.sort();
+    for path in wavs {
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        files.push((normalize(&stem), path));
+    }
+
+    if files.is_empty() {
+        return Err(format!("no WAV files in {}", dir.display()).into());
+    }
+    Ok(Kit {
+        dir: dir.to_path_buf(),
+        files,
+    })
+}
+
+// kits/ 以下のキットのフォルダ（名前順）
+pub fn list() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(KITS_DIR) else { return Vec::new() };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+impl Kit {
+    pub fn name(&self) -> String {
+        kit_name(&self.dir)
+    }
+
+    // トラックに割り当てる WAV。名前がそのまま一致するものを優先し、
+    // なければ同じ呼び名のグループのファイルを探す
+    pub fn sample_for(&self, track: &Track) -> Option<PathBuf> {
+        let key = normalize(&track.name);
+        if let Some((_, path)) = self.files.iter().find(|(k, _)| *k == key) {
+            return Some(path.clone());
+        }
+        let group = aliases(&key).or_else(|| aliases(&normalize(track.instrument.name())))?;
+        self.files
+            .iter()
+            .find(|(k, _)| group.contains(&strip_number(k)))
+            .map(|(_, path)| path.clone())
+    }
+}
+
+pub fn kit_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| dir.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::pattern::InstrumentType;
+
+    // 空の WAV（中身は読まない）と kit.txt を置いた一時フォルダ
+    fn kit_dir(name: &str, files: &[&str], manifest: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rhythm-box-kit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+        if let Some(manifest) = manifest {
+            fs::write(dir.join(MANIFEST), manifest).unwrap();
+        }
+        dir
+    }
+
+    fn file_for(kit: &Kit, name: &str, instrument: InstrumentType) -> Option<String> {
+        let path = kit.sample_for(&Track::new(name, instrument))?;
+        Some(path.file_name()?.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn tracks_find_files_by_name_and_alias() {
+        let dir = kit_dir("alias", &["BD01.wav", "SD.WAV", "Hi-Hat.wav", "oh.wav", "Perc 2.wav", "notes.txt"], None);
+        let kit = load(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(file_for(&kit, "Kick", InstrumentType::Kick).as_deref(), Some("BD01.wav"));
+        assert_eq!(file_for(&kit, "Snare", InstrumentType::Snare).as_deref(), Some("SD.WAV"));
+        assert_eq!(file_for(&kit, "HH", InstrumentType::HiHat).as_deref(), Some("Hi-Hat.wav"));
+        assert_eq!(file_for(&kit, "Open Hat", InstrumentType::OpenHat).as_deref(), Some("oh.wav"));
+        // 名前がそのまま一致すればそれを使う
+        assert_eq!(file_for(&kit, "perc2", InstrumentType::Rim).as_deref(), Some("Perc 2.wav"));
+        // 名前で見つからなければ楽器の呼び名で探す
+        assert_eq!(file_for(&kit, "Boom", InstrumentType::Kick).as_deref(), Some("BD01.wav"));
+        assert_eq!(file_for(&kit, "Clap", InstrumentType::Clap), None);
+    }
+
+    #[test]
+    fn manifest_overrides_file_names() {
+        let manifest = "# マニフェスト\nKick = sub.wav\n\nClap = snap.wav\n";
+        let dir = kit_dir("manifest", &["kick.wav", "sub.wav", "snap.wav"], Some(manifest));
+        let kit = load(&dir).unwrap();
+        assert_eq!(file_for(&kit, "Kick", InstrumentType::Kick).as_deref(), Some("sub.wav"));
+        assert_eq!(file_for(&kit, "Clap", InstrumentType::Clap).as_deref(), Some("snap.wav"));
+
+        fs::write(dir.join(MANIFEST), "Kick sub.wav\n").unwrap();
+        let broken = load(&dir).err().map(|e| e.to_string());
+        let _ = fs::remove_dir_all(&dir);
+        let dir = kit_dir("empty", &[], None);
+        let empty = load(&dir).err().map(|e| e.to_string());
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(broken.as_deref(), Some("kit.txt line 1: expected name = file"));
+        assert!(empty.unwrap().starts_with("no WAV files"));
+    }
+}
@@ -1,5 +1,5 @@
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -12,202 +12,2158 @@ use ratatui::{
     Frame, Terminal,
 };
 use std::{error::Error, io};
-use std::sync::{Arc, Mutex};
+use std::ops::Range;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use hound::{WavWriter, WavSpec};
 
+mod audio;
+mod kit;
+mod project;
+mod sequencer;
+
+use audio::effects::{self, Chain, ChainUpdate, Context, Key};
+use audio::sampler::{self, Sample, Sampler, Slicer};
+use audio::filter::TrackFilter;
+use audio::voice::{Hit, Instrument, Registry, Voice};
+use project::Project;
+use sequencer::history::{Edit, Field, History, Settings};
+use sequencer::modulation::{
+    is_continuous, Lfo, LfoShape, ModSource, Modulator, Route, LFO_COUNT, MAX_ROUTES,
+};
+use sequencer::pattern::{
+    audible, EffectSlot, EffectType, FilterMode, InstrumentType, Param, Pattern, Step, Track,
+    BANK_COUNT, CHOKE_GROUPS, CHORDS, MAX_EFFECTS, MAX_FILTER_DECAY, MAX_NOTE_LENGTH, MAX_TRACKS,
+    MIN_FILTER_DECAY,
+};
+use sequencer::song::{Section, Song, MAX_BPM, MIN_BPM};
+
 // アプリケーションの状態
 struct App {
-    pattern: Pattern,
+    tracks: Vec<Track>,
+    pattern: Pattern,         // 編集中のバンクのパターン
+    banks: Vec<Pattern>,      // パターンバンク（編集中のスロットは pattern が最新）
+    bank: usize,
     current_step: usize,
     selected_track: usize,
     selected_step: usize,
+    selection_anchor: Option<(usize, usize)>, // 範囲選択の起点 (track, step)
+    clipboard: Option<Vec<Vec<Step>>>,
     playing: bool,
-    bpm: u32,
-    audio_engine: Arc<Mutex<AudioEngine>>,
+    recording: bool,
+    overdub: bool,             // false なら置き換え録音
+    quantize: f32,             // クオンタイズの強さ 0.0〜1.0
+    take_tracks: Vec<(usize, usize)>, // 今回の録音で叩いた (バンク, トラック)（置き換え録音用）
+    clock: Option<Clock>,      // オーディオスレッドから受け取った再生位置
+    settings: Settings,        // BPM、ソング、LFO、センドバスとマスター、余韻（履歴に積む）
+    bpm_input: Option<String>, // BPM を入力中の文字列
+    rename_input: Option<String>, // トラック名を入力中の文字列
+    sample_input: Option<String>, // 読み込む WAV のパスを入力中の文字列
+    taps: Vec<Instant>,        // タップテンポの時刻
+    song_mode: bool,
+    song_cursor: Option<usize>, // ソング編集中なら選択中のセクション
+    playing_section: Option<usize>,
+    playing_bpm: f32,
+    lock_param: Option<usize>, // パラメータロック編集中なら Param::ALL のインデックス
+    sound_param: Option<usize>, // サウンド編集中なら楽器のパラメータのインデックス
+    mod_row: Option<usize>,     // モジュレーション編集中なら選択中の行（LFO の行の後にルート）
+    fx_row: Option<usize>,      // エフェクト編集中なら fx_rows() の行
+    meters: Meters,             // オーディオスレッドから受け取ったエフェクトのメーター
+    mixer: bool,               // ミキサー画面を表示中
+    registry: Registry,        // サウンド編集で楽器のパラメータを調べる
+    samples: HashMap<PathBuf, Arc<Sample>>, // 読み込んだ WAV（パスごと）
+    kit: Option<PathBuf>,                   // いま使っているドラムキットのフォルダ
+    history: History,
+    project_path: PathBuf,
+    audio_commands: Arc<Mutex<Vec<AudioCommand>>>,
+    playback: Arc<Mutex<PlaybackStatus>>,
+    layouts: Layouts, // オーディオスレッドのエフェクトチェーンの控え
+    sent: Sequence,   // 最後にオーディオスレッドへ送った内容（変わっていなければ送らない）
     export_message: Option<String>,
 }
 
-// パターンデータ
-struct Pattern {
-    tracks: Vec<Track>,
-    length: usize,
-}
+// 再生に必要なデータ（UIスレッドで編集し、オーディオスレッドへコピーを送る）
+#[derive(Clone, Default)]
+struct Sequence {
+    tracks: Vec<Track>,
+    samples: Vec<Option<Arc<Sample>>>, // トラックごとの読み込み済みサンプル
+    patterns: Vec<Pattern>, // 全バンク（編集中のバンクも最新）
+    bank: usize,
+    bpm: f32,
+    song: Option<Song>, // ソングモードならセクションの並び
+    lfos: [Lfo; LFO_COUNT],
+    send_bus: Vec<EffectSlot>, // センドバスのエフェクトチェーン
+    master: Vec<EffectSlot>, // マスターのエフェクトチェーン
+}
+
+// エフェクトパネルで編集するチェーン
+#[derive(Clone, Copy, PartialEq)]
+enum FxChain {
+    Track, // 選択中のトラックのインサート
+    Send,
+    Master,
+}
+
+// エフェクトパネルの行
+#[derive(Clone, Copy, PartialEq)]
+enum FxRow {
+    Chain { chain: FxChain }, // 見出し（センドバスでは選択中のトラックの送り量を動かす）
+    Slot { chain: FxChain, slot: usize },
+    Param { chain: FxChain, slot: usize, param: usize },
+    Tail, // エクスポートの余韻
+}
+
+// UIスレッドからオーディオスレッドへの指示
+enum AudioCommand {
+    Play,
+    Stop,
+    Update(Box<Update>),
+    Hit(usize), // パッド入力や試聴（トラック番号）。すぐに鳴らす
+}
+
+// Sequence の差し替え。エフェクトのインスタンスはUIスレッドで作っておき、オーディオスレッドでは入れ替えるだけにする。
+// 入れ替えで外したものはこの箱に入れてUIスレッドへ返し、そちらで捨てる
+struct Update {
+    seq: Sequence,
+    tracks: Vec<ChainUpdate>, // トラックごとのインサート
+    send_bus: ChainUpdate,
+    master: ChainUpdate,
+    removed: Vec<Bus>, // 消したトラックのバス（容量だけ確保しておく）
+}
+
+// オーディオスレッドのエフェクトチェーンの控え（UIスレッドで差し替えを作るため）
+#[derive(Clone)]
+struct Layouts {
+    sample_rate: f32, // 出力デバイスのサンプルレート（エフェクトのバッファの長さに使う）
+    tracks: Vec<effects::Layout>,
+    send_bus: effects::Layout,
+    master: effects::Layout,
+}
+
+impl Layouts {
+    fn new(sample_rate: f32) -> Self {
+        Self { sample_rate, tracks: Vec::new(), send_bus: effects::Layout::default(), master: effects::Layout::default() }
+    }
+
+    fn update(&mut self, seq: Sequence) -> Box<Update> {
+        let removed = Vec::with_capacity(self.tracks.len().saturating_sub(seq.tracks.len()));
+        self.tracks.resize_with(seq.tracks.len(), effects::Layout::default);
+        let tracks = self
+            .tracks
+            .iter_mut()
+            .zip(&seq.tracks)
+            .map(|(layout, track)| layout.update(&track.effects, self.sample_rate))
+            .collect();
+        Box::new(Update {
+            tracks,
+            send_bus: self.send_bus.update(&seq.send_bus, self.sample_rate),
+            master: self.master.update(&seq.master, self.sample_rate),
+            removed,
+            seq,
+        })
+    }
+}
+
+// オーディオスレッドからUIスレッドへ返す差し替えの上限（これより溜まったら差し替えを待たせる）
+const MAX_RETIRED: usize = 64;
+
+// オーディオスレッドからUIスレッドへの通知
+#[derive(Default)]
+struct PlaybackStatus {
+    step: usize,
+    section: Option<usize>, // ソングモードで再生中のセクション
+    bpm: f32,               // テンポランプ中の現在のテンポ
+    clock: Option<Clock>,   // 再生中の位置（パッド入力の録音用）
+    meters: Meters,
+    // 適用済みの差し替え（UIスレッドで捨てる。箱ごと返すので Box のまま持つ）
+    #[allow(clippy::vec_box)]
+    retired: Vec<Box<Update>>,
+}
+
+// ブロックを作り始めたときの再生位置。パッド入力を録音する位置は、押した時刻との差から UIスレッドで求める
+#[derive(Clone, Copy)]
+struct Clock {
+    at: Instant,
+    bank: usize,           // 再生中のバンク
+    position: f32,         // ステップ単位の再生位置
+    steps_per_second: f32,
+}
+
+impl Clock {
+    fn position_at(&self, time: Instant) -> f32 {
+        self.position + time.saturating_duration_since(self.at).as_secs_f32() * self.steps_per_second
+    }
+}
+
+// エフェクトのメーター（チェーンのスロットごと。メーターのないエフェクトは None）
+#[derive(Clone, Default)]
+struct Meters {
+    tracks: Vec<Vec<Option<f32>>>,
+    send_bus: Vec<Option<f32>>,
+    master: Vec<Option<f32>>,
+}
+
+// トラックのステップを鳴らすときの値（ロックを反映したもの）
+#[derive(Clone, Copy)]
+struct Trigger {
+    track: usize,
+    hit: Hit,
+    pan: f32,    // -1.0 (L) 〜 1.0 (R)
+    length: u8,  // 音を伸ばすステップ数
+    chord: u8,   // CHORDS のインデックス
+    slide: bool, // 次のステップへ滑らせる
+    cutoff: Option<f32>, // ステップのロック（なければトラックのフィルターの値に付いていく）
+    resonance: Option<f32>,
+    filter_env: f32,
+    at: Option<(usize, usize)>, // パターン上の位置 (バンク, ステップ)。楽器のパラメータのロックはここから読む
+}
+
+fn step_trigger(idx: usize, track: &Track, step: &Step) -> Trigger {
+    Trigger {
+        track: idx,
+        hit: Hit {
+            note: step.note as f32,
+            gain: track.resolve(step, Param::Volume) * step.velocity as f32 / 127.0,
+            pitch: track.resolve(step, Param::Pitch),
+            decay: track.resolve(step, Param::Decay),
+            gate: 0.0,        // 秒にするにはテンポが要るので鳴らすときに決める
+            glide_from: None, // 同上（直前のノートはプレイヤーが覚えている）
+            accent: step.accent,
+        },
+        pan: track.resolve(step, Param::Pan),
+        length: step.length,
+        chord: step.chord,
+        slide: step.slide,
+        cutoff: step.lock(Param::FilterCutoff),
+        resonance: step.lock(Param::FilterResonance),
+        filter_env: track.resolve(step, Param::FilterEnv),
+        at: None,
+    }
+}
+
+// 再生位置（ステップ単位）を一番近いステップとずれに分ける。
+// クオンタイズの強さ 1.0 でずれをなくし、0.0 で叩いたままのずれを残す
+fn quantize_hit(position: f32, length: usize, strength: f32) -> (usize, f32) {
+    let nearest = position.round();
+    let offset = (position - nearest) * (1.0 - strength);
+    (nearest as usize % length, offset)
+}
+
+// トラックごとのステレオバス。ボイスをパンして集め、フィルターを通してからミックスする
+struct Bus {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    filter: TrackFilter,
+    hits: Vec<(usize, Trigger)>, // このブロックで鳴らした位置（フィルターのエンベロープ用）
+    filter_mod: Vec<(f32, f32)>, // サンプルごとのカットオフ（オクターブ）とレゾナンスのずれ
+    gain_mod: Vec<(f32, f32)>,   // サンプルごとの左右の音量（空ならモジュレーションなし）
+    effects: Chain,              // インサートエフェクト（フィルターの後にかける）
+}
+
+impl Bus {
+    fn new() -> Self {
+        Self {
+            left: Vec::new(),
+            right: Vec::new(),
+            filter: TrackFilter::new(),
+            hits: Vec::new(),
+            filter_mod: Vec::new(),
+            gain_mod: Vec::new(),
+            effects: Chain::default(),
+        }
+    }
+
+    // 発音の位置で区切ってフィルターをかける
+    fn filter(&mut self, track: &Track, sample_rate: f32) {
+        let len = self.left.len();
+        let modulation = |range: Range<usize>| self.filter_mod.get(range).unwrap_or(&[]);
+        let mut from = 0;
+        self.filter.set_target(track.filter_cutoff, track.filter_resonance);
+        for (at, trigger) in std::mem::take(&mut self.hits) {
+            self.filter.process(
+                track.filter_mode,
+                track.filter_decay,
+                sample_rate,
+                &mut self.left[from..at],
+                &mut self.right[from..at],
+                modulation(from..at),
+            );
+            self.filter.hit(trigger.cutoff, trigger.resonance, trigger.filter_env);
+            from = at;
+        }
+        self.filter.process(
+            track.filter_mode,
+            track.filter_decay,
+            sample_rate,
+            &mut self.left[from..],
+            &mut self.right[from..],
+            modulation(from..len),
+        );
+    }
+}
+
+// 発音中のボイス
+struct ActiveVoice {
+    track: usize,
+    pan: f32,
+    start: usize, // 現在のブロック内で鳴り始める位置
+    release_at: Option<usize>, // 止めるならブロック内でフェードアウトを始める位置
+    level: f32,                // フェードアウト中の音量
+    voice: Box<dyn Voice>,
+}
+
+// チョークや発音数の上限で止める音のフェードアウト時間（クリック防止）
+const FADE_TIME: f32 = 0.005;
+
+// エフェクトパネルでエクスポートの余韻を動かす幅（秒）
+const TAIL_STEP: f32 = 0.5;
+
+// ステップの進行と発音を管理する（ライブ再生とエクスポートで共用）
+struct Player {
+    sample_rate: f32,
+    seq: Sequence,
+    registry: Registry,
+    playing: bool,
+    bank: usize,    // 再生中のパターン
+    section: usize, // ソングモードのセクション
+    repeat: usize,  // セクション内の繰り返し回数
+    passes: usize,  // パターン（ソングモードでは曲全体）を最後まで再生した回数
+    step: usize,
+    phase: f32, // ステップ内の位置 0.0〜1.0
+    offset: usize,               // ブロック内でクロックが進んだ位置
+    wrapped_at: Option<usize>,   // このブロックで passes が増えた位置
+    scheduled: Vec<(f32, Trigger)>, // このステップ内で鳴らす予定の音 (phase, ...)
+    voices: Vec<ActiveVoice>,
+    buses: Vec<Bus>,
+    last_notes: Vec<Option<f32>>, // トラックごとに直前に鳴らしたノート
+    sliding: Vec<Option<u8>>,     // トラックごとに、直前のスライドの音があと何ステップ先まで届くか
+    modulator: Modulator,         // LFO と乱数（サンプルごとに進める）
+    send_bus: Chain,              // センドバスのエフェクトチェーン
+    keys: Vec<Key>,               // トラックごとのサイドチェインのキー
+    send_left: Vec<f32>,          // トラックから送った音
+    send_right: Vec<f32>,
+    master: Chain,                // マスターのエフェクトチェーン
+    stop_after: Option<usize>,    // passes がこの回数になったら止める（エクスポート用）
+    scratch: Vec<f32>,
+}
+
+impl Player {
+    // エクスポートやテスト用（エフェクトもこの場で作る）
+    fn new(sample_rate: f32, seq: Sequence) -> Self {
+        Self::start(&mut Layouts::new(sample_rate), seq)
+    }
+
+    // layouts は作ったチェーンの控えになる（ライブ再生ではUIスレッドに渡す）
+    fn start(layouts: &mut Layouts, seq: Sequence) -> Self {
+        let mut player = Self {
+            sample_rate: layouts.sample_rate,
+            bank: seq.bank,
+            seq: Sequence::default(),
+            registry: Registry::new(),
+            playing: false,
+            section: 0,
+            repeat: 0,
+            passes: 0,
+            step: 0,
+            phase: 0.0,
+            offset: 0,
+            wrapped_at: None,
+            scheduled: Vec::new(),
+            voices: Vec::new(),
+            // トラックを足してもオーディオスレッドで伸ばさない
+            buses: Vec::with_capacity(MAX_TRACKS),
+            last_notes: Vec::with_capacity(MAX_TRACKS),
+            sliding: Vec::with_capacity(MAX_TRACKS),
+            modulator: Modulator::new(),
+            send_bus: Chain::default(),
+            keys: Vec::with_capacity(MAX_TRACKS),
+            send_left: Vec::new(),
+            send_right: Vec::new(),
+            master: Chain::default(),
+            stop_after: None,
+            scratch: Vec::new(),
+        };
+        player.update(&mut layouts.update(seq));
+        player
+    }
+
+    fn pattern(&self) -> &Pattern {
+        &self.seq.patterns[self.bank]
+    }
+
+    // ソングモードで、セクションがあるときだけ
+    fn song(&self) -> Option<&Song> {
+        self.seq.song.as_ref().filter(|song| !song.sections.is_empty())
+    }
+
+    // 現在のテンポ（テンポランプ中はセクション内の位置で補間）
+    fn tempo(&self) -> f32 {
+        let Some(song) = self.song() else { return self.seq.bpm };
+        let section = &song.sections[self.section];
+        let length = self.pattern().length as f32;
+        let progress = (self.repeat as f32 * length + self.position())
+            / (section.repeats.max(1) as f32 * length);
+        song.tempo_at(self.section, progress)
+    }
+
+    fn samples_per_step(&self) -> f32 {
+        60.0 / self.tempo() * self.sample_rate / 4.0
+    }
+
+    // 再生位置（ステップ単位）
+    fn position(&self) -> f32 {
+        self.step as f32 + self.phase
+    }
+
+    // UIスレッドで作った差し替えを適用する。外したバスやエフェクトは update に入れて返す
+    fn update(&mut self, update: &mut Update) {
+        // 前の Sequence も update に入れて返す
+        std::mem::swap(&mut self.seq, &mut update.seq);
+        let tracks = self.seq.tracks.len();
+        self.last_notes.resize(tracks, None);
+        self.sliding.resize(tracks, None);
+        if self.buses.len() > tracks {
+            update.removed.extend(self.buses.drain(tracks..));
+        }
+        self.buses.resize_with(tracks, Bus::new);
+        // キーはトラックを消しても捨てない（オーディオスレッドで解放しない）
+        if self.keys.len() < tracks {
+            self.keys.resize_with(tracks, Key::default);
+        }
+        for (bus, chain) in self.buses.iter_mut().zip(&mut update.tracks) {
+            bus.effects.apply(chain);
+        }
+        self.send_bus.apply(&mut update.send_bus);
+        self.master.apply(&mut update.master);
+        // self.song() だと self 全体を借用するのでフィールドから直接
+        match self.seq.song.as_ref().filter(|song| !song.sections.is_empty()) {
+            Some(song) => {
+                if self.section >= song.sections.len() {
+                    self.section = 0;
+                    self.repeat = 0;
+                }
+                self.bank = song.sections[self.section].bank;
+            }
+            None => self.bank = self.seq.bank,
+        }
+        self.step %= self.pattern().length;
+    }
+
+    fn context(&self) -> Context<'_> {
+        Context {
+            sample_rate: self.sample_rate,
+            samples_per_step: self.samples_per_step(),
+            keys: &self.keys[..self.buses.len()],
+        }
+    }
+
+    // エフェクトのメーターを UI へ送る形にまとめる
+    fn read_meters(&self, meters: &mut Meters) {
+        meters.tracks.resize_with(self.buses.len(), Vec::new);
+        for (out, bus) in meters.tracks.iter_mut().zip(&self.buses) {
+            bus.effects.meters(out);
+        }
+        self.send_bus.meters(&mut meters.send_bus);
+        self.master.meters(&mut meters.master);
+    }
+
+    // エフェクトの余韻の長さ（秒）。いちばん長いトラック（送っていればセンドバスの分も）にマスターの分を足す
+    fn tail(&self) -> f32 {
+        let ctx = self.context();
+        let tracks = &self.seq.tracks;
+        let send_tail = self.send_bus.tail(&ctx);
+        let track_tail = (0..tracks.len())
+            .filter(|&idx| audible(tracks, idx))
+            .filter_map(|idx| Some((&tracks[idx], self.buses.get(idx)?)))
+            .map(|(track, bus)| bus.effects.tail(&ctx) + if track.send > 0.0 { send_tail } else { 0.0 })
+            .fold(0.0, f32::max);
+        track_tail + self.master.tail(&ctx)
+    }
+
+    fn play(&mut self) {
+        self.playing = true;
+        self.section = 0;
+        self.repeat = 0;
+        self.passes = 0;
+        self.bank = match self.song() {
+            Some(song) => song.sections[0].bank,
+            None => self.seq.bank,
+        };
+        self.step = 0;
+        self.phase = 0.0;
+        self.scheduled.clear();
+        // LFO と乱数は頭から（エクスポートとライブ再生で同じ揺れにする）
+        self.modulator.reset();
+        self.modulator.next_step(&self.seq.tracks);
+        self.schedule_step();
+
+        // 先頭ステップの前ノリの音は直前のステップがないので頭で鳴らす
+        let pattern = &self.seq.patterns[self.bank];
+        for (idx, (track, steps)) in self.seq.tracks.iter().zip(&pattern.steps).enumerate() {
+            let step = &steps[0];
+            if step.active && step.offset < 0.0 {
+                let trigger = Trigger { at: Some((self.bank, 0)), ..step_trigger(idx, track, step) };
+                self.scheduled.push((0.0, trigger));
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.playing = false;
+        self.scheduled.clear();
+    }
+
+    // 楽器の登録表からボイスを作り、ブロック内の現在位置から鳴らす
+    fn trigger(&mut self, mut trigger: Trigger) {
+        let Some(track) = self.seq.tracks.get(trigger.track) else { return };
+        // サンプルを読み込んだトラックはそのサンプルで鳴らす
+        let (sampler, slicer);
+        let instrument: &dyn Instrument = match self.seq.samples.get(trigger.track) {
+            Some(Some(sample)) if track.instrument == InstrumentType::Sampler => {
+                sampler = Sampler(Some(sample.clone()));
+                &sampler
+            }
+            Some(Some(sample)) if track.instrument == InstrumentType::Slicer => {
+                slicer = Slicer(Some(sample.clone()));
+                &slicer
+            }
+            _ => match self.registry.get(track.instrument) {
+                Some(instrument) => instrument,
+                None => return,
+            },
+        };
+        let mut params = instrument.values(&track.sound);
+        // ステップでロックした楽器のパラメータ
+        let step = trigger.at.and_then(|(bank, pos)| self.seq.patterns.get(bank)?.steps.get(trigger.track)?.get(pos));
+        for &(idx, value) in step.map_or(&[][..], |step| &step.sound_locks) {
+            if let Some(param) = params.get_mut(idx) {
+                *param = value;
+            }
+        }
+        let offset = self.offset;
+
+        // 長さはテンポで秒に直し（スライドは次のステップまで伸ばす）、
+        // グライドは同じトラックの直前のノートから
+        let mut hit = trigger.hit;
+        // 発音の瞬間の値を使うパラメータのモジュレーション
+        let amount = |param| self.modulator.amount(trigger.track, track, param);
+        hit.pitch = Param::Pitch.modulate(hit.pitch, amount(Param::Pitch));
+        hit.decay = Param::Decay.modulate(hit.decay, amount(Param::Decay));
+        trigger.filter_env = Param::FilterEnv.modulate(trigger.filter_env, amount(Param::FilterEnv));
+        let length = trigger.length as f32 + if trigger.slide { 1.0 } else { 0.0 };
+        hit.gate = length * self.samples_per_step() / self.sample_rate;
+        if self.last_notes.len() <= trigger.track {
+            self.last_notes.resize(trigger.track + 1, None);
+            self.sliding.resize(trigger.track + 1, None);
+        }
+        hit.glide_from = self.last_notes[trigger.track].replace(hit.note);
+
+        // 直前のスライドの音がこのステップまで伸びていれば、鳴っている音をそのまま次のノートへ移す
+        let reach = trigger.slide.then_some(trigger.length);
+        if std::mem::replace(&mut self.sliding[trigger.track], reach).is_some() {
+            let held = self
+                .voices
+                .iter_mut()
+                .rev()
+                .find(|v| v.track == trigger.track && v.release_at.is_none());
+            if held.is_some_and(|active| active.voice.slide(hit)) {
+                return;
+            }
+        }
+        // 和音の音数は同時発音数まで（単音の楽器では根音だけ）
+        let (_, intervals) = CHORDS[trigger.chord as usize % CHORDS.len()];
+        let intervals = &intervals[..intervals.len().min(instrument.polyphony())];
+        if self.buses.len() <= trigger.track {
+            self.buses.resize_with(trigger.track + 1, Bus::new);
+        }
+        self.buses[trigger.track].hits.push((offset, trigger));
+
+        let tracks = &self.seq.tracks;
+        let sounding = |v: &&mut ActiveVoice| v.release_at.is_none();
+
+        // チョーク：同じグループの他のトラックの音を止める
+        if track.choke != 0 {
+            for active in self.voices.iter_mut().filter(sounding) {
+                if active.track != trigger.track
+                    && tracks.get(active.track).is_some_and(|t| t.choke == track.choke)
+                {
+                    active.release_at = Some(offset);
+                }
+            }
+        }
+
+        // 同時発音数を超える分は古い音から止める
+        let count = self
+            .voices
+            .iter()
+            .filter(|v| v.release_at.is_none() && v.track == trigger.track)
+            .count();
+        let excess = (count + intervals.len()).saturating_sub(instrument.polyphony());
+        for active in self
+            .voices
+            .iter_mut()
+            .filter(sounding)
+            .filter(|v| v.track == trigger.track)
+            .take(excess)
+        {
+            active.release_at = Some(offset);
+        }
+
+        // 和音はノートごとにボイスを作る
+        for interval in intervals {
+            let hit = Hit { note: (hit.note + *interval as f32).min(127.0), ..hit };
+            self.voices.push(ActiveVoice {
+                track: trigger.track,
+                pan: trigger.pan,
+                start: offset,
+                release_at: None,
+                level: 1.0,
+                voice: instrument.note_on(self.sample_rate, hit, &params),
+            });
+        }
+    }
+
+    // パッド入力：トラックの音をロックなしで鳴らす
+    fn trigger_track(&mut self, track_idx: usize) {
+        let Some(track) = self.seq.tracks.get(track_idx) else { return };
+        let step = Step::new(track.instrument.default_note());
+        self.trigger(step_trigger(track_idx, track, &step));
+    }
+
+    // パターンの終わりに来たら、ソングモードでは次のセクションへ進む
+    fn advance_pattern(&mut self) {
+        let Some(song) = self.song() else {
+            self.passes += 1;
+            self.wrapped_at = Some(self.offset);
+            return;
+        };
+        let sections = &song.sections;
+        let (mut section, mut repeat) = (self.section, self.repeat + 1);
+        if repeat >= sections[section].repeats {
+            repeat = 0;
+            section = (section + 1) % sections.len();
+        }
+        let bank = sections[section].bank;
+        if section == 0 && repeat == 0 {
+            self.passes += 1;
+            self.wrapped_at = Some(self.offset);
+        }
+        self.section = section;
+        self.repeat = repeat;
+        self.bank = bank;
+    }
+
+    // 現在のステップで鳴らす音を予約する。
+    // 負のオフセットを持つ次のステップの音は、このステップの終わりに鳴らす
+    fn schedule_step(&mut self) {
+        // スライドの音はノートの長さの次のステップまで伸びる。届かなくなったら滑らせない
+        for reach in &mut self.sliding {
+            *reach = reach.and_then(|steps| steps.checked_sub(1));
+        }
+        let pattern = &self.seq.patterns[self.bank];
+        let next = (self.step + 1) % pattern.length;
+        for (idx, (track, steps)) in self.seq.tracks.iter().zip(&pattern.steps).enumerate() {
+            let current = &steps[self.step];
+            let upcoming = &steps[next];
+            let due = [
+                (current, self.step, current.offset >= 0.0, current.offset),
+                (upcoming, next, upcoming.offset < 0.0, 1.0 + upcoming.offset),
+            ];
+            for (step, pos, in_this_step, phase) in due {
+                if !step.active || !in_this_step {
+                    continue;
+                }
+                let trigger = Trigger { at: Some((self.bank, pos)), ..step_trigger(idx, track, step) };
+                self.scheduled.push((phase, trigger));
+            }
+        }
+    }
+
+    // 1サンプル分クロックを進め、時刻になった音を鳴らす
+    fn tick(&mut self) {
+        // 予定の時刻になった音を鳴らす
+        let mut i = 0;
+        while i < self.scheduled.len() {
+            if self.scheduled[i].0 <= self.phase {
+                let (_, trigger) = self.scheduled.swap_remove(i);
+                self.trigger(trigger);
+            } else {
+                i += 1;
+            }
+        }
+
+        // テンポが変わってもステップ内の位置を保つよう、位相で進める
+        self.phase += 1.0 / self.samples_per_step();
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.step += 1;
+            if self.step >= self.pattern().length {
+                self.step = 0;
+                self.advance_pattern();
+            }
+            for (_, trigger) in std::mem::take(&mut self.scheduled) {
+                self.trigger(trigger);
+            }
+            self.modulator.next_step(&self.seq.tracks);
+            self.schedule_step();
+        }
+
+        // 決めた回数だけ再生したら止める（鳴っている音と余韻はそのまま残す）
+        if self.stop_after.is_some_and(|passes| self.passes >= passes) {
+            self.stop();
+        }
+    }
+
+    // 鳴っている間も動かすパラメータのモジュレーションを、このサンプルの分だけバスに書く
+    fn modulate_buses(&mut self) {
+        for (idx, (track, bus)) in self.seq.tracks.iter().zip(&mut self.buses).enumerate() {
+            if !track.mods.iter().any(|route| is_continuous(route.target)) {
+                continue;
+            }
+            let amount = |param| self.modulator.amount(idx, track, param);
+            // カットオフは Param::modulate と同じく深さ 1.0 で ±5 オクターブ
+            bus.filter_mod.push((amount(Param::FilterCutoff) * 5.0, amount(Param::FilterResonance)));
+            // 音量は深さ 1.0 で無音〜2倍、パンはバランス型
+            let volume = (1.0 + amount(Param::Volume)).max(0.0);
+            let pan = amount(Param::Pan).clamp(-1.0, 1.0);
+            bus.gain_mod.push((volume * (1.0 - pan).min(1.0), volume * (1.0 + pan).min(1.0)));
+        }
+    }
+
+    // ステレオのブロックを生成する（left と right は同じ長さ）
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let len = left.len();
+        left.fill(0.0);
+        right.fill(0.0);
+
+        self.buses.resize_with(self.seq.tracks.len(), Bus::new);
+        for bus in &mut self.buses {
+            bus.left.clear();
+            bus.left.resize(len, 0.0);
+            bus.right.clear();
+            bus.right.resize(len, 0.0);
+            bus.filter_mod.clear();
+            bus.gain_mod.clear();
+        }
+
+        // 先にクロックと LFO を進めて、ブロック内のどこで鳴り始めるかを決める
+        self.wrapped_at = None;
+        for offset in 0..len {
+            self.offset = offset;
+            let steps = if self.playing { 1.0 / self.samples_per_step() } else { 0.0 };
+            self.modulator.advance(&self.seq.lfos, steps, self.sample_rate);
+            if self.playing {
+                self.tick();
+            }
+            self.modulate_buses();
+        }
+        self.offset = 0;
+
+        // すべてのアクティブな音を生成してトラックのバスに集める
+        self.scratch.resize(len, 0.0);
+        let tracks = &self.seq.tracks;
+        let buses = &mut self.buses;
+        let scratch = &mut self.scratch;
+        let fade_step = 1.0 / (FADE_TIME * self.sample_rate);
+        self.voices.retain_mut(|active| {
+            scratch.fill(0.0);
+            let start = std::mem::take(&mut active.start);
+            let alive = active.voice.render(&mut scratch[start..]);
+
+            // 止める音はフェードアウト（次のブロックでは頭から続ける）
+            if let Some(from) = active.release_at {
+                for s in &mut scratch[from..] {
+                    active.level = (active.level - fade_step).max(0.0);
+                    *s *= active.level;
+                }
+                active.release_at = Some(0);
+            }
+
+            // パンはバランス型：中央で左右とも等倍
+            if let Some(bus) = buses.get_mut(active.track) {
+                let (gain_l, gain_r) = ((1.0 - active.pan).min(1.0), (1.0 + active.pan).min(1.0));
+                for ((l, r), s) in bus.left.iter_mut().zip(bus.right.iter_mut()).zip(scratch.iter()) {
+                    *l += s * gain_l;
+                    *r += s * gain_r;
+                }
+            }
+
+            // 音が終わったら削除
+            alive && active.level > 0.0
+        });
+
+        // ミュート中もフィルターは進める（解除したとき途中から聞こえる）。
+        // フィルターの後の音と発音の位置はサイドチェインのキーとして取っておく
+        for ((track, bus), key) in tracks.iter().zip(&mut self.buses).zip(&mut self.keys) {
+            key.triggers.clear();
+            key.triggers.extend(bus.hits.iter().map(|(at, trigger)| (*at, trigger.hit.gain)));
+            bus.filter(track, self.sample_rate);
+            key.level.clear();
+            key.level.extend(bus.left.iter().zip(&bus.right).map(|(l, r)| l.abs().max(r.abs())));
+        }
+
+        let ctx = Context {
+            sample_rate: self.sample_rate,
+            samples_per_step: self.samples_per_step(),
+            keys: &self.keys[..self.buses.len()],
+        };
+        self.send_left.clear();
+        self.send_left.resize(len, 0.0);
+        self.send_right.clear();
+        self.send_right.resize(len, 0.0);
+        for (idx, (track, bus)) in tracks.iter().zip(&mut self.buses).enumerate() {
+            bus.effects.process(&mut bus.left, &mut bus.right, &ctx);
+            if !audible(tracks, idx) {
+                continue;
+            }
+            let bus_frames = bus.left.iter().zip(&bus.right);
+            let sends = self.send_left.iter_mut().zip(self.send_right.iter_mut());
+            for (i, (((l, r), (bl, br)), (sl, sr))) in
+                left.iter_mut().zip(right.iter_mut()).zip(bus_frames).zip(sends).enumerate()
+            {
+                let (gain_l, gain_r) = bus.gain_mod.get(i).copied().unwrap_or((1.0, 1.0));
+                *l += bl * gain_l;
+                *r += br * gain_r;
+                // センドはフェーダーの後から送る
+                *sl += bl * gain_l * track.send;
+                *sr += br * gain_r * track.send;
+            }
+        }
+
+        // センドバスの戻りを混ぜてからマスターへ
+        self.send_bus.process(&mut self.send_left, &mut self.send_right, &ctx);
+        for ((l, r), (sl, sr)) in left.iter_mut().zip(right.iter_mut()).zip(self.send_left.iter().zip(&self.send_right)) {
+            *l += sl;
+            *r += sr;
+        }
+        self.master.process(left, right, &ctx);
+        for sample in left.iter_mut().chain(right.iter_mut()) {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+impl App {
+    fn new(project_path: PathBuf) -> App {
+        let tracks = vec![
+            Track::new("Kick", InstrumentType::Kick),
+            Track::new("Snare", InstrumentType::Snare),
+            Track::new("Hi-Hat", InstrumentType::HiHat),
+            Track::new("Bass", InstrumentType::Bass),
+        ];
+        let pattern = Pattern::new(&tracks, 16);
+        let banks = vec![pattern.clone(); BANK_COUNT];
+
+        App {
+            tracks,
+            pattern,
+            banks,
+            bank: 0,
+            current_step: 0,
+            selected_track: 0,
+            selected_step: 0,
+            selection_anchor: None,
+            clipboard: None,
+            playing: false,
+            recording: false,
+            overdub: true,
+            quantize: 1.0,
+            take_tracks: Vec::new(),
+            clock: None,
+            settings: Settings {
+                bpm: 120.0,
+                song: Song::default(),
+                lfos: [Lfo::default(); LFO_COUNT],
+                send_bus: vec![effects::default_reverb()],
+                master: Vec::new(),
+                export_tail: None,
+            },
+            bpm_input: None,
+            rename_input: None,
+            sample_input: None,
+            taps: Vec::new(),
+            song_mode: false,
+            song_cursor: None,
+            playing_section: None,
+            playing_bpm: 120.0,
+            lock_param: None,
+            sound_param: None,
+            mod_row: None,
+            fx_row: None,
+            meters: Meters::default(),
+            mixer: false,
+            registry: Registry::new(),
+            samples: HashMap::new(),
+            kit: None,
+            history: History::new(),
+            project_path,
+            audio_commands: Arc::new(Mutex::new(Vec::new())),
+            playback: Arc::new(Mutex::new(PlaybackStatus::default())),
+            layouts: Layouts::new(44100.0),
+            sent: Sequence::default(),
+            export_message: None,
+        }
+    }
+
+    // 選択中のステップを編集して履歴に積む
+    // field があれば同じ対象を続けて動かした分をひとつの操作にまとめる
+    fn edit_step(&mut self, field: Option<Field>, f: impl FnOnce(&mut Step)) {
+        self.edit_step_in(self.bank, self.selected_track, self.selected_step, field, f);
+    }
+
+    // 編集中でないバンクのステップも編集できる（録音で再生中のバンクに書き込む）
+    fn edit_step_in(&mut self, bank: usize, track: usize, step: usize, field: Option<Field>, f: impl FnOnce(&mut Step)) {
+        let pattern = self.bank_mut(bank);
+        let before = pattern.steps[track][step].clone();
+        f(&mut pattern.steps[track][step]);
+        let after = pattern.steps[track][step].clone();
+        self.history.push(Edit::Step { bank, track, step, field, before, after });
+    }
+
+    // バンクのパターン（編集中のバンクなら pattern）
+    fn bank_mut(&mut self, bank: usize) -> &mut Pattern {
+        if bank == self.bank {
+            &mut self.pattern
+        } else {
+            &mut self.banks[bank]
+        }
+    }
+
+    // パターン全体に及ぶ操作。f が false を返したら何もしなかったとみなす
+    fn edit_pattern(&mut self, f: impl FnOnce(&mut Pattern) -> bool) -> bool {
+        self.edit_pattern_in(self.bank, f)
+    }
+
+    fn edit_pattern_in(&mut self, bank: usize, f: impl FnOnce(&mut Pattern) -> bool) -> bool {
+        let pattern = self.bank_mut(bank);
+        let before = pattern.clone();
+        if !f(pattern) {
+            return false;
+        }
+        let after = pattern.clone();
+        self.history.push(Edit::Pattern { bank, before, after });
+        true
+    }
+
+    // 選択中のトラックの設定を編集して履歴に積む
+    fn edit_track(&mut self, field: Option<Field>, f: impl FnOnce(&mut Track)) {
+        let track = self.selected_track;
+        let before = Box::new(self.tracks[track].clone());
+        f(&mut self.tracks[track]);
+        let after = Box::new(self.tracks[track].clone());
+        self.history.push(Edit::Track { track, field, before, after });
+    }
+
+    // BPM やソング、LFO、センドバスとマスターのチェーンなどを編集して履歴に積む
+    fn edit_settings<R>(&mut self, field: Option<Field>, f: impl FnOnce(&mut Settings) -> R) -> R {
+        let before = Box::new(self.settings.clone());
+        let result = f(&mut self.settings);
+        let after = Box::new(self.settings.clone());
+        self.history.push(Edit::Settings { field, before, after });
+        result
+    }
+
+    // トラック一覧を変える操作（全バンクの行も合わせて変える）。
+    // f が false を返したら何もしなかったとみなす
+    fn edit_tracks(&mut self, f: impl FnOnce(&mut Vec<Track>, &mut Vec<Pattern>) -> bool) -> bool {
+        self.banks[self.bank] = self.pattern.clone();
+        let before = (self.tracks.clone(), self.banks.clone());
+        if !f(&mut self.tracks, &mut self.banks) {
+            return false;
+        }
+        let after = (self.tracks.clone(), self.banks.clone());
+        self.pattern = self.banks[self.bank].clone();
+        self.history.push(Edit::Tracks { before, after });
+        self.take_tracks.clear();
+        self.clamp_cursor();
+        true
+    }
+
+    // 既存のトラックと重ならない名前（"Kick", "Kick 2", ...）
+    fn unique_track_name(&self, base: &str) -> String {
+        let taken = |name: &str| self.tracks.iter().any(|t| t.name == name);
+        if !taken(base) {
+            return base.to_string();
+        }
+        (2..)
+            .map(|n| format!("{} {}", base, n))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+
+    // 選択中のトラックの下に新しいトラックを追加する
+    fn add_track(&mut self) {
+        if self.tracks.len() >= MAX_TRACKS {
+            self.export_message = Some(format!("✗ At most {} tracks", MAX_TRACKS));
+            return;
+        }
+        let idx = self.selected_track + 1;
+        let instrument = InstrumentType::Kick;
+        let track = Track::new(&self.unique_track_name(instrument.name()), instrument);
+        self.edit_tracks(|tracks, banks| {
+            for pattern in banks.iter_mut() {
+                let row = pattern.empty_row(&track);
+                pattern.steps.insert(idx, row);
+            }
+            tracks.insert(idx, track);
+            true
+        });
+        self.selected_track = idx;
+    }
+
+    fn duplicate_track(&mut self) {
+        if self.tracks.len() >= MAX_TRACKS {
+            self.export_message = Some(format!("✗ At most {} tracks", MAX_TRACKS));
+            return;
+        }
+        let idx = self.selected_track;
+        let mut track = self.tracks[idx].clone();
+        track.name = self.unique_track_name(&track.name);
+        self.edit_tracks(|tracks, banks| {
+            for pattern in banks.iter_mut() {
+                let row = pattern.steps[idx].clone();
+                pattern.steps.insert(idx + 1, row);
+            }
+            tracks.insert(idx + 1, track);
+            true
+        });
+        self.selected_track = idx + 1;
+    }
+
+    fn remove_track(&mut self) {
+        let idx = self.selected_track;
+        let removed = self.edit_tracks(|tracks, banks| {
+            if tracks.len() <= 1 {
+                return false;
+            }
+            for pattern in banks.iter_mut() {
+                pattern.steps.remove(idx);
+            }
+            tracks.remove(idx);
+            true
+        });
+        if !removed {
+            self.export_message = Some("✗ Cannot remove the last track".to_string());
+        }
+    }
+
+    // 選択中のトラックを上下に移動する
+    fn move_track(&mut self, dir: i32) {
+        let idx = self.selected_track;
+        let Some(other) = idx.checked_add_signed(dir as isize).filter(|&i| i < self.tracks.len()) else {
+            return;
+        };
+        self.edit_tracks(|tracks, banks| {
+            for pattern in banks.iter_mut() {
+                pattern.steps.swap(idx, other);
+            }
+            tracks.swap(idx, other);
+            true
+        });
+        self.selected_track = other;
+    }
+
+    // 楽器を切り替える。既定の音程のままのステップは新しい楽器の既定値にする
+    fn cycle_instrument(&mut self, dir: i32) {
+        let old = self.tracks[self.selected_track].instrument;
+        let pos = InstrumentType::ALL.iter().position(|&i| i == old).unwrap_or(0);
+        let new = InstrumentType::ALL
+            [(pos as i32 + dir).rem_euclid(InstrumentType::ALL.len() as i32) as usize];
+        self.set_instrument(new, None, new.name());
+    }
+
+    // 選択中のトラックの楽器（とサンプル）を差し替える
+    fn set_instrument(&mut self, new: InstrumentType, sample: Option<PathBuf>, name: &str) {
+        let idx = self.selected_track;
+        let track = &self.tracks[idx];
+        let old = track.instrument;
+        // 楽器名やサンプル名のままのトラック名は新しい名前に合わせる
+        let rename = track.name.starts_with(old.name())
+            || track.sample.as_deref().is_some_and(|p| sample_name(p) == track.name);
+        let name = self.unique_track_name(name);
+        self.edit_tracks(|tracks, banks| {
+            for pattern in banks.iter_mut() {
+                for step in &mut pattern.steps[idx] {
+                    if step.note == old.default_note() {
+                        step.note = new.default_note();
+                    }
+                    // 楽器のパラメータは楽器ごとに違う
+                    step.sound_locks.clear();
+                }
+            }
+            tracks[idx].instrument = new;
+            tracks[idx].sound.clear();
+            tracks[idx].choke = new.default_choke();
+            tracks[idx].sample = sample;
+            if rename {
+                tracks[idx].name = name;
+            }
+            true
+        });
+    }
+
+    // WAV を読み込んでおく（同じファイルは一度だけ）
+    fn cache_sample(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if !self.samples.contains_key(path) {
+            let sample = sampler::load(path)?;
+            self.samples.insert(path.to_path_buf(), Arc::new(sample));
+        }
+        Ok(())
+    }
+
+    // 入力したパスの WAV を選択中のトラックに読み込み、サンプラーにする（スライサーならそのまま）。
+    // フォルダならドラムキットとして全トラックに割り当てる
+    fn confirm_sample_input(&mut self) {
+        let Some(input) = self.sample_input.take() else { return };
+        let path = PathBuf::from(input.trim());
+        if path.is_dir() {
+            self.apply_kit(path);
+            return;
+        }
+        match self.cache_sample(&path) {
+            Ok(()) => {
+                let name = sample_name(&path);
+                let instrument = match self.tracks[self.selected_track].instrument {
+                    InstrumentType::Slicer => InstrumentType::Slicer,
+                    _ => InstrumentType::Sampler,
+                };
+                self.set_instrument(instrument, Some(path.clone()), &name);
+                self.export_message = Some(format!("✓ Loaded sample {}", path.display()));
+            }
+            Err(e) => self.export_message = Some(format!("✗ Cannot load {}: {}", path.display(), e)),
+        }
+    }
+
+    // キットの WAV を名前の合うトラックに割り当てる（パターンはそのまま）。
+    // 合うものがないトラックは今の音を残す
+    fn apply_kit(&mut self, dir: PathBuf) {
+        let kit = match kit::load(&dir) {
+            Ok(kit) => kit,
+            Err(e) => {
+                self.export_message = Some(format!("✗ Cannot load kit {}: {}", dir.display(), e));
+                return;
+            }
+        };
+        let mut assigned: Vec<Option<PathBuf>> = Vec::new();
+        for track in self.tracks.clone() {
+            let path = kit.sample_for(&track);
+            if let Some(path) = &path {
+                if let Err(e) = self.cache_sample(path) {
+                    self.export_message = Some(format!("✗ Cannot load {}: {}", path.display(), e));
+                    return;
+                }
+            }
+            assigned.push(path);
+        }
+        let count = assigned.iter().flatten().count();
+        let new = InstrumentType::Sampler;
+        self.edit_tracks(|tracks, banks| {
+            for (idx, path) in assigned.into_iter().enumerate() {
+                let Some(path) = path else { continue };
+                let old = tracks[idx].instrument;
+                for pattern in banks.iter_mut() {
+                    for step in &mut pattern.steps[idx] {
+                        if step.note == old.default_note() {
+                            step.note = new.default_note();
+                        }
+                        if old != new {
+                            step.sound_locks.clear();
+                        }
+                    }
+                }
+                let track = &mut tracks[idx];
+                if track.instrument != new || track.sample.as_ref() != Some(&path) {
+                    track.sound.clear();
+                }
+                track.instrument = new;
+                track.sample = Some(path);
+            }
+            count > 0
+        });
+        self.export_message = Some(format!(
+            "✓ Kit {}: {} of {} tracks",
+            kit.name(),
+            count,
+            self.tracks.len()
+        ));
+        self.kit = Some(dir);
+    }
+
+    // kits/ 以下のキットを順に切り替える
+    fn cycle_kit(&mut self) {
+        let kits = kit::list();
+        if kits.is_empty() {
+            self.export_message = Some(format!("No kits in {}/", kit::KITS_DIR));
+            return;
+        }
+        let next = self
+            .kit
+            .as_ref()
+            .and_then(|current| kits.iter().position(|k| k == current))
+            .map_or(0, |i| (i + 1) % kits.len());
+        self.apply_kit(kits[next].clone());
+    }
+
+    // スライサーのトラックなら、読み込んだループと音色の値
+    fn slicer(&self, idx: usize) -> Option<(&Sample, Vec<f32>)> {
+        let track = &self.tracks[idx];
+        if track.instrument != InstrumentType::Slicer {
+            return None;
+        }
+        let sample = self.samples.get(track.sample.as_ref()?)?;
+        Some((sample, Slicer(None).values(&track.sound)))
+    }
+
+    // スライスを順にステップへ並べる（選択中のトラックの行を置き換える）
+    fn assign_slices(&mut self) {
+        let idx = self.selected_track;
+        let Some((sample, values)) = self.slicer(idx) else {
+            self.export_message = Some("Load a loop into a Slicer track first (W)".to_string());
+            return;
+        };
+        let count = Slicer::points(sample, &values).len();
+        let note = InstrumentType::Slicer.default_note();
+        let length = self.pattern.length;
+        self.edit_pattern(|pattern| {
+            for step in &mut pattern.steps[idx] {
+                *step = Step::new(note);
+            }
+            for slice in 0..count.min(length) {
+                let step = &mut pattern.steps[idx][slice * length / count.min(length)];
+                step.active = true;
+                step.note = note + slice as u8;
+            }
+            true
+        });
+        self.export_message = Some(format!("✓ {} slices laid across {} steps", count, length));
+    }
+
+    // ループの元のテンポに合わせる
+    fn match_loop_tempo(&mut self) {
+        let Some((sample, values)) = self.slicer(self.selected_track) else { return };
+        let bpm = sampler::loop_bpm(sample, values[2]);
+        self.set_bpm(bpm);
+        self.export_message = Some(format!("✓ Tempo set to the loop's {:.1} BPM", self.settings.bpm));
+    }
+
+    // プロジェクトのトラックが使う WAV をすべて読み込む。失敗したファイルを返す
+    fn cache_track_samples(&mut self) -> Vec<String> {
+        let paths: Vec<PathBuf> = self.tracks.iter().filter_map(|t| t.sample.clone()).collect();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                self.cache_sample(&path)
+                    .err()
+                    .map(|e| format!("{}: {}", path.display(), e))
+            })
+            .collect()
+    }
+
+    fn confirm_rename(&mut self) {
+        let Some(input) = self.rename_input.take() else { return };
+        let name = input.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        self.edit_track(None, |track| track.name = name);
+    }
+
+    fn toggle_step(&mut self) {
+        self.edit_step(None, |step| step.active = !step.active);
+    }
+
+    fn undo(&mut self) {
+        let Some(edit) = self.history.undo() else {
+            self.export_message = Some("Nothing to undo".to_string());
+            return;
+        };
+        if let Some(bank) = edit.bank() {
+            self.switch_bank(bank);
+        }
+        self.banks[self.bank] = self.pattern.clone();
+        edit.revert(&mut self.tracks, &mut self.banks, &mut self.settings);
+        self.pattern = self.banks[self.bank].clone();
+        self.clamp_cursor();
+    }
+
+    fn redo(&mut self) {
+        let Some(edit) = self.history.redo() else {
+            self.export_message = Some("Nothing to redo".to_string());
+            return;
+        };
+        if let Some(bank) = edit.bank() {
+            self.switch_bank(bank);
+        }
+        self.banks[self.bank] = self.pattern.clone();
+        edit.apply(&mut self.tracks, &mut self.banks, &mut self.settings);
+        self.pattern = self.banks[self.bank].clone();
+        self.clamp_cursor();
+    }
+
+    fn clamp_cursor(&mut self) {
+        self.selected_track = self.selected_track.min(self.tracks.len() - 1);
+        self.selected_step = self.selected_step.min(self.pattern.length - 1);
+        self.selection_anchor = None;
+        let sections = self.settings.song.sections.len();
+        self.song_cursor = self.song_cursor.map(|idx| idx.min(sections.saturating_sub(1)));
+    }
+
+    // バンク切り替え（履歴には残さない）
+    fn switch_bank(&mut self, bank: usize) {
+        if bank == self.bank {
+            return;
+        }
+        self.banks[self.bank] = std::mem::replace(&mut self.pattern, self.banks[bank].clone());
+        self.bank = bank;
+        self.clamp_cursor();
+    }
+
+    fn step_bank(&mut self, dir: i32) {
+        let bank = (self.bank as i32 + dir).rem_euclid(BANK_COUNT as i32) as usize;
+        self.switch_bank(bank);
+    }
+
+    // 現在のパターンを隣のバンクへコピーして移動する
+    fn copy_to_bank(&mut self, dir: i32) {
+        let from = self.bank;
+        let pattern = self.pattern.clone();
+        self.step_bank(dir);
+        self.edit_pattern(|p| {
+            *p = pattern;
+            true
+        });
+        self.export_message = Some(format!(
+            "Copied bank {} to bank {}",
+            bank_name(from),
+            bank_name(self.bank)
+        ));
+    }
+
+    // 選択範囲 (tracks, steps)。選択がなければカーソルの1マス
+    fn selection(&self) -> (Range<usize>, Range<usize>) {
+        let (t0, s0) = self
+            .selection_anchor
+            .unwrap_or((self.selected_track, self.selected_step));
+        (
+            t0.min(self.selected_track)..t0.max(self.selected_track) + 1,
+            s0.min(self.selected_step)..s0.max(self.selected_step) + 1,
+        )
+    }
+
+    // 回転・反転などの対象。選択がなければ選択中のトラック全体
+    fn transform_target(&self) -> (Range<usize>, Range<usize>) {
+        if self.selection_anchor.is_some() {
+            self.selection()
+        } else {
+            (self.selected_track..self.selected_track + 1, 0..self.pattern.length)
+        }
+    }
+
+    fn extend_selection(&mut self, dx: i32, dy: i32) {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some((self.selected_track, self.selected_step));
+        }
+        let anchor = self.selection_anchor;
+        self.move_cursor(dx, dy);
+        self.selection_anchor = anchor;
+    }
 
-struct Track {
-    name: String,
-    steps: Vec<bool>,
-}
+    fn copy_selection(&mut self) {
+        let (tracks, steps) = self.selection();
+        self.export_message = Some(format!(
+            "Copied {} track(s) x {} step(s)",
+            tracks.len(),
+            steps.len()
+        ));
+        self.clipboard = Some(self.pattern.copy_block(tracks, steps));
+    }
 
-// オーディオエンジン
-struct AudioEngine {
-    sample_rate: f32,
-    time: f32,
-}
+    fn cut_selection(&mut self) {
+        self.copy_selection();
+        let (tracks, steps) = self.selection();
+        self.edit_pattern(|p| {
+            p.clear_block(tracks, steps);
+            true
+        });
+    }
 
-impl AudioEngine {
-    fn new(sample_rate: f32) -> Self {
-        Self {
-            sample_rate,
-            time: 0.0,
+    fn paste(&mut self) {
+        let Some(block) = self.clipboard.clone() else {
+            self.export_message = Some("Clipboard is empty".to_string());
+            return;
+        };
+        let (track, step) = (self.selected_track, self.selected_step);
+        self.edit_pattern(|p| {
+            p.paste_block(track, step, &block);
+            true
+        });
+    }
+
+    fn rotate(&mut self, dir: i32) {
+        let (tracks, steps) = self.transform_target();
+        self.edit_pattern(|p| {
+            tracks.for_each(|t| p.rotate(t, steps.clone(), dir));
+            true
+        });
+    }
+
+    fn reverse(&mut self) {
+        let (tracks, steps) = self.transform_target();
+        self.edit_pattern(|p| {
+            tracks.for_each(|t| p.reverse(t, steps.clone()));
+            true
+        });
+    }
+
+    fn invert(&mut self) {
+        let (tracks, steps) = self.transform_target();
+        self.edit_pattern(|p| {
+            tracks.for_each(|t| p.invert(t, steps.clone()));
+            true
+        });
+    }
+
+    fn double_length(&mut self) {
+        if !self.edit_pattern(Pattern::double) {
+            self.export_message = Some(format!("Pattern is already {} steps", self.pattern.length));
         }
     }
 
-    // キックドラム生成（低周波数のサイン波 + ピッチダウン）
-    fn generate_kick(&mut self) -> f32 {
-        let duration = 0.3;
-        let freq_start = 150.0;
-        let freq_end = 40.0;
-        
-        if self.time < duration {
-            let t = self.time / duration;
-            let freq = freq_start + (freq_end - freq_start) * t;
-            let phase = 2.0 * std::f32::consts::PI * freq * self.time;
-            let envelope = (1.0 - t).powf(2.0);
-            
-            self.time += 1.0 / self.sample_rate;
-            (phase.sin() * envelope * 0.5).clamp(-1.0, 1.0)
-        } else {
-            self.time = 0.0;
-            0.0
+    fn halve_length(&mut self) {
+        if self.edit_pattern(Pattern::halve) {
+            self.clamp_cursor();
         }
     }
 
-    // スネア（ノイズ + サイン波）
-    fn generate_snare(&mut self) -> f32 {
-        let duration = 0.15;
-        
-        if self.time < duration {
-            let t = self.time / duration;
-            let envelope = (1.0 - t).powf(1.5);
-            
-            // ノイズ成分
-            let noise = (rand_xorshift() % 1000) as f32 / 1000.0 - 0.5;
-            // トーン成分
-            let tone = (2.0 * std::f32::consts::PI * 180.0 * self.time).sin();
-            
-            self.time += 1.0 / self.sample_rate;
-            ((noise * 0.7 + tone * 0.3) * envelope * 0.4).clamp(-1.0, 1.0)
-        } else {
-            self.time = 0.0;
-            0.0
+    fn save_project(&mut self) {
+        let mut patterns = self.banks.clone();
+        patterns[self.bank] = self.pattern.clone();
+        let project = Project {
+            bpm: self.settings.bpm,
+            tracks: self.tracks.clone(),
+            patterns,
+            bank: self.bank,
+            song: self.settings.song.clone(),
+            kit: self.kit.clone(),
+            lfos: self.settings.lfos,
+            send_bus: self.settings.send_bus.clone(),
+            master: self.settings.master.clone(),
+            export_tail: self.settings.export_tail,
+        };
+        match project::save(&self.project_path, &project) {
+            Ok(()) => {
+                self.history.mark_saved();
+                self.export_message = Some(format!("✓ Saved to {}", self.project_path.display()));
+            }
+            Err(e) => self.export_message = Some(format!("✗ Save failed: {}", e)),
         }
     }
 
-    // ハイハット（高周波ノイズ）
-    fn generate_hihat(&mut self) -> f32 {
-        let duration = 0.05;
-        
-        if self.time < duration {
-            let t = self.time / duration;
-            let envelope = (1.0 - t).powf(3.0);
-            let noise = (rand_xorshift() % 1000) as f32 / 1000.0 - 0.5;
-            
-            self.time += 1.0 / self.sample_rate;
-            (noise * envelope * 0.2).clamp(-1.0, 1.0)
-        } else {
-            self.time = 0.0;
-            0.0
+    fn load_project(&mut self) {
+        match project::load(&self.project_path) {
+            Ok(project) => {
+                self.settings = Settings {
+                    bpm: project.bpm,
+                    song: project.song,
+                    lfos: project.lfos,
+                    send_bus: project.send_bus,
+                    master: project.master,
+                    export_tail: project.export_tail,
+                };
+                self.kit = project.kit;
+                self.song_cursor = None;
+                self.tracks = project.tracks;
+                self.banks = project.patterns;
+                self.bank = project.bank;
+                self.pattern = self.banks[self.bank].clone();
+                // 履歴はセッションの間だけ（読み込んだ時点から始める）
+                self.history = History::new();
+                self.take_tracks.clear();
+                self.current_step = 0;
+                self.selected_track = 0;
+                self.selected_step = 0;
+                self.selection_anchor = None;
+                let missing = self.cache_track_samples();
+                self.export_message = Some(match missing.first() {
+                    None => format!("✓ Loaded {}", self.project_path.display()),
+                    Some(first) => format!("✗ Loaded with missing samples ({})", first),
+                });
+            }
+            Err(e) => self.export_message = Some(format!("✗ Load failed: {}", e)),
         }
     }
 
-    // ベース（低音サイン波）
-    fn generate_bass(&mut self, note: f32) -> f32 {
-        let duration = 0.2;
-        
-        if self.time < duration {
-            let t = self.time / duration;
-            let freq = 440.0 * 2.0_f32.powf((note - 69.0) / 12.0);
-            let phase = 2.0 * std::f32::consts::PI * freq * self.time;
-            let envelope = (1.0 - t).powf(0.5);
-            
-            self.time += 1.0 / self.sample_rate;
-            (phase.sin() * envelope * 0.3).clamp(-1.0, 1.0)
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        self.selection_anchor = None;
+        self.selected_step = ((self.selected_step as i32 + dx)
+            .rem_euclid(self.pattern.length as i32)) as usize;
+        self.selected_track = ((self.selected_track as i32 + dy)
+            .rem_euclid(self.tracks.len() as i32)) as usize;
+    }
+
+    fn toggle_lock_edit(&mut self) {
+        self.lock_param = match self.lock_param {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    fn move_lock_cursor(&mut self, dy: i32) {
+        if let Some(idx) = self.lock_param {
+            // ロックできるパラメータの後にノート・長さ・和音・アクセント・スライドの行、
+            // その後に楽器の合成パラメータの行がある
+            let rows = (LOCK_SOUND_ROW + self.sound_param_count()) as i32;
+            self.lock_param = Some((idx as i32 + dy).rem_euclid(rows) as usize);
+        }
+    }
+
+    // 選択中のステップのロック値を動かす（ロックがなければトラックの値から作る）
+    fn nudge_lock(&mut self, dir: i32) {
+        let Some(idx) = self.lock_param else { return };
+        if idx >= LOCK_SOUND_ROW {
+            let sound = idx - LOCK_SOUND_ROW;
+            let track = &self.tracks[self.selected_track];
+            let Some(instrument) = self.registry.get(track.instrument) else { return };
+            let Some(spec) = instrument.params().get(sound) else { return };
+            let step = &self.pattern.steps[self.selected_track][self.selected_step];
+            let current = step.sound_lock(sound).unwrap_or(instrument.values(&track.sound)[sound]);
+            let value = spec.nudge(current, dir);
+            self.edit_step(Some(Field::Row("lock", idx)), |step| step.set_sound_lock(sound, value));
+            return;
+        }
+        let Some(&param) = Param::ALL.get(idx) else {
+            let row = idx - Param::ALL.len();
+            self.edit_step(Some(Field::Row("lock", idx)), |step| match row {
+                0 => step.note = (step.note as i32 + dir).clamp(0, 127) as u8,
+                1 => step.length = (step.length as i32 + dir).clamp(1, MAX_NOTE_LENGTH as i32) as u8,
+                2 => step.chord = (step.chord as i32 + dir).rem_euclid(CHORDS.len() as i32) as u8,
+                3 => step.accent = !step.accent,
+                _ => step.slide = !step.slide,
+            });
+            return;
+        };
+        let track = &self.tracks[self.selected_track];
+        let step = &self.pattern.steps[self.selected_track][self.selected_step];
+        let value = param.nudge(track.resolve(step, param), dir);
+        self.edit_step(Some(Field::Param(param)), |step| step.set_lock(param, value));
+    }
+
+    fn clear_lock(&mut self) {
+        let Some(idx) = self.lock_param else { return };
+        match Param::ALL.get(idx) {
+            Some(&param) => self.edit_step(None, |step| step.clear_lock(param)),
+            None if idx >= LOCK_SOUND_ROW => {
+                self.edit_step(None, |step| step.clear_sound_lock(idx - LOCK_SOUND_ROW))
+            }
+            None => {
+                let note = self.tracks[self.selected_track].instrument.default_note();
+                let row = idx - Param::ALL.len();
+                self.edit_step(None, |step| match row {
+                    0 => step.note = note,
+                    1 => step.length = 1,
+                    2 => step.chord = 0,
+                    3 => step.accent = false,
+                    _ => step.slide = false,
+                });
+            }
+        }
+    }
+
+    fn toggle_mixer(&mut self) {
+        self.mixer = !self.mixer;
+        self.lock_param = None;
+        self.sound_param = None;
+        self.mod_row = None;
+        self.fx_row = None;
+        self.song_cursor = None;
+    }
+
+    fn toggle_sound_edit(&mut self) {
+        self.sound_param = match self.sound_param {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    // 選択中のトラックの楽器の合成パラメータの数（音色パネルではこの後にフィルターの行が続く）
+    fn sound_param_count(&self) -> usize {
+        let track = &self.tracks[self.selected_track];
+        self.registry.get(track.instrument).map_or(0, |i| i.params().len())
+    }
+
+    fn move_sound_cursor(&mut self, dy: i32) {
+        let count = (self.sound_param_count() + FILTER_ROWS.len()) as i32;
+        if let Some(idx) = self.sound_param {
+            self.sound_param = Some((idx as i32 + dy).rem_euclid(count) as usize);
+        }
+    }
+
+    // 選択中のトラックの合成パラメータを動かし、すぐに鳴らして確かめる
+    fn nudge_sound(&mut self, dir: i32) {
+        let Some(idx) = self.sound_param else { return };
+        let count = self.sound_param_count();
+        if idx >= count {
+            self.nudge_filter(idx - count, dir);
+            self.audition();
+            return;
+        }
+        let track = &self.tracks[self.selected_track];
+        let Some(instrument) = self.registry.get(track.instrument) else { return };
+        let Some(spec) = instrument.params().get(idx) else { return };
+        let mut values = instrument.values(&track.sound);
+        values[idx] = spec.nudge(values[idx], dir);
+        self.edit_track(Some(Field::Row("sound", idx)), |track| track.sound = values);
+        self.audition();
+    }
+
+    // FILTER_ROWS の行の値を動かす
+    fn nudge_filter(&mut self, row: usize, dir: i32) {
+        let nudge = |track: &mut Track, param: Param| {
+            track.set_param(param, param.nudge(track.param(param), dir))
+        };
+        self.edit_track(Some(Field::Row("filter", row)), |track| match row {
+            0 => {
+                let modes = FilterMode::ALL;
+                let pos = modes.iter().position(|&m| m == track.filter_mode).unwrap_or(0);
+                track.filter_mode = modes[(pos as i32 + dir).rem_euclid(modes.len() as i32) as usize];
+            }
+            1 => nudge(track, Param::FilterCutoff),
+            2 => nudge(track, Param::FilterResonance),
+            3 => nudge(track, Param::FilterEnv),
+            _ => {
+                let decay = track.filter_decay * 2.0_f32.powf(dir as f32 / 4.0);
+                track.filter_decay = decay.clamp(MIN_FILTER_DECAY, MAX_FILTER_DECAY);
+            }
+        });
+    }
+
+    // 既定値に戻す（フィルターの行ならフィルターの設定を）
+    fn reset_sound(&mut self) {
+        if self.sound_param.is_some_and(|idx| idx >= self.sound_param_count()) {
+            self.edit_track(None, |track| {
+                let default = Track::new(&track.name, track.instrument);
+                track.filter_mode = default.filter_mode;
+                track.filter_cutoff = default.filter_cutoff;
+                track.filter_resonance = default.filter_resonance;
+                track.filter_env = default.filter_env;
+                track.filter_decay = default.filter_decay;
+            });
         } else {
-            self.time = 0.0;
-            0.0
+            self.edit_track(None, |track| track.sound.clear());
         }
+        self.audition();
     }
 
-    fn reset(&mut self) {
-        self.time = 0.0;
+    // 楽器に用意された音色を順に読み込む（今の値がどれかと同じなら、その次を）
+    fn next_preset(&mut self) {
+        let track = &self.tracks[self.selected_track];
+        let Some(instrument) = self.registry.get(track.instrument) else { return };
+        let presets = instrument.presets();
+        if presets.is_empty() {
+            self.export_message = Some(format!("{} has no presets", track.instrument.name()));
+            return;
+        }
+        let values = instrument.values(&track.sound);
+        let next = presets
+            .iter()
+            .position(|(_, preset)| *preset == values.as_slice())
+            .map_or(0, |i| (i + 1) % presets.len());
+        let (name, preset) = presets[next];
+        self.edit_track(None, |track| track.sound = preset.to_vec());
+        self.export_message = Some(format!("✓ Preset {}", name));
+        self.audition();
     }
-}
 
-// シンプルな乱数生成器（std::randomを避けるため）
-static mut XORSHIFT_STATE: u32 = 123456789;
-fn rand_xorshift() -> u32 {
-    unsafe {
-        XORSHIFT_STATE ^= XORSHIFT_STATE << 13;
-        XORSHIFT_STATE ^= XORSHIFT_STATE >> 17;
-        XORSHIFT_STATE ^= XORSHIFT_STATE << 5;
-        XORSHIFT_STATE
+    fn toggle_mod_edit(&mut self) {
+        self.mod_row = match self.mod_row {
+            Some(_) => None,
+            None => Some(0),
+        };
     }
-}
 
-// トリガーされた音声
-#[derive(Clone, Copy)]
-enum SoundTrigger {
-    Kick,
-    Snare,
-    HiHat,
-    Bass(f32), // MIDI note
-}
+    // モジュレーションパネルの行数（LFO ごとに Shape・Sync・Rate、その後に選択中のトラックのルート）
+    fn mod_rows(&self) -> usize {
+        LFO_COUNT * 3 + self.tracks[self.selected_track].mods.len()
+    }
 
-impl App {
-    fn new() -> App {
-        let tracks = vec![
-            Track {
-                name: "Kick".to_string(),
-                steps: vec![false; 16],
-            },
-            Track {
-                name: "Snare".to_string(),
-                steps: vec![false; 16],
-            },
-            Track {
-                name: "Hi-Hat".to_string(),
-                steps: vec![false; 16],
-            },
-            Track {
-                name: "Bass".to_string(),
-                steps: vec![false; 16],
-            },
-        ];
+    fn move_mod_cursor(&mut self, dy: i32) {
+        let rows = self.mod_rows() as i32;
+        if let Some(row) = self.mod_row {
+            self.mod_row = Some((row as i32 + dy).rem_euclid(rows) as usize);
+        }
+    }
 
-        App {
-            pattern: Pattern { tracks, length: 16 },
-            current_step: 0,
-            selected_track: 0,
-            selected_step: 0,
-            playing: false,
-            bpm: 120,
-            audio_engine: Arc::new(Mutex::new(AudioEngine::new(44100.0))),
-            export_message: None,
+    // 選択中のルートのインデックス（LFO の行なら None）
+    fn selected_route(&self) -> Option<usize> {
+        self.mod_row?.checked_sub(LFO_COUNT * 3)
+    }
+
+    // LFO の行なら値を、ルートの行なら深さを動かす
+    fn nudge_mod(&mut self, dir: i32) {
+        let Some(row) = self.mod_row else { return };
+        if let Some(route) = self.selected_route() {
+            self.edit_track(Some(Field::Row("mod", row)), |track| {
+                // 0.05 刻みに丸めて誤差をためない
+                let depth = track.mods[route].depth + 0.05 * dir as f32;
+                track.mods[route].depth = ((depth * 100.0).round() / 100.0).clamp(-1.0, 1.0);
+            });
+            return;
         }
+        self.edit_settings(Some(Field::Row("mod", row)), |settings| {
+            let lfo = &mut settings.lfos[row / 3];
+            match row % 3 {
+                0 => {
+                    let shapes = LfoShape::ALL;
+                    let pos = shapes.iter().position(|&s| s == lfo.shape).unwrap_or(0);
+                    lfo.shape = shapes[(pos as i32 + dir).rem_euclid(shapes.len() as i32) as usize];
+                }
+                1 => lfo.sync = !lfo.sync,
+                _ => lfo.nudge_rate(dir),
+            }
+        });
     }
 
-    fn toggle_step(&mut self) {
-        let step = &mut self.pattern.tracks[self.selected_track].steps[self.selected_step];
-        *step = !*step;
+    // 選択中のトラックにルートを足す
+    fn add_route(&mut self) {
+        if self.tracks[self.selected_track].mods.len() >= MAX_ROUTES {
+            self.export_message = Some(format!("✗ At most {} routes per track", MAX_ROUTES));
+            return;
+        }
+        self.edit_track(None, |track| track.mods.push(Route::new()));
+        self.mod_row = Some(self.mod_rows() - 1);
     }
 
-    fn move_cursor(&mut self, dx: i32, dy: i32) {
-        self.selected_step = ((self.selected_step as i32 + dx)
-            .rem_euclid(self.pattern.length as i32)) as usize;
-        self.selected_track = ((self.selected_track as i32 + dy)
-            .rem_euclid(self.pattern.tracks.len() as i32)) as usize;
+    // ルートの送り元か送り先を次のものにする
+    fn cycle_route(&mut self, source: bool) {
+        let Some(route) = self.selected_route() else { return };
+        self.edit_track(None, |track| {
+            let route = &mut track.mods[route];
+            if source {
+                let sources = ModSource::ALL;
+                let pos = sources.iter().position(|&s| s == route.source).unwrap_or(0);
+                route.source = sources[(pos + 1) % sources.len()];
+            } else {
+                let params = Param::ALL;
+                let pos = params.iter().position(|&p| p == route.target).unwrap_or(0);
+                route.target = params[(pos + 1) % params.len()];
+            }
+        });
+    }
+
+    // ルートの行なら削除し、LFO の行なら LFO を既定値に戻す
+    fn remove_mod_row(&mut self) {
+        let Some(row) = self.mod_row else { return };
+        match self.selected_route() {
+            Some(route) => {
+                self.edit_track(None, |track| {
+                    track.mods.remove(route);
+                });
+                self.mod_row = Some(row.min(self.mod_rows() - 1));
+            }
+            None => self.edit_settings(None, |settings| settings.lfos[row / 3] = Lfo::default()),
+        }
+    }
+
+    fn toggle_fx_edit(&mut self) {
+        self.fx_row = match self.fx_row {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    // エフェクトパネルの行の並び（選択中のトラック、センドバス、マスターのチェーンと、最後に余韻）
+    fn fx_rows(&self) -> Vec<FxRow> {
+        let mut rows = Vec::new();
+        for chain in [FxChain::Track, FxChain::Send, FxChain::Master] {
+            rows.push(FxRow::Chain { chain });
+            for (slot, effect) in self.chain(chain).iter().enumerate() {
+                rows.push(FxRow::Slot { chain, slot });
+                for param in 0..effects::params(effect.kind).len() {
+                    rows.push(FxRow::Param { chain, slot, param });
+                }
+            }
+        }
+        rows.push(FxRow::Tail);
+        rows
+    }
+
+    fn fx_cursor(&self) -> Option<FxRow> {
+        self.fx_rows().get(self.fx_row?).copied()
+    }
+
+    // スロットのゲインリダクション（メーターのあるエフェクトだけ）
+    fn meter(&self, chain: FxChain, slot: usize) -> Option<f32> {
+        let meters = match chain {
+            FxChain::Track => self.meters.tracks.get(self.selected_track)?,
+            FxChain::Send => &self.meters.send_bus,
+            FxChain::Master => &self.meters.master,
+        };
+        meters.get(slot).copied().flatten()
+    }
+
+    fn chain(&self, chain: FxChain) -> &[EffectSlot] {
+        match chain {
+            FxChain::Track => &self.tracks[self.selected_track].effects,
+            FxChain::Send => &self.settings.send_bus,
+            FxChain::Master => &self.settings.master,
+        }
+    }
+
+    // チェーンを編集して履歴に積む
+    fn edit_chain(&mut self, chain: FxChain, field: Option<Field>, f: impl FnOnce(&mut Vec<EffectSlot>)) {
+        match chain {
+            FxChain::Track => self.edit_track(field, |track| f(&mut track.effects)),
+            FxChain::Send => self.edit_settings(field, |settings| f(&mut settings.send_bus)),
+            FxChain::Master => self.edit_settings(field, |settings| f(&mut settings.master)),
+        }
+    }
+
+    // 続けて動かした値をまとめるための、エフェクトパネルの今の行
+    fn fx_field(&self) -> Option<Field> {
+        self.fx_row.map(|row| Field::Row("fx", row))
+    }
+
+    fn move_fx_cursor(&mut self, dy: i32) {
+        let rows = self.fx_rows().len() as i32;
+        if let Some(row) = self.fx_row {
+            self.fx_row = Some((row as i32 + dy).rem_euclid(rows) as usize);
+        }
+    }
+
+    // 行を探してカーソルを合わせる
+    fn select_fx_row(&mut self, row: FxRow) {
+        self.fx_row = self.fx_rows().iter().position(|&r| r == row).or(self.fx_row);
+    }
+
+    // エフェクトの行なら種類を替え、パラメータの行なら値を動かす。
+    // センドバスの見出しでは選択中のトラックの送り量、最後の行ではエクスポートの余韻を動かす
+    fn nudge_fx(&mut self, dir: i32) {
+        match self.fx_cursor() {
+            Some(FxRow::Chain { chain: FxChain::Send }) => self.nudge_send(dir),
+            Some(FxRow::Slot { chain, slot }) => self.edit_chain(chain, None, |chain| {
+                let kinds = EffectType::ALL;
+                let pos = kinds.iter().position(|&k| k == chain[slot].kind).unwrap_or(0);
+                let kind = kinds[(pos as i32 + dir).rem_euclid(kinds.len() as i32) as usize];
+                chain[slot] = EffectSlot::new(kind);
+            }),
+            Some(FxRow::Param { chain, slot, param }) => self.edit_chain(chain, self.fx_field(), |chain| {
+                let specs = effects::params(chain[slot].kind);
+                let mut values = effects::values(specs, &chain[slot].values);
+                values[param] = specs[param].nudge(values[param], dir);
+                chain[slot].values = values;
+            }),
+            // Auto の次が 0 秒
+            Some(FxRow::Tail) => self.edit_settings(self.fx_field(), |settings| {
+                let tail = settings.export_tail.map_or(-TAIL_STEP, |t| t) + TAIL_STEP * dir as f32;
+                settings.export_tail = (tail >= 0.0).then(|| tail.min(effects::MAX_EXPORT_TAIL));
+            }),
+            _ => {}
+        }
+    }
+
+    // カーソルの下（チェーンの見出しなら末尾）にエフェクトを挿す
+    fn add_effect(&mut self) {
+        let (chain, at) = match self.fx_cursor() {
+            Some(FxRow::Chain { chain }) => (chain, self.chain(chain).len()),
+            Some(FxRow::Slot { chain, slot } | FxRow::Param { chain, slot, .. }) => (chain, slot + 1),
+            _ => return,
+        };
+        if self.chain(chain).len() >= MAX_EFFECTS {
+            self.export_message = Some(format!("✗ At most {} effects per chain", MAX_EFFECTS));
+            return;
+        }
+        self.edit_chain(chain, None, |effects| {
+            effects.insert(at, EffectSlot::new(EffectType::ALL[0]))
+        });
+        self.select_fx_row(FxRow::Slot { chain, slot: at });
+    }
+
+    fn toggle_bypass(&mut self) {
+        if let Some(FxRow::Slot { chain, slot } | FxRow::Param { chain, slot, .. }) = self.fx_cursor() {
+            self.edit_chain(chain, None, |effects| effects[slot].bypass = !effects[slot].bypass);
+        }
+    }
+
+    // エフェクトの順番を入れ替える
+    fn move_effect(&mut self, dir: i32) {
+        let Some(FxRow::Slot { chain, slot }) = self.fx_cursor() else { return };
+        let other = slot as i32 + dir;
+        if other < 0 || other as usize >= self.chain(chain).len() {
+            return;
+        }
+        let other = other as usize;
+        self.edit_chain(chain, None, |effects| effects.swap(slot, other));
+        self.select_fx_row(FxRow::Slot { chain, slot: other });
+    }
+
+    // エフェクトの行なら外し、パラメータの行なら既定値に戻す（余韻の行なら Auto に）
+    fn remove_fx_row(&mut self) {
+        match self.fx_cursor() {
+            Some(FxRow::Slot { chain, slot }) => {
+                self.edit_chain(chain, None, |effects| {
+                    effects.remove(slot);
+                });
+                let rows = self.fx_rows().len();
+                self.fx_row = self.fx_row.map(|row| row.min(rows - 1));
+            }
+            Some(FxRow::Param { chain, slot, param }) => self.edit_chain(chain, None, |effects| {
+                let specs = effects::params(effects[slot].kind);
+                let mut values = effects::values(specs, &effects[slot].values);
+                values[param] = specs[param].default;
+                effects[slot].values = values;
+            }),
+            Some(FxRow::Tail) => self.edit_settings(None, |settings| settings.export_tail = None),
+            _ => {}
+        }
+    }
+
+    // 編集内容を反映してから選択中のトラックを鳴らす
+    fn audition(&mut self) {
+        self.sync_audio();
+        self.send(AudioCommand::Hit(self.selected_track));
+    }
+
+    fn select_mixer_track(&mut self, dx: i32) {
+        self.selected_track =
+            (self.selected_track as i32 + dx).rem_euclid(self.tracks.len() as i32) as usize;
+    }
+
+    fn nudge_track_param(&mut self, param: Param, dir: i32) {
+        let value = param.nudge(self.tracks[self.selected_track].param(param), dir);
+        self.edit_track(Some(Field::Param(param)), |track| track.set_param(param, value));
+    }
+
+    // 選択中のトラックからセンドバスへ送る量
+    fn nudge_send(&mut self, dir: i32) {
+        let send = (self.tracks[self.selected_track].send + 0.05 * dir as f32).clamp(0.0, 1.0);
+        self.edit_track(Some(Field::Row("send", 0)), |track| track.send = send);
     }
 
     fn toggle_play(&mut self) {
         self.playing = !self.playing;
+        self.send(if self.playing { AudioCommand::Play } else { AudioCommand::Stop });
+    }
+
+    fn send(&self, command: AudioCommand) {
+        if let Ok(mut commands) = self.audio_commands.lock() {
+            commands.push(command);
+        }
+    }
+
+    fn sequence(&self) -> Sequence {
+        let mut patterns = self.banks.clone();
+        patterns[self.bank] = self.pattern.clone();
+        let samples = self
+            .tracks
+            .iter()
+            .map(|t| t.sample.as_ref().and_then(|path| self.samples.get(path).cloned()))
+            .collect();
+        Sequence {
+            tracks: self.tracks.clone(),
+            samples,
+            patterns,
+            bank: self.bank,
+            bpm: self.settings.bpm,
+            song: self.song_mode.then(|| self.settings.song.clone()),
+            lfos: self.settings.lfos,
+            send_bus: self.settings.send_bus.clone(),
+            master: self.settings.master.clone(),
+        }
+    }
+
+    fn set_bpm(&mut self, bpm: f32) {
+        self.edit_settings(Some(Field::Bpm), |settings| settings.bpm = bpm.clamp(MIN_BPM, MAX_BPM));
+    }
+
+    // 直近のタップ間隔の平均からテンポを決める（2秒空いたらやり直し）
+    fn tap_tempo(&mut self) {
+        let now = Instant::now();
+        if self
+            .taps
+            .last()
+            .is_some_and(|last| now.duration_since(*last) > Duration::from_secs(2))
+        {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > 5 {
+            self.taps.remove(0);
+        }
+        if self.taps.len() >= 2 {
+            let span = now.duration_since(self.taps[0]).as_secs_f32();
+            let interval = span / (self.taps.len() - 1) as f32;
+            self.set_bpm((60.0 / interval * 10.0).round() / 10.0);
+        }
+    }
+
+    fn confirm_bpm_input(&mut self) {
+        if let Some(input) = self.bpm_input.take() {
+            match input.parse::<f32>() {
+                Ok(bpm) => self.set_bpm(bpm),
+                Err(_) => self.export_message = Some(format!("✗ Invalid BPM: {}", input)),
+            }
+        }
+    }
+
+    fn toggle_song_mode(&mut self) {
+        self.song_mode = !self.song_mode;
+        if self.song_mode && self.settings.song.sections.is_empty() {
+            self.export_message = Some("Song is empty: press A to arrange sections".to_string());
+        }
+    }
+
+    fn toggle_song_edit(&mut self) {
+        self.song_cursor = match self.song_cursor {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    fn move_song_cursor(&mut self, dy: i32) {
+        let count = self.settings.song.sections.len().max(1) as i32;
+        if let Some(idx) = self.song_cursor {
+            self.song_cursor = Some((idx as i32 + dy).rem_euclid(count) as usize);
+        }
+    }
+
+    // 選択中のセクションの後ろに、現在のバンクとテンポで追加
+    fn add_section(&mut self) {
+        let section = Section {
+            bank: self.bank,
+            repeats: 1,
+            bpm: self.settings.bpm,
+            ramp: false,
+        };
+        let idx = match self.song_cursor {
+            Some(idx) if !self.settings.song.sections.is_empty() => idx + 1,
+            _ => 0,
+        };
+        self.edit_settings(None, |settings| settings.song.sections.insert(idx, section));
+        self.song_cursor = Some(idx);
+    }
+
+    fn remove_section(&mut self) {
+        let Some(idx) = self.song_cursor else { return };
+        if idx < self.settings.song.sections.len() {
+            self.edit_settings(None, |settings| settings.song.sections.remove(idx));
+            self.song_cursor = Some(idx.min(self.settings.song.sections.len().saturating_sub(1)));
+        }
+    }
+
+    // 選択中のセクションを編集する。key が同じなら続けて動かした分をまとめる
+    fn edit_section(&mut self, key: Option<&'static str>, f: impl FnOnce(&mut Section)) {
+        let Some(idx) = self.song_cursor else { return };
+        if idx < self.settings.song.sections.len() {
+            let field = key.map(|key| Field::Row(key, idx));
+            self.edit_settings(field, |settings| f(&mut settings.song.sections[idx]));
+        }
+    }
+
+    // 最後にオーディオスレッドへ送ってから変わったか（複製を作らずに比べる）
+    fn sequence_changed(&self) -> bool {
+        let sent = &self.sent;
+        let patterns = self.banks.iter().enumerate().map(|(i, p)| if i == self.bank { &self.pattern } else { p });
+        let samples = self.tracks.iter().map(|t| t.sample.as_ref().and_then(|path| self.samples.get(path)));
+        let same_samples = sent.samples.len() == self.tracks.len()
+            && samples.zip(&sent.samples).all(|pair| match pair {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            });
+        !(same_samples
+            && sent.tracks == self.tracks
+            && sent.patterns.len() == self.banks.len()
+            && patterns.zip(&sent.patterns).all(|(a, b)| a == b)
+            && sent.bank == self.bank
+            && sent.bpm == self.settings.bpm
+            && sent.song.as_ref() == self.song_mode.then_some(&self.settings.song)
+            && sent.lfos == self.settings.lfos
+            && sent.send_bus == self.settings.send_bus
+            && sent.master == self.settings.master)
+    }
+
+    // 編集内容をオーディオスレッドに反映
+    fn sync_audio(&mut self) {
+        if !self.sequence_changed() {
+            return;
+        }
+        let seq = self.sequence();
+        self.sent = seq.clone();
+        let update = self.layouts.update(seq);
+        self.send(AudioCommand::Update(update));
+    }
+
+    // オーディオスレッドから再生位置を受け取る
+    fn poll_playback(&mut self) {
+        let retired = match self.playback.lock() {
+            Ok(mut status) => {
+                self.current_step = status.step;
+                self.playing_section = status.section;
+                self.playing_bpm = status.bpm;
+                self.clock = status.clock;
+                self.meters.clone_from(&status.meters);
+                std::mem::replace(&mut status.retired, Vec::with_capacity(MAX_RETIRED))
+            }
+            Err(_) => return,
+        };
+        // 返ってきた差し替えはロックを放してから捨てる
+        drop(retired);
+    }
+
+    fn pad_hit(&mut self, track: usize) {
+        if track < self.tracks.len() {
+            self.send(AudioCommand::Hit(track));
+            // 押した時刻の位置に録音する（鳴るのはオーディオスレッドの次のブロックから）
+            if let Some(clock) = self.clock {
+                self.record_hit(track, clock.bank, clock.position_at(Instant::now()));
+            }
+        }
+    }
+
+    fn toggle_record(&mut self) {
+        self.recording = !self.recording;
+        self.take_tracks.clear();
+    }
+
+    fn toggle_overdub(&mut self) {
+        self.overdub = !self.overdub;
+    }
+
+    fn cycle_quantize(&mut self) {
+        self.quantize = if self.quantize <= 0.0 { 1.0 } else { self.quantize - 0.25 };
+    }
+
+    // オーディオクロック上の位置から、再生中のバンクの一番近いステップにヒットを書き込む
+    // （ソングモードでは編集中のバンクと違うことがある）
+    fn record_hit(&mut self, track: usize, bank: usize, position: f32) {
+        if !self.recording || !self.playing || track >= self.tracks.len() || bank >= self.banks.len() {
+            return;
+        }
+        let length = self.bank_mut(bank).length;
+        let (step, offset) = quantize_hit(position, length, self.quantize);
+
+        // 置き換え録音では、テイク中に初めて叩いたトラックを先に空にする
+        if !self.overdub && !self.take_tracks.contains(&(bank, track)) {
+            self.take_tracks.push((bank, track));
+            self.edit_pattern_in(bank, |p| {
+                p.clear_block(track..track + 1, 0..length);
+                true
+            });
+        }
+
+        self.edit_step_in(bank, track, step, None, |s| {
+            s.active = true;
+            s.offset = offset;
+        });
     }
 
     fn export_to_wav(&self, filename: &str, loops: usize) -> Result<(), Box<dyn Error>> {
         let sample_rate = 44100;
         let spec = WavSpec {
-            channels: 1,
+            channels: 2,
             sample_rate,
             bits_per_sample: 16,
             sample_format: hound::SampleFormat::Int,
@@ -215,72 +2171,35 @@ impl App {
 
         let mut writer = WavWriter::create(filename, spec)?;
         
-        let samples_per_step = (60.0 / self.bpm as f32 * sample_rate as f32 / 4.0) as usize;
-        let total_steps = self.pattern.length * loops;
+        // ライブ再生と同じ Player でオフラインレンダリング
+        // （ソングモードでは曲を1回、それ以外はパターンを loops 回）
+        let mut player = Player::new(sample_rate as f32, self.sequence());
+        let passes = if player.song().is_some() { 1 } else { loops };
 
-        let mut active_sounds: Vec<(SoundTrigger, AudioEngine)> = Vec::new();
-
-        for step in 0..total_steps {
-            let current_step = step % self.pattern.length;
-            
-            // このステップでトリガーされる音を追加
-            for (track_idx, track) in self.pattern.tracks.iter().enumerate() {
-                if track.steps[current_step] {
-                    let trigger = match track_idx {
-                        0 => SoundTrigger::Kick,
-                        1 => SoundTrigger::Snare,
-                        2 => SoundTrigger::HiHat,
-                        3 => SoundTrigger::Bass(36.0),
-                        _ => continue,
-                    };
-                    let engine = AudioEngine::new(sample_rate as f32);
-                    active_sounds.push((trigger, engine));
-                }
+        // ブロック単位で生成する。終わった位置で再生を止め、そこからエフェクトの余韻の分だけ書き足す
+        // （余韻の長さを指定していればその分）
+        let (mut left, mut right) = (vec![0.0; 512], vec![0.0; 512]);
+        player.stop_after = Some(passes);
+        player.play();
+        let mut remaining = None; // 書き出す残りのサンプル数（終わりが決まってから）
+        while remaining != Some(0) {
+            player.render(&mut left, &mut right);
+            if remaining.is_none() && player.passes >= passes {
+                let end = player.wrapped_at.map_or(left.len(), |offset| offset + 1);
+                let tail = self.settings.export_tail.unwrap_or_else(|| player.tail());
+                remaining = Some(end + (tail * sample_rate as f32) as usize);
             }
-
-            // このステップのサンプルを生成
-            for _ in 0..samples_per_step {
-                let mut mix = 0.0f32;
-
-                active_sounds.retain_mut(|(trigger, engine)| {
-                    let s = match trigger {
-                        SoundTrigger::Kick => engine.generate_kick(),
-                        SoundTrigger::Snare => engine.generate_snare(),
-                        SoundTrigger::HiHat => engine.generate_hihat(),
-                        SoundTrigger::Bass(note) => engine.generate_bass(*note),
-                    };
-                    
-                    mix += s;
-                    engine.time > 0.0 || s.abs() > 0.001
-                });
-
-                let sample = (mix.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                writer.write_sample(sample)?;
+            let len = remaining.map_or(left.len(), |r| r.min(left.len()));
+            remaining = remaining.map(|r| r - len);
+            for (l, r) in left[..len].iter().zip(&right[..len]) {
+                writer.write_sample((l * i16::MAX as f32) as i16)?;
+                writer.write_sample((r * i16::MAX as f32) as i16)?;
             }
         }
 
         writer.finalize()?;
         Ok(())
     }
-
-    fn get_active_sounds(&self) -> Vec<SoundTrigger> {
-        let mut triggers = Vec::new();
-        
-        for (track_idx, track) in self.pattern.tracks.iter().enumerate() {
-            if track.steps[self.current_step] {
-                let trigger = match track_idx {
-                    0 => SoundTrigger::Kick,
-                    1 => SoundTrigger::Snare,
-                    2 => SoundTrigger::HiHat,
-                    3 => SoundTrigger::Bass(36.0), // C1
-                    _ => continue,
-                };
-                triggers.push(trigger);
-            }
-        }
-        
-        triggers
-    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -291,18 +2210,31 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new();
+    // 引数でプロジェクトファイルを指定（既存なら読み込む）
+    let project_path = PathBuf::from(
+        std::env::args().nth(1).unwrap_or_else(|| "rhythm-box.project".to_string()),
+    );
+    let mut app = App::new(project_path);
+    if app.project_path.exists() {
+        app.load_project();
+    }
     
-    // オーディオストリーム起動
-    let audio_engine = app.audio_engine.clone();
-    let triggered_sounds = Arc::new(Mutex::new(Vec::<SoundTrigger>::new()));
-    let triggered_sounds_clone = triggered_sounds.clone();
+    // オーディオストリーム起動（読み込みで積んだ差し替えは要らない。Player は今の内容から作る）
+    app.audio_commands.lock().unwrap().clear();
+    let sequence = app.sequence();
+    let audio_commands = app.audio_commands.clone();
+    let playback = app.playback.clone();
+    let (ready, ready_rx) = mpsc::channel();
     
     thread::spawn(move || {
-        start_audio_stream(audio_engine, triggered_sounds_clone).unwrap();
+        start_audio_stream(sequence, audio_commands, playback, ready).unwrap();
     });
+    // 出力デバイスが開けたら、そのサンプルレートで作ったチェーンの控えを受け取る
+    if let Ok(layouts) = ready_rx.recv() {
+        app.layouts = layouts;
+    }
     
-    let res = run_app(&mut terminal, &mut app, triggered_sounds);
+    let res = run_app(&mut terminal, &mut app);
 
     // クリーンアップ
     disable_raw_mode()?;
@@ -321,45 +2253,80 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 fn start_audio_stream(
-    audio_engine: Arc<Mutex<AudioEngine>>,
-    triggered_sounds: Arc<Mutex<Vec<SoundTrigger>>>,
+    sequence: Sequence,
+    audio_commands: Arc<Mutex<Vec<AudioCommand>>>,
+    playback: Arc<Mutex<PlaybackStatus>>,
+    ready: mpsc::Sender<Layouts>,
 ) -> Result<(), Box<dyn Error>> {
     let host = cpal::default_host();
     let device = host.default_output_device().ok_or("No output device")?;
     let config = device.default_output_config()?;
+    let channels = config.channels() as usize;
 
-    let mut active_sounds: Vec<(SoundTrigger, AudioEngine)> = Vec::new();
+    // ステップの進行はオーディオクロックで行う。作ったチェーンの控えはUIスレッドへ渡す
+    let mut layouts = Layouts::new(config.sample_rate() as f32);
+    let mut player = Player::start(&mut layouts, sequence);
+    ready.send(layouts)?;
+    let mut retired: Vec<Box<Update>> = Vec::with_capacity(MAX_RETIRED);
+    let (mut left, mut right) = (Vec::new(), Vec::new());
 
     let stream = device.build_output_stream(
         &config.into(),
         move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            // 新しいトリガーをチェック
-            if let Ok(mut triggers) = triggered_sounds.try_lock() {
-                for trigger in triggers.drain(..) {
-                    let engine = AudioEngine::new(44100.0);
-                    active_sounds.push((trigger, engine));
+            // UIからの指示をチェック
+            if let Ok(mut commands) = audio_commands.try_lock() {
+                // 差し替えは返す場所があるだけ適用し、残りは次のブロックに回す
+                let mut room = retired.capacity() - retired.len();
+                let mut done = 0;
+                for command in commands.iter_mut() {
+                    match command {
+                        AudioCommand::Update(_) if room == 0 => break,
+                        AudioCommand::Update(update) => {
+                            player.update(update);
+                            room -= 1;
+                        }
+                        AudioCommand::Play => player.play(),
+                        AudioCommand::Stop => player.stop(),
+                        AudioCommand::Hit(track) => player.trigger_track(*track),
+                    }
+                    done += 1;
+                }
+                for command in commands.drain(..done) {
+                    if let AudioCommand::Update(update) = command {
+                        retired.push(update);
+                    }
                 }
             }
 
-            for sample in data.iter_mut() {
-                let mut mix = 0.0f32;
+            let clock = player.playing.then(|| Clock {
+                at: Instant::now(),
+                bank: player.bank,
+                position: player.position(),
+                steps_per_second: player.tempo() / 15.0,
+            });
+            let frames = data.len() / channels;
+            left.resize(frames, 0.0);
+            right.resize(frames, 0.0);
+            player.render(&mut left, &mut right);
 
-                // すべてのアクティブな音を生成してミックス
-                active_sounds.retain_mut(|(trigger, engine)| {
-                    let s = match trigger {
-                        SoundTrigger::Kick => engine.generate_kick(),
-                        SoundTrigger::Snare => engine.generate_snare(),
-                        SoundTrigger::HiHat => engine.generate_hihat(),
-                        SoundTrigger::Bass(note) => engine.generate_bass(*note),
-                    };
-                    
-                    mix += s;
-                    
-                    // 音が終わったら削除
-                    engine.time > 0.0 || s.abs() > 0.001
-                });
+            for ((frame, &l), &r) in data.chunks_mut(channels).zip(&left).zip(&right) {
+                // モノラル出力や3ch以上では余ったチャンネルに左右の平均を出す
+                frame.fill((l + r) * 0.5);
+                if let [first, second, ..] = frame {
+                    *first = l;
+                    *second = r;
+                }
+            }
 
-                *sample = mix.clamp(-1.0, 1.0);
+            if let Ok(mut status) = playback.try_lock() {
+                status.step = player.step;
+                status.section = player.song().map(|_| player.section);
+                status.bpm = player.tempo();
+                status.clock = clock;
+                player.read_meters(&mut status.meters);
+                // UIスレッドが確保した容量に収まる分だけ返す
+                let room = status.retired.capacity() - status.retired.len();
+                status.retired.extend(retired.drain(..room.min(retired.len())));
             }
         },
         |err| eprintln!("Audio error: {}", err),
@@ -372,20 +2339,100 @@ fn start_audio_stream(
     Ok(())
 }
 
-fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    app: &mut App,
-    triggered_sounds: Arc<Mutex<Vec<SoundTrigger>>>,
-) -> io::Result<()> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
+        app.poll_playback();
         terminal.draw(|f| ui(f, app))?;
 
-        if event::poll(std::time::Duration::from_millis(100))? {
+        if event::poll(std::time::Duration::from_millis(20))? {
             if let Event::Key(key) = event::read()? {
                 match key.code {
+                    code if app.bpm_input.is_some() => handle_bpm_key(app, code),
+                    code if app.rename_input.is_some() => handle_rename_key(app, code),
+                    code if app.sample_input.is_some() => handle_sample_key(app, code),
                     KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char(' ') => app.toggle_step(),
                     KeyCode::Enter => app.toggle_play(),
+                    KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => app.undo(),
+                    KeyCode::Char('y') if key.modifiers.contains(KeyModifiers::CONTROL) => app.redo(),
+                    KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.save_project()
+                    }
+                    KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.load_project()
+                    }
+                    code if app.lock_param.is_some() => handle_lock_key(app, code),
+                    code if app.sound_param.is_some() => handle_sound_key(app, code),
+                    code if app.mod_row.is_some() => handle_mod_key(app, code),
+                    code if app.fx_row.is_some() => handle_fx_key(app, code),
+                    code if app.song_cursor.is_some() => handle_song_key(app, code),
+                    code if app.mixer => handle_mixer_key(app, code),
+                    KeyCode::Tab => app.toggle_mixer(),
+                    KeyCode::Char(' ') => app.toggle_step(),
+                    // トラックの管理
+                    KeyCode::Char('n') | KeyCode::Char('N') => app.add_track(),
+                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.duplicate_track()
+                    }
+                    KeyCode::Delete => app.remove_track(),
+                    KeyCode::F(2) => app.rename_input = Some(app.tracks[app.selected_track].name.clone()),
+                    KeyCode::Char('w') | KeyCode::Char('W') => {
+                        let track = &app.tracks[app.selected_track];
+                        let path = track.sample.as_ref().map(|p| p.display().to_string());
+                        app.sample_input = Some(path.unwrap_or_default());
+                    }
+                    KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => app.cycle_kit(),
+                    KeyCode::Char('k') => app.cycle_instrument(1),
+                    KeyCode::Char('K') => app.cycle_instrument(-1),
+                    KeyCode::Up if key.modifiers.contains(KeyModifiers::CONTROL) => app.move_track(-1),
+                    KeyCode::Down if key.modifiers.contains(KeyModifiers::CONTROL) => app.move_track(1),
+                    KeyCode::Char('l') | KeyCode::Char('L') => app.toggle_lock_edit(),
+                    KeyCode::Char('s') | KeyCode::Char('S') => app.toggle_sound_edit(),
+                    KeyCode::Char('u') | KeyCode::Char('U') => app.toggle_mod_edit(),
+                    KeyCode::Char('f') | KeyCode::Char('F') => app.toggle_fx_edit(),
+                    // 範囲選択とクリップボード
+                    KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        app.extend_selection(-1, 0)
+                    }
+                    KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        app.extend_selection(1, 0)
+                    }
+                    KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        app.extend_selection(0, -1)
+                    }
+                    KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        app.extend_selection(0, 1)
+                    }
+                    KeyCode::Esc => app.selection_anchor = None,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.copy_selection()
+                    }
+                    KeyCode::Char('x') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.cut_selection()
+                    }
+                    KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => app.paste(),
+                    KeyCode::Char('<') | KeyCode::Char(',') => app.rotate(-1),
+                    KeyCode::Char('>') | KeyCode::Char('.') => app.rotate(1),
+                    KeyCode::Char('v') => app.reverse(),
+                    KeyCode::Char('i') => app.invert(),
+                    KeyCode::Char('d') => app.double_length(),
+                    KeyCode::Char('h') => app.halve_length(),
+                    // パターンバンク
+                    KeyCode::Char('[') => app.step_bank(-1),
+                    KeyCode::Char(']') => app.step_bank(1),
+                    KeyCode::Char('{') => app.copy_to_bank(-1),
+                    KeyCode::Char('}') => app.copy_to_bank(1),
+                    // パッド入力と録音
+                    KeyCode::Char(c @ '1'..='9') => app.pad_hit(c as usize - '1' as usize),
+                    KeyCode::Char('r') | KeyCode::Char('R') => app.toggle_record(),
+                    KeyCode::Char('o') | KeyCode::Char('O') => app.toggle_overdub(),
+                    KeyCode::Char('g') | KeyCode::Char('G') => app.cycle_quantize(),
+                    // テンポとソング
+                    KeyCode::Char('+') | KeyCode::Char('=') => app.set_bpm(app.settings.bpm + 1.0),
+                    KeyCode::Char('-') => app.set_bpm(app.settings.bpm - 1.0),
+                    KeyCode::Char('t') | KeyCode::Char('T') => app.tap_tempo(),
+                    KeyCode::Char('b') | KeyCode::Char('B') => app.bpm_input = Some(String::new()),
+                    KeyCode::Char('m') | KeyCode::Char('M') => app.toggle_song_mode(),
+                    KeyCode::Char('a') | KeyCode::Char('A') => app.toggle_song_edit(),
                     KeyCode::Left => app.move_cursor(-1, 0),
                     KeyCode::Right => app.move_cursor(1, 0),
                     KeyCode::Up => app.move_cursor(0, -1),
@@ -414,22 +2461,158 @@ fn run_app<B: Backend>(
                     }
                     _ => {}
                 }
+                app.sync_audio();
             }
         }
+    }
+}
 
-        // 再生中はステップを進める
-        if app.playing {
-            std::thread::sleep(std::time::Duration::from_millis(
-                (60000 / app.bpm / 4) as u64,
-            ));
-            app.current_step = (app.current_step + 1) % app.pattern.length;
-            
-            // アクティブな音をトリガー
-            let sounds = app.get_active_sounds();
-            if let Ok(mut triggers) = triggered_sounds.try_lock() {
-                triggers.extend(sounds);
-            }
+// パラメータロック編集中のキー操作
+fn handle_lock_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.move_lock_cursor(-1),
+        KeyCode::Down => app.move_lock_cursor(1),
+        KeyCode::Left => app.nudge_lock(-1),
+        KeyCode::Right => app.nudge_lock(1),
+        KeyCode::Backspace | KeyCode::Delete => app.clear_lock(),
+        KeyCode::Char('l') | KeyCode::Char('L') | KeyCode::Esc => app.toggle_lock_edit(),
+        _ => {}
+    }
+}
+
+// ミキサー画面のキー操作
+fn handle_mixer_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Left => app.select_mixer_track(-1),
+        KeyCode::Right => app.select_mixer_track(1),
+        KeyCode::Up => app.nudge_track_param(Param::Volume, 1),
+        KeyCode::Down => app.nudge_track_param(Param::Volume, -1),
+        KeyCode::Char('<') | KeyCode::Char(',') => app.nudge_track_param(Param::Pan, -1),
+        KeyCode::Char('>') | KeyCode::Char('.') => app.nudge_track_param(Param::Pan, 1),
+        KeyCode::Char('[') => app.nudge_send(-1),
+        KeyCode::Char(']') => app.nudge_send(1),
+        KeyCode::Char('m') | KeyCode::Char('M') => app.edit_track(None, |t| t.mute = !t.mute),
+        KeyCode::Char('s') | KeyCode::Char('S') => app.edit_track(None, |t| t.solo = !t.solo),
+        KeyCode::Char('c') | KeyCode::Char('C') => {
+            app.edit_track(None, |t| t.choke = (t.choke + 1) % (CHOKE_GROUPS + 1))
+        }
+        KeyCode::Char(c @ '1'..='9') => app.pad_hit(c as usize - '1' as usize),
+        KeyCode::Tab | KeyCode::Esc => app.toggle_mixer(),
+        _ => {}
+    }
+}
+
+// サウンド編集中のキー操作
+fn handle_sound_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.move_sound_cursor(-1),
+        KeyCode::Down => app.move_sound_cursor(1),
+        KeyCode::Left => app.nudge_sound(-1),
+        KeyCode::Right => app.nudge_sound(1),
+        KeyCode::Char(' ') => app.audition(),
+        KeyCode::Backspace | KeyCode::Delete => app.reset_sound(),
+        KeyCode::Char('a') | KeyCode::Char('A') => app.assign_slices(),
+        KeyCode::Char('t') | KeyCode::Char('T') => app.match_loop_tempo(),
+        KeyCode::Char('p') | KeyCode::Char('P') => app.next_preset(),
+        KeyCode::Char('s') | KeyCode::Char('S') | KeyCode::Esc => app.toggle_sound_edit(),
+        _ => {}
+    }
+}
+
+// モジュレーション編集中のキー操作
+fn handle_mod_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.move_mod_cursor(-1),
+        KeyCode::Down => app.move_mod_cursor(1),
+        KeyCode::Left => app.nudge_mod(-1),
+        KeyCode::Right => app.nudge_mod(1),
+        KeyCode::Char(' ') => app.audition(),
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Insert => app.add_route(),
+        KeyCode::Char('o') | KeyCode::Char('O') => app.cycle_route(true),
+        KeyCode::Char('t') | KeyCode::Char('T') => app.cycle_route(false),
+        KeyCode::Backspace | KeyCode::Delete => app.remove_mod_row(),
+        KeyCode::Char('u') | KeyCode::Char('U') | KeyCode::Esc => app.toggle_mod_edit(),
+        _ => {}
+    }
+}
+
+// エフェクト編集中のキー操作
+fn handle_fx_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.move_fx_cursor(-1),
+        KeyCode::Down => app.move_fx_cursor(1),
+        KeyCode::Left => app.nudge_fx(-1),
+        KeyCode::Right => app.nudge_fx(1),
+        KeyCode::Char(' ') => app.audition(),
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Insert => app.add_effect(),
+        KeyCode::Char('b') | KeyCode::Char('B') => app.toggle_bypass(),
+        KeyCode::Char('[') => app.move_effect(-1),
+        KeyCode::Char(']') => app.move_effect(1),
+        KeyCode::Backspace | KeyCode::Delete => app.remove_fx_row(),
+        KeyCode::Char('f') | KeyCode::Char('F') | KeyCode::Esc => app.toggle_fx_edit(),
+        _ => {}
+    }
+}
+
+// BPM 入力中のキー操作
+fn handle_bpm_key(app: &mut App, code: KeyCode) {
+    let Some(input) = app.bpm_input.as_mut() else { return };
+    match code {
+        KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
         }
+        KeyCode::Enter => app.confirm_bpm_input(),
+        KeyCode::Esc => app.bpm_input = None,
+        _ => {}
+    }
+}
+
+// トラック名を入力中のキー操作
+fn handle_rename_key(app: &mut App, code: KeyCode) {
+    let Some(input) = app.rename_input.as_mut() else { return };
+    match code {
+        KeyCode::Char(c) if input.chars().count() < 16 => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        KeyCode::Enter => app.confirm_rename(),
+        KeyCode::Esc => app.rename_input = None,
+        _ => {}
+    }
+}
+
+// WAV のパスを入力中のキー操作
+fn handle_sample_key(app: &mut App, code: KeyCode) {
+    let Some(input) = app.sample_input.as_mut() else { return };
+    match code {
+        KeyCode::Char(c) => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        KeyCode::Enter => app.confirm_sample_input(),
+        KeyCode::Esc => app.sample_input = None,
+        _ => {}
+    }
+}
+
+// ソング編集中のキー操作
+fn handle_song_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up => app.move_song_cursor(-1),
+        KeyCode::Down => app.move_song_cursor(1),
+        KeyCode::Insert | KeyCode::Char('n') | KeyCode::Char('N') => app.add_section(),
+        KeyCode::Backspace | KeyCode::Delete => app.remove_section(),
+        KeyCode::Left => app.edit_section(Some("bank"), |s| s.bank = (s.bank + BANK_COUNT - 1) % BANK_COUNT),
+        KeyCode::Right => app.edit_section(Some("bank"), |s| s.bank = (s.bank + 1) % BANK_COUNT),
+        KeyCode::Char('+') | KeyCode::Char('=') => app.edit_section(Some("repeats"), |s| s.repeats += 1),
+        KeyCode::Char('-') => app.edit_section(Some("repeats"), |s| s.repeats = (s.repeats - 1).max(1)),
+        KeyCode::Char('[') => app.edit_section(Some("bpm"), |s| s.bpm = (s.bpm - 1.0).max(MIN_BPM)),
+        KeyCode::Char(']') => app.edit_section(Some("bpm"), |s| s.bpm = (s.bpm + 1.0).min(MAX_BPM)),
+        KeyCode::Char('p') | KeyCode::Char('P') => app.edit_section(None, |s| s.ramp = !s.ramp),
+        KeyCode::Char('m') | KeyCode::Char('M') => app.toggle_song_mode(),
+        KeyCode::Char('a') | KeyCode::Char('A') | KeyCode::Esc => app.toggle_song_edit(),
+        _ => {}
     }
 }
 
@@ -439,7 +2622,7 @@ fn ui(f: &mut Frame, app: &App) {
         .constraints([
             Constraint::Length(3),  // Header
             Constraint::Min(10),    // Sequencer
-            Constraint::Length(5),  // Controls
+            Constraint::Length(19), // Controls
             Constraint::Length(2),  // Export message
         ])
         .split(f.size());
@@ -449,11 +2632,39 @@ fn ui(f: &mut Frame, app: &App) {
         Line::from(vec![
             Span::styled("🎵 ", Style::default().fg(Color::Cyan)),
             Span::styled("Rust Rhythm Box", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-            Span::styled(format!(" | BPM: {} | ", app.bpm), Style::default().fg(Color::Yellow)),
+            Span::styled(
+                match &app.bpm_input {
+                    Some(input) => format!(" | BPM: [{}_] | ", input),
+                    None if app.playing && app.song_mode => format!(" | BPM: {:.1} | ", app.playing_bpm),
+                    None => format!(" | BPM: {:.1} | ", app.settings.bpm),
+                },
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::styled(
+                if app.song_mode { "SONG | " } else { "PATTERN | " },
+                Style::default().fg(if app.song_mode { Color::Magenta } else { Color::Gray }),
+            ),
+            Span::styled(
+                format!(
+                    "{}{} | ",
+                    app.project_path.display(),
+                    if app.history.is_dirty() { " *" } else { "" }
+                ),
+                Style::default().fg(Color::Gray),
+            ),
             Span::styled(
                 if app.playing { "▶ PLAYING" } else { "⏸ STOPPED" },
                 Style::default().fg(if app.playing { Color::Green } else { Color::Red })
             ),
+            Span::styled(
+                format!(
+                    " | {}{} Q{:.0}%",
+                    if app.recording { "● REC " } else { "" },
+                    if app.overdub { "Overdub" } else { "Replace" },
+                    app.quantize * 100.0
+                ),
+                Style::default().fg(if app.recording { Color::Red } else { Color::Gray }),
+            ),
         ]),
     ];
     let header = Paragraph::new(title)
@@ -461,8 +2672,34 @@ fn ui(f: &mut Frame, app: &App) {
         .alignment(Alignment::Center);
     f.render_widget(header, chunks[0]);
 
-    // シーケンサーグリッド
-    draw_sequencer(f, chunks[1], app);
+    // シーケンサーグリッド（ロック/ソング編集中は右にパネル）
+    if app.mixer {
+        draw_mixer(f, chunks[1], app);
+    } else if app.lock_param.is_some()
+        || app.sound_param.is_some()
+        || app.mod_row.is_some()
+        || app.fx_row.is_some()
+        || app.song_cursor.is_some()
+    {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(34)])
+            .split(chunks[1]);
+        draw_sequencer(f, columns[0], app);
+        if app.lock_param.is_some() {
+            draw_lock_panel(f, columns[1], app);
+        } else if app.sound_param.is_some() {
+            draw_sound_panel(f, columns[1], app);
+        } else if app.mod_row.is_some() {
+            draw_mod_panel(f, columns[1], app);
+        } else if app.fx_row.is_some() {
+            draw_fx_panel(f, columns[1], app);
+        } else {
+            draw_song_panel(f, columns[1], app);
+        }
+    } else {
+        draw_sequencer(f, chunks[1], app);
+    }
 
     // コントロール説明
     let controls = vec![
@@ -470,14 +2707,31 @@ fn ui(f: &mut Frame, app: &App) {
         Line::from("  ← → ↑ ↓  : Move cursor"),
         Line::from("  Space     : Toggle step  |  E : Export WAV (4 loops)"),
         Line::from("  Enter     : Play/Stop    |  Q : Quit"),
+        Line::from("  L         : Param locks (↑↓ param, ←→ value, Bksp clear)"),
+        Line::from("  S         : Sound design (↑↓ param, ←→ value, Space audition, Bksp reset, P preset)"),
+        Line::from("  U         : Modulation (LFOs, N add route, O source, T target, ←→ depth, Bksp remove)"),
+        Line::from("  F         : Effects + send bus (N add, ←→ type/value/send, B bypass, [ ] reorder, Bksp remove)"),
+        Line::from("  Ctrl+Z/Y  : Undo/Redo    |  Ctrl+S/O : Save/Load project"),
+        Line::from("  Shift+←→↑↓: Select  |  Ctrl+C/X/V : Copy/Cut/Paste  |  < > : Rotate"),
+        Line::from("  V : Reverse  I : Invert  D/H : Double/Halve  |  [ ] : Bank  { } : Copy to bank"),
+        Line::from("  1-9 : Pads  |  R : Record  O : Overdub/Replace  G : Quantize strength"),
+        Line::from("  + - : BPM  T : Tap tempo  B : Type BPM  |  M : Song mode  A : Arrange song"),
+        Line::from("  Tab : Mixer (←→ track, ↑↓ volume, < > pan, [ ] send, M mute, S solo, C choke group)"),
+        Line::from("  N : Add track  Ctrl+D : Duplicate  Del : Remove  F2 : Rename  K : Instrument  Ctrl+↑↓ : Move"),
+        Line::from("  W : Load a WAV sample (or a kit folder)   Ctrl+K : Next kit"),
     ];
     let controls_widget = Paragraph::new(controls)
         .block(Block::default().borders(Borders::ALL).title("Help"))
         .style(Style::default().fg(Color::Gray));
     f.render_widget(controls_widget, chunks[2]);
 
-    // エクスポートメッセージ
-    if let Some(msg) = &app.export_message {
+    // エクスポートメッセージ（WAV のパス入力中はその入力欄）
+    if let Some(input) = &app.sample_input {
+        let prompt = Paragraph::new(format!("Sample WAV or kit folder: {}_  (Enter load, Esc cancel)", input))
+            .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center);
+        f.render_widget(prompt, chunks[3]);
+    } else if let Some(msg) = &app.export_message {
         let color = if msg.starts_with('✓') {
             Color::Green
         } else if msg.starts_with('✗') {
@@ -494,16 +2748,25 @@ fn ui(f: &mut Frame, app: &App) {
 }
 
 fn draw_sequencer(f: &mut Frame, area: Rect, app: &App) {
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .title("Step Sequencer (16 steps)");
+    let kit = app.kit.as_deref().map(|dir| format!(" | Kit {}", kit::kit_name(dir)));
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "Step Sequencer ({} steps) | Bank {}{}",
+        app.pattern.length,
+        bank_name(app.bank),
+        kit.unwrap_or_default()
+    ));
     let inner = block.inner(area);
     f.render_widget(block, area);
 
-    // 各トラックを描画
-    let track_height = inner.height / app.pattern.tracks.len() as u16;
-    
-    for (track_idx, track) in app.pattern.tracks.iter().enumerate() {
+    let (sel_tracks, sel_steps) = app.selection();
+
+    // 各トラックを描画（収まらないトラックは省略）
+    let track_height = (inner.height / app.tracks.len() as u16).max(1);
+
+    for (track_idx, track) in app.tracks.iter().enumerate() {
+        if track_idx as u16 * track_height >= inner.height {
+            break;
+        }
         let track_area = Rect {
             x: inner.x,
             y: inner.y + (track_idx as u16 * track_height),
@@ -511,34 +2774,930 @@ fn draw_sequencer(f: &mut Frame, area: Rect, app: &App) {
             height: track_height,
         };
 
+        // ミュートされている（ソロで外れている）トラックは暗く表示
+        let name_color = if audible(&app.tracks, track_idx) { Color::Cyan } else { Color::DarkGray };
+        let name = match &app.rename_input {
+            Some(input) if track_idx == app.selected_track => format!("{}_", input),
+            _ => track.name.clone(),
+        };
         let mut line_content = vec![
             Span::styled(
-                format!("{:8} ", track.name),
-                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
-            )
+                format!("{:8} ", name),
+                Style::default().fg(name_color).add_modifier(Modifier::BOLD)
+            ),
+            Span::styled(
+                format!("{:8}", track.instrument.name()),
+                Style::default().fg(Color::DarkGray),
+            ),
         ];
 
         // 16ステップを描画
-        for (step_idx, &active) in track.steps.iter().enumerate() {
+        let mut held_until = 0; // 伸ばしている音が続くステップ
+        for (step_idx, step) in app.pattern.steps[track_idx].iter().enumerate() {
+            let active = step.active;
+            if active {
+                held_until = step_idx + step.length as usize;
+            }
+            let held = !active && step_idx < held_until;
             let is_current = step_idx == app.current_step && app.playing;
             let is_selected = step_idx == app.selected_step && track_idx == app.selected_track;
+            let in_selection = app.selection_anchor.is_some()
+                && sel_tracks.contains(&track_idx)
+                && sel_steps.contains(&step_idx);
 
-            let symbol = if active { "●" } else { "○" };
+            // ロック付きのステップは別の記号で表示
+            let symbol = match (active, step.has_locks()) {
+                (true, false) => "●",
+                (true, true) => "◉",
+                (false, _) if held => "─",
+                (false, false) => "○",
+                (false, true) => "◎",
+            };
             
             let style = if is_selected {
                 Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
             } else if is_current {
                 Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
-            } else if active {
+            } else if step.has_locks() {
+                Style::default().fg(Color::Magenta)
+            } else if active || held {
                 Style::default().fg(Color::White)
             } else {
                 Style::default().fg(Color::DarkGray)
             };
 
+            let style = if in_selection { style.bg(Color::Blue) } else { style };
+
             line_content.push(Span::styled(format!("{} ", symbol), style));
         }
 
         let paragraph = Paragraph::new(Line::from(line_content));
         f.render_widget(paragraph, track_area);
     }
-}
\ No newline at end of file
+}
+
+// ロックパネルで楽器の合成パラメータが始まる行（Param::ALL とノート・長さ・和音・アクセント・スライドの後）
+const LOCK_SOUND_ROW: usize = Param::ALL.len() + 5;
+
+// 音色パネルで楽器のパラメータの後に並ぶ、トラックのフィルターの行
+const FILTER_ROWS: [&str; 5] = ["Filter", "Cutoff", "Resonance", "Env", "Env Decay"];
+
+// ファイル名（拡張子なし）をサンプルの名前にする
+fn sample_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Sampler".to_string())
+}
+
+// MIDI ノート番号の音名（60 = C4）
+fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+fn bank_name(bank: usize) -> char {
+    (b'A' + bank as u8) as char
+}
+
+fn draw_lock_panel(f: &mut Frame, area: Rect, app: &App) {
+    let track = &app.tracks[app.selected_track];
+    let step = &app.pattern.steps[app.selected_track][app.selected_step];
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("P-Lock: {} #{}", track.name, app.selected_step + 1));
+
+    let locked = Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD);
+    let unlocked = Style::default().fg(Color::DarkGray);
+    let row = |idx: usize, name: &str, value: String, style: Style| {
+        let selected = app.lock_param == Some(idx);
+        let cursor = if selected { "▶" } else { " " };
+        let style = if selected { style.add_modifier(Modifier::REVERSED) } else { style };
+        Line::from(vec![
+            Span::raw(format!("{} {:10}", cursor, name)),
+            Span::styled(format!("{:>10}", value), style),
+        ])
+    };
+    let mut lines: Vec<Line> = Param::ALL
+        .iter()
+        .enumerate()
+        .map(|(idx, &param)| match step.lock(param) {
+            Some(v) => row(idx, param.name(), param.format(v), locked),
+            None => row(idx, param.name(), param.format(track.param(param)), unlocked),
+        })
+        .collect();
+
+    // ステップのノート（スライサーではスライスの番号）
+    let default_note = track.instrument.default_note();
+    let style = if step.note != default_note { locked } else { unlocked };
+    let note_row = match app.slicer(app.selected_track) {
+        Some((sample, values)) => {
+            let count = Slicer::points(sample, &values).len() as i32;
+            let slice = (step.note as i32 - default_note as i32).rem_euclid(count);
+            row(Param::ALL.len(), "Slice", format!("{}/{}", slice + 1, count), style)
+        }
+        None => row(Param::ALL.len(), "Note", note_name(step.note), style),
+    };
+    lines.push(note_row);
+    let style = if step.length != 1 { locked } else { unlocked };
+    let length = match step.length {
+        1 => "1 step".to_string(),
+        n => format!("{} steps", n),
+    };
+    lines.push(row(Param::ALL.len() + 1, "Length", length, style));
+    let style = if step.chord != 0 { locked } else { unlocked };
+    let chord = CHORDS[step.chord as usize].0;
+    lines.push(row(Param::ALL.len() + 2, "Chord", chord.to_string(), style));
+    let flag = |on: bool| if on { "on" } else { "off" }.to_string();
+    let style = if step.accent { locked } else { unlocked };
+    lines.push(row(Param::ALL.len() + 3, "Accent", flag(step.accent), style));
+    let style = if step.slide { locked } else { unlocked };
+    lines.push(row(Param::ALL.len() + 4, "Slide", flag(step.slide), style));
+
+    // 楽器の合成パラメータ
+    if let Some(instrument) = app.registry.get(track.instrument) {
+        let values = instrument.values(&track.sound);
+        for (idx, (spec, value)) in instrument.params().iter().zip(values).enumerate() {
+            lines.push(match step.sound_lock(idx) {
+                Some(v) => row(LOCK_SOUND_ROW + idx, spec.name, spec.format(v), locked),
+                None => row(LOCK_SOUND_ROW + idx, spec.name, spec.format(value), unlocked),
+            });
+        }
+    }
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_sound_panel(f: &mut Frame, area: Rect, app: &App) {
+    let track = &app.tracks[app.selected_track];
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Sound: {} ({})", track.name, track.instrument.name()));
+
+    // 既定値から変えた値は色を付ける
+    let row = |idx: usize, name: &str, value: String, changed: bool| {
+        let selected = app.sound_param == Some(idx);
+        let cursor = if selected { "▶" } else { " " };
+        let style = if changed {
+            Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let style = if selected { style.add_modifier(Modifier::REVERSED) } else { style };
+        Line::from(vec![
+            Span::raw(format!("{} {:10}", cursor, name)),
+            Span::styled(format!("{:>10}", value), style),
+        ])
+    };
+
+    let mut lines: Vec<Line> = match app.registry.get(track.instrument) {
+        Some(instrument) => instrument
+            .params()
+            .iter()
+            .zip(instrument.values(&track.sound))
+            .enumerate()
+            .map(|(idx, (spec, value))| row(idx, spec.name, spec.format(value), value != spec.default))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // トラックのフィルター
+    let count = lines.len();
+    let default = Track::new(&track.name, track.instrument);
+    let values = [
+        (track.filter_mode.name().to_string(), track.filter_mode != default.filter_mode),
+        (
+            Param::FilterCutoff.format(track.filter_cutoff),
+            track.filter_cutoff != default.filter_cutoff,
+        ),
+        (
+            Param::FilterResonance.format(track.filter_resonance),
+            track.filter_resonance != default.filter_resonance,
+        ),
+        (Param::FilterEnv.format(track.filter_env), track.filter_env != default.filter_env),
+        (format!("{:.2} s", track.filter_decay), track.filter_decay != default.filter_decay),
+    ];
+    for (i, (name, (value, changed))) in FILTER_ROWS.iter().zip(values).enumerate() {
+        lines.push(row(count + i, name, value, changed));
+    }
+
+    if matches!(track.instrument, InstrumentType::Sampler | InstrumentType::Slicer) {
+        let file = match &track.sample {
+            Some(path) => format!("  {}", sample_name(path)),
+            None => "  (no sample: press W)".to_string(),
+        };
+        let mut info = vec![Line::from(Span::styled(file, Style::default().fg(Color::Cyan)))];
+        if let Some((sample, values)) = app.slicer(app.selected_track) {
+            let count = Slicer::points(sample, &values).len();
+            let bpm = sampler::loop_bpm(sample, values[2]);
+            info.push(Line::from(Span::styled(
+                format!("  {} slices, loop {:.1} BPM", count, bpm),
+                Style::default().fg(Color::Cyan),
+            )));
+            info.push(Line::from(Span::styled(
+                "  A lay out steps, T match tempo",
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+        lines.splice(0..0, info);
+    }
+
+    // 用意された音色があれば、今どれを使っているか
+    if let Some(instrument) = app.registry.get(track.instrument) {
+        let values = instrument.values(&track.sound);
+        let presets = instrument.presets();
+        if !presets.is_empty() {
+            let name = presets
+                .iter()
+                .find(|(_, preset)| *preset == values.as_slice())
+                .map_or("custom", |(name, _)| name);
+            lines.insert(
+                0,
+                Line::from(Span::styled(
+                    format!("  Preset: {} (P next)", name),
+                    Style::default().fg(Color::Cyan),
+                )),
+            );
+        }
+    }
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_mod_panel(f: &mut Frame, area: Rect, app: &App) {
+    let track = &app.tracks[app.selected_track];
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Modulation: {}", track.name));
+
+    let row = |idx: usize, name: String, value: String| {
+        let selected = app.mod_row == Some(idx);
+        let cursor = if selected { "▶" } else { " " };
+        let style = if selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        Line::from(vec![
+            Span::raw(format!("{} {:16}", cursor, name)),
+            Span::styled(format!("{:>10}", value), style),
+        ])
+    };
+
+    // プロジェクト全体の LFO
+    let mut lines = Vec::new();
+    for (i, lfo) in app.settings.lfos.iter().enumerate() {
+        let name = ModSource::Lfo(i).name();
+        let sync = if lfo.sync { "on" } else { "off" };
+        lines.push(row(i * 3, format!("{} Shape", name), lfo.shape.name().to_string()));
+        lines.push(row(i * 3 + 1, format!("{} Sync", name), sync.to_string()));
+        lines.push(row(i * 3 + 2, format!("{} Rate", name), lfo.rate_name()));
+    }
+
+    // 選択中のトラックのルート
+    lines.push(Line::from(Span::styled(
+        format!("  Routes ({}/{})", track.mods.len(), MAX_ROUTES),
+        Style::default().fg(Color::Cyan),
+    )));
+    if track.mods.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "  N add a route",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    for (r, route) in track.mods.iter().enumerate() {
+        let name = format!("{} > {}", route.source.name(), route.target.name());
+        lines.push(row(LFO_COUNT * 3 + r, name, format!("{:+.2}", route.depth)));
+    }
+    if app.selected_route().is_some() {
+        lines.push(Line::from(Span::styled(
+            "  O source, T target, ←→ depth",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_fx_panel(f: &mut Frame, area: Rect, app: &App) {
+    let track = &app.tracks[app.selected_track];
+    let block = Block::default().borders(Borders::ALL).title("Effects (N add, B bypass)");
+    let cursor = app.fx_cursor();
+
+    let lines: Vec<Line> = app
+        .fx_rows()
+        .into_iter()
+        .map(|row| {
+            let selected = cursor == Some(row);
+            let mark = if selected { "▶" } else { " " };
+            let highlight = |style: Style| {
+                if selected {
+                    style.add_modifier(Modifier::REVERSED)
+                } else {
+                    style
+                }
+            };
+            match row {
+                FxRow::Chain { chain } => {
+                    let name = match chain {
+                        FxChain::Track => format!("Track: {}", track.name),
+                        FxChain::Send => format!("Send (from {}: {:.2})", track.name, track.send),
+                        FxChain::Master => "Master".to_string(),
+                    };
+                    let count = app.chain(chain).len();
+                    Line::from(vec![
+                        Span::raw(format!("{} ", mark)),
+                        Span::styled(
+                            format!("{} ({}/{})", name, count, MAX_EFFECTS),
+                            highlight(Style::default().fg(Color::Cyan)),
+                        ),
+                    ])
+                }
+                FxRow::Slot { chain, slot } => {
+                    let effect = &app.chain(chain)[slot];
+                    let state = if effect.bypass { "bypass" } else { "on" };
+                    let style = if effect.bypass {
+                        Style::default().fg(Color::DarkGray)
+                    } else {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    };
+                    let mut spans = vec![
+                        Span::raw(format!("{} {}. ", mark, slot + 1)),
+                        Span::styled(format!("{:14}", effect.kind.name()), highlight(style)),
+                        Span::raw(format!("{:>10}", state)),
+                    ];
+                    // ゲインリダクション：3dB ごとに1マス
+                    if let Some(reduction) = app.meter(chain, slot).filter(|_| !effect.bypass) {
+                        let bar = "▮".repeat(((reduction / 3.0).round() as usize).min(8));
+                        spans.push(Span::styled(format!("  GR {:>5.1} ", -reduction), Style::default().fg(Color::DarkGray)));
+                        spans.push(Span::styled(bar, Style::default().fg(Color::Red)));
+                    }
+                    Line::from(spans)
+                }
+                FxRow::Param { chain, slot, param } => {
+                    let effect = &app.chain(chain)[slot];
+                    let specs = effects::params(effect.kind);
+                    let value = effects::values(specs, &effect.values)[param];
+                    let spec = &specs[param];
+                    let style = if value != spec.default {
+                        Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    };
+                    Line::from(vec![
+                        Span::raw(format!("{}    {:10}", mark, spec.name)),
+                        Span::styled(format!("{:>12}", spec.format(value)), highlight(style)),
+                    ])
+                }
+                FxRow::Tail => {
+                    let tail = match app.settings.export_tail {
+                        Some(tail) => format!("{:.1} s", tail),
+                        None => "Auto".to_string(),
+                    };
+                    Line::from(vec![
+                        Span::raw(format!("{} Export tail", mark)),
+                        Span::styled(format!("{:>16}", tail), highlight(Style::default().fg(Color::Cyan))),
+                    ])
+                }
+            }
+        })
+        .collect();
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_song_panel(f: &mut Frame, area: Rect, app: &App) {
+    let block = Block::default().borders(Borders::ALL).title("Song (N add, P ramp)");
+
+    let mut lines: Vec<Line> = app
+        .settings
+        .song
+        .sections
+        .iter()
+        .enumerate()
+        .map(|(idx, section)| {
+            let cursor = if app.song_cursor == Some(idx) { "▶" } else { " " };
+            let playing = app.playing && app.playing_section == Some(idx);
+            let style = if app.song_cursor == Some(idx) {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else if playing {
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(
+                format!(
+                    "{} {:2}. {} x{:<2} {:>6.1} BPM{}",
+                    cursor,
+                    idx + 1,
+                    bank_name(section.bank),
+                    section.repeats,
+                    section.bpm,
+                    if section.ramp { " ↗" } else { "" }
+                ),
+                style,
+            ))
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "  (empty)",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_mixer(f: &mut Frame, area: Rect, app: &App) {
+    let block = Block::default().borders(Borders::ALL).title("Mixer");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let strips = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Length(12); app.tracks.len()])
+        .split(inner);
+
+    for (idx, (track, &strip)) in app.tracks.iter().zip(strips.iter()).enumerate() {
+        let selected = idx == app.selected_track;
+        let border = if selected { Color::Yellow } else { Color::DarkGray };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border))
+            .title(track.name.as_str());
+        let area = block.inner(strip);
+        f.render_widget(block, strip);
+
+        // フェーダー：下から音量の分だけ埋める（残りの5行は数値・パン・センド・M/S・チョーク）
+        let height = area.height.saturating_sub(5) as usize;
+        let filled = (track.volume * height as f32).round() as usize;
+        let fader_color = if audible(&app.tracks, idx) { Color::Green } else { Color::DarkGray };
+        let mut lines: Vec<Line> = (0..height)
+            .map(|row| {
+                if height - row <= filled {
+                    Line::from(Span::styled("   ███", Style::default().fg(fader_color)))
+                } else {
+                    Line::from(Span::styled("    │", Style::default().fg(Color::DarkGray)))
+                }
+            })
+            .collect();
+
+        lines.push(Line::from(format!("  {:.2}", track.volume)));
+
+        // パン：L───R の上の位置
+        let pos = ((track.pan + 1.0) / 2.0 * 6.0).round() as usize;
+        let pan: String = (0..7).map(|i| if i == pos { '●' } else { '─' }).collect();
+        lines.push(Line::from(format!("L{}R", pan)));
+        lines.push(Line::from(Span::styled(
+            format!("  send {:.2}", track.send),
+            Style::default().fg(if track.send > 0.0 { Color::Cyan } else { Color::DarkGray }),
+        )));
+
+        let flag = |on: bool, label: &'static str, color: Color| {
+            if on {
+                Span::styled(label, Style::default().fg(Color::Black).bg(color))
+            } else {
+                Span::styled(label, Style::default().fg(Color::DarkGray))
+            }
+        };
+        lines.push(Line::from(vec![
+            Span::raw("  "),
+            flag(track.mute, " M ", Color::Red),
+            Span::raw(" "),
+            flag(track.solo, " S ", Color::Yellow),
+        ]));
+        lines.push(Line::from(Span::styled(
+            match track.choke {
+                0 => "  choke -".to_string(),
+                group => format!("  choke {}", group),
+            },
+            Style::default().fg(if track.choke == 0 { Color::DarkGray } else { Color::Cyan }),
+        )));
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1トラックだけのシーケンス
+    fn sequence(instrument: InstrumentType) -> Sequence {
+        let tracks = vec![Track::new("Test", instrument)];
+        let pattern = Pattern::new(&tracks, 16);
+        Sequence {
+            samples: vec![None],
+            patterns: vec![pattern; BANK_COUNT],
+            tracks,
+            bank: 0,
+            bpm: 120.0,
+            song: None,
+            lfos: [Lfo::default(); LFO_COUNT],
+            send_bus: Vec::new(),
+            master: Vec::new(),
+        }
+    }
+
+    fn sounding(player: &Player) -> usize {
+        player.voices.iter().filter(|v| v.release_at.is_none()).count()
+    }
+
+    // 再生位置が position を過ぎるまで少しずつ鳴らす
+    fn play_until(player: &mut Player, position: f32) {
+        let (mut left, mut right) = ([0.0; 32], [0.0; 32]);
+        while player.position() < position {
+            player.render(&mut left, &mut right);
+        }
+    }
+
+    // ステップ0をスライドにしたアシッドのトラック
+    fn slide_into(step: usize) -> Player {
+        let mut seq = sequence(InstrumentType::Acid);
+        let steps = &mut seq.patterns[0].steps[0];
+        steps[0].active = true;
+        steps[0].slide = true;
+        steps[step].active = true;
+        let mut player = Player::new(44100.0, seq);
+        player.play();
+        player
+    }
+
+    #[test]
+    fn slide_holds_the_voice_into_the_next_step() {
+        let mut player = slide_into(1);
+        play_until(&mut player, 1.01);
+        assert_eq!(player.voices.len(), 1);
+    }
+
+    #[test]
+    fn slide_does_not_reach_past_its_gate() {
+        // 間に空のステップがあれば新しい音を鳴らし、前の音はフェードアウトさせる
+        let mut player = slide_into(2);
+        play_until(&mut player, 2.01);
+        assert_eq!(sounding(&player), 1);
+        assert_eq!(player.voices.len(), 2);
+    }
+
+    #[test]
+    fn every_lock_reaches_the_trigger() {
+        // パラメータを足したらここにも足す（match で漏れに気づける）
+        let track = Track::new("Bass", InstrumentType::Bass);
+        let value = |trigger: &Trigger, param: Param| match param {
+            Param::Volume => trigger.hit.gain,
+            Param::Pan => trigger.pan,
+            Param::FilterCutoff => trigger.cutoff.unwrap_or(track.filter_cutoff),
+            Param::FilterResonance => trigger.resonance.unwrap_or(track.filter_resonance),
+            Param::FilterEnv => trigger.filter_env,
+            Param::Pitch => trigger.hit.pitch,
+            Param::Decay => trigger.hit.decay,
+        };
+        let plain = Step::new(36);
+        for param in Param::ALL {
+            let base = track.param(param);
+            let mut lock = param.nudge(base, 1);
+            if lock == base {
+                lock = param.nudge(base, -1);
+            }
+            let mut step = plain.clone();
+            step.set_lock(param, lock);
+            let locked = step_trigger(0, &track, &step);
+            assert_eq!(value(&locked, param), lock, "{:?}", param);
+            assert_ne!(value(&locked, param), value(&step_trigger(0, &track, &plain), param));
+        }
+    }
+
+    #[test]
+    fn quantize_pulls_hits_towards_the_step() {
+        let close = |(step, offset): (usize, f32), expected: (usize, f32)| {
+            step == expected.0 && (offset - expected.1).abs() < 1e-4
+        };
+        // 強さ 1.0 でぴったり、0.0 で叩いたまま、0.5 でその中間
+        assert!(close(quantize_hit(4.2, 16, 1.0), (4, 0.0)));
+        assert!(close(quantize_hit(4.2, 16, 0.0), (4, 0.2)));
+        assert!(close(quantize_hit(4.2, 16, 0.5), (4, 0.1)));
+        assert!(close(quantize_hit(3.7, 16, 0.5), (4, -0.15)));
+        // パターンの終わり近くの早めのヒットは頭のステップへ回り込む
+        assert!(close(quantize_hit(15.8, 16, 0.0), (0, -0.2)));
+        assert!(close(quantize_hit(31.6, 16, 1.0), (0, 0.0)));
+    }
+
+    #[test]
+    fn hits_are_recorded_into_the_playing_bank() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        app.recording = true;
+        app.playing = true;
+        app.record_hit(0, 2, 4.0);
+        assert!(app.banks[2].steps[0][4].active);
+        assert!(!app.pattern.steps[0][4].active);
+
+        // 置き換え録音はそのバンクのトラックだけを空にする
+        app.overdub = false;
+        app.pattern.steps[0][1].active = true;
+        app.record_hit(0, 2, 8.0);
+        assert!(!app.banks[2].steps[0][4].active);
+        assert!(app.banks[2].steps[0][8].active);
+        assert!(app.pattern.steps[0][1].active);
+
+        app.undo();
+        app.undo();
+        assert!(app.banks[2].steps[0][4].active);
+    }
+
+    #[test]
+    fn track_edits_keep_every_bank_in_step() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        let count = app.tracks.len();
+        let rows = |app: &App| {
+            app.banks.iter().all(|b| b.steps.len() == app.tracks.len())
+                && app.pattern.steps.len() == app.tracks.len()
+        };
+        app.selected_track = 0;
+        app.banks[1].steps[0][3].active = true;
+
+        app.duplicate_track();
+        assert_eq!(app.tracks.len(), count + 1);
+        assert_eq!(app.selected_track, 1);
+        assert_eq!(app.tracks[1].name, format!("{} 2", app.tracks[0].name));
+        assert!(app.banks[1].steps[1][3].active);
+        assert!(rows(&app));
+
+        app.add_track();
+        assert_eq!(app.tracks.len(), count + 2);
+        assert!(app.banks.iter().all(|b| b.steps[2].iter().all(|s| !s.active)));
+        assert!(rows(&app));
+
+        app.move_track(-1);
+        assert_eq!(app.selected_track, 1);
+        assert!(app.banks[1].steps[2][3].active);
+
+        app.remove_track();
+        assert_eq!(app.tracks.len(), count + 1);
+        assert!(rows(&app));
+
+        app.undo();
+        app.undo();
+        app.undo();
+        app.undo();
+        assert_eq!(app.tracks.len(), count);
+        assert!(app.banks[1].steps[0][3].active);
+        assert!(rows(&app));
+    }
+
+    #[test]
+    fn sound_edits_are_clamped_and_undoable() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        app.selected_track = 0;
+        app.edit_track(None, |track| track.instrument = InstrumentType::Kick);
+        app.sound_param = Some(0); // Pitch: 20..200 Hz、既定 40 Hz
+        app.nudge_sound(1);
+        assert_eq!(app.tracks[0].sound, [44.5, 110.0, 0.3, 2.0]);
+
+        for _ in 0..100 {
+            app.nudge_sound(1);
+        }
+        assert_eq!(app.tracks[0].sound[0], 200.0);
+
+        // 続けて動かした分はまとめて1回で戻る
+        app.undo();
+        assert!(app.tracks[0].sound.is_empty());
+        app.redo();
+        assert_eq!(app.tracks[0].sound[0], 200.0);
+        app.reset_sound();
+        assert!(app.tracks[0].sound.is_empty());
+    }
+
+    #[test]
+    fn track_count_is_capped() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        while app.tracks.len() < MAX_TRACKS {
+            app.add_track();
+        }
+        app.add_track();
+        assert_eq!(app.tracks.len(), MAX_TRACKS);
+        assert!(app.export_message.is_some());
+
+        // 最後の1本は消せない
+        while app.tracks.len() > 1 {
+            app.remove_track();
+        }
+        app.remove_track();
+        assert_eq!(app.tracks.len(), 1);
+    }
+
+    // ステップ0を鳴らした頭のブロック (左, 右)
+    fn first_block(seq: Sequence) -> (Vec<f32>, Vec<f32>) {
+        let mut player = Player::new(44100.0, seq);
+        player.play();
+        let (mut left, mut right) = (vec![0.0; 2048], vec![0.0; 2048]);
+        player.render(&mut left, &mut right);
+        (left, right)
+    }
+
+    fn peak(block: &[f32]) -> f32 {
+        block.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn mixer_settings_reach_the_output() {
+        let mut seq = sequence(InstrumentType::Kick);
+        seq.patterns[0].steps[0][0].active = true;
+        let (left, right) = first_block(seq.clone());
+        assert!(peak(&left) > 0.05);
+        assert_eq!(left, right);
+
+        // パンは振った側の反対を絞る
+        seq.tracks[0].pan = -1.0;
+        let (panned, silent) = first_block(seq.clone());
+        assert_eq!((panned, peak(&silent)), (left.clone(), 0.0));
+
+        // 音量はそのまま掛かる
+        seq.tracks[0].pan = 0.0;
+        seq.tracks[0].volume = 0.5;
+        let (half, _) = first_block(seq.clone());
+        assert!(half.iter().zip(&left).all(|(h, l)| (h - l * 0.5).abs() < 1e-5));
+
+        // ミュートしたトラックは聞こえず、ソロの他のトラックも聞こえない
+        seq.tracks[0].mute = true;
+        assert_eq!(peak(&first_block(seq.clone()).0), 0.0);
+        seq.tracks[0].mute = false;
+        seq.tracks.push(Track::new("Solo", InstrumentType::Kick));
+        seq.tracks[1].solo = true;
+        seq.samples.push(None);
+        for pattern in &mut seq.patterns {
+            pattern.steps.push(vec![Step::new(36); pattern.length]);
+        }
+        assert_eq!(peak(&first_block(seq).0), 0.0);
+    }
+
+    #[test]
+    fn tap_tempo_averages_the_last_taps() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        let ago = |ms: &[u64]| {
+            let now = Instant::now();
+            ms.iter().map(|&ms| now - Duration::from_millis(ms)).collect::<Vec<_>>()
+        };
+        // 最後のタップから2秒空いたら数え直す（1回だけではテンポを変えない）
+        app.set_bpm(90.0);
+        app.taps = ago(&[3000]);
+        app.tap_tempo();
+        assert_eq!((app.settings.bpm, app.taps.len()), (90.0, 1));
+
+        app.taps = ago(&[1500, 1000, 500]);
+        app.tap_tempo();
+        assert_eq!(app.settings.bpm, 120.0);
+
+        // 打ち込んだ BPM は範囲に収める
+        app.bpm_input = Some("1000".to_string());
+        app.confirm_bpm_input();
+        assert_eq!(app.settings.bpm, MAX_BPM);
+        app.bpm_input = Some("fast".to_string());
+        app.confirm_bpm_input();
+        assert_eq!(app.settings.bpm, MAX_BPM);
+    }
+
+    #[test]
+    fn tempo_ramps_over_the_section() {
+        let mut seq = sequence(InstrumentType::Kick);
+        let section = |bpm, ramp| Section { bank: 0, repeats: 2, bpm, ramp };
+        seq.song = Some(Song { sections: vec![section(100.0, true), section(140.0, false)] });
+        let mut player = Player::new(44100.0, seq);
+        player.play();
+        assert_eq!(player.tempo(), 100.0);
+        // 2回繰り返すセクションの1回目の終わりで半分
+        play_until(&mut player, 15.99);
+        assert!((player.tempo() - 120.0).abs() < 0.1, "{}", player.tempo());
+    }
+
+    #[test]
+    fn sound_locks_reach_the_instrument() {
+        let render = |sound: f32, lock: Option<f32>| {
+            let mut seq = sequence(InstrumentType::Kick);
+            let kick = Registry::new();
+            let mut values = kick.get(InstrumentType::Kick).unwrap().values(&[]);
+            values[0] = sound;
+            seq.tracks[0].sound = values;
+            let step = &mut seq.patterns[0].steps[0][0];
+            step.active = true;
+            if let Some(value) = lock {
+                step.set_sound_lock(0, value);
+            }
+            first_block(seq).0
+        };
+        // Pitch をロックした音は、トラックの Pitch をその値にした音と同じ
+        let plain = render(40.0, None);
+        let locked = render(40.0, Some(120.0));
+        assert_ne!(locked, plain);
+        assert_eq!(locked, render(120.0, None));
+    }
+
+    #[test]
+    fn send_bus_adds_a_reverb_tail() {
+        // 頭のキックの 0.6〜0.8 秒後のエネルギーと、エクスポートの余韻の長さ
+        let render = |send: f32| {
+            let mut seq = sequence(InstrumentType::Kick);
+            seq.patterns[0].steps[0][0].active = true;
+            seq.tracks[0].send = send;
+            seq.send_bus = vec![effects::default_reverb()];
+            let mut player = Player::new(44100.0, seq);
+            player.play();
+            let (mut left, mut right) = (vec![0.0; 2205], vec![0.0; 2205]);
+            let mut late = 0.0;
+            for block in 0..16 {
+                player.render(&mut left, &mut right);
+                if block >= 12 {
+                    late += left.iter().map(|s| s * s).sum::<f32>();
+                }
+            }
+            (late, player.tail())
+        };
+        let (dry, dry_tail) = render(0.0);
+        let (wet, wet_tail) = render(0.5);
+        assert!(wet > dry * 10.0 + 1e-6, "{} vs {}", wet, dry);
+        assert_eq!(dry_tail, 0.0);
+        assert!(wet_tail > 1.0);
+    }
+
+    #[test]
+    fn muted_kick_ducks_the_bass() {
+        // キックはミュートしたまま、ベースのコンプレッサーのキーにする
+        let render = |keyed: bool| {
+            let mut seq = sequence(InstrumentType::Kick);
+            seq.tracks[0].mute = true;
+            let mut bass = Track::new("Bass", InstrumentType::Bass);
+            if keyed {
+                let mut values = effects::values(effects::params(EffectType::Compressor), &[]);
+                values[0] = -40.0; // Threshold
+                values[5] = 1.0; // Key: トラック 1
+                bass.effects = vec![EffectSlot { values, ..EffectSlot::new(EffectType::Compressor) }];
+            }
+            seq.tracks.push(bass);
+            seq.samples.push(None);
+            seq.patterns = vec![Pattern::new(&seq.tracks, 16); BANK_COUNT];
+            seq.patterns[0].steps[0][0].active = true;
+            seq.patterns[0].steps[1][0].active = true;
+            let (left, _) = first_block(seq);
+            left[1024..].iter().map(|s| s * s).sum::<f32>()
+        };
+        let (plain, ducked) = (render(false), render(true));
+        assert!(ducked < plain / 4.0, "{} vs {}", ducked, plain);
+    }
+
+    #[test]
+    fn pad_hits_are_recorded_where_they_were_pressed() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        app.recording = true;
+        app.playing = true;
+        // 250ms 前にステップ 2 の頭だった（120 BPM で 1秒に 8ステップ）
+        app.clock = Some(Clock {
+            at: Instant::now() - Duration::from_millis(250),
+            bank: 2,
+            position: 2.0,
+            steps_per_second: 8.0,
+        });
+        app.pad_hit(0);
+        assert!(app.banks[2].steps[0][4].active);
+    }
+
+    #[test]
+    fn unchanged_sequence_is_not_sent_again() {
+        let mut app = App::new(PathBuf::from("test.project"));
+        let sent = |app: &App| app.audio_commands.lock().unwrap().len();
+        app.sync_audio();
+        assert_eq!(sent(&app), 1);
+
+        // カーソルを動かしただけでは送らない
+        app.selected_step = 3;
+        app.sync_audio();
+        assert_eq!(sent(&app), 1);
+
+        app.edit_step(None, |s| s.active = !s.active);
+        app.sync_audio();
+        assert_eq!(sent(&app), 2);
+    }
+
+    #[test]
+    fn lead_plays_chords() {
+        let mut player = Player::new(44100.0, sequence(InstrumentType::Lead));
+        let mut step = Step::new(60);
+        step.chord = 1; // maj
+        let trigger = step_trigger(0, &player.seq.tracks[0], &step);
+        player.trigger(trigger);
+        assert_eq!(sounding(&player), 3);
+    }
+
+    #[test]
+    fn chords_never_exceed_polyphony() {
+        for instrument in [InstrumentType::Pad, InstrumentType::Lead, InstrumentType::Acid, InstrumentType::Snare] {
+            let mut player = Player::new(44100.0, sequence(instrument));
+            let limit = player.registry.get(instrument).unwrap().polyphony();
+            for chord in 0..CHORDS.len() {
+                let mut step = Step::new(60);
+                step.chord = chord as u8;
+                let trigger = step_trigger(0, &player.seq.tracks[0], &step);
+                player.trigger(trigger);
+                assert!(sounding(&player) <= limit, "{:?} chord {}", instrument, chord);
+            }
+        }
+    }
+}
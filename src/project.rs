@@ -0,0 +1,716 @@
+// src/project.rs
+// プロジェクトファイルの保存と読み込み（1行1項目のテキスト形式）
+//
+//   rhythm-box-project 1
+//   bpm 120
+//   bank 0
+//   kit kits/909          <- ドラムキットのフォルダ
+//   section 0 2 120 ramp  <- ソング: バンク 繰り返し BPM [ramp]
+//   lfo 0 shape=Tri sync=1 steps=16 rate=1  <- LFO（既定値から変えたものだけ）
+//   send_effect Reverb Mix=1                <- センドバスのエフェクト
+//   master_effect Utility Gain=-3 Width=1   <- マスターのエフェクト（上から順にかける）
+//   export_tail 4         <- エクスポートで書き足す余韻（秒。なければエフェクトから求める）
+//   track Kick            <- 以降の行はこのトラックの設定
+//   instrument Kick
+//   Volume 1
+//   mute                  <- ミュート/ソロ中のときだけ
+//   choke 1               <- チョークグループ（0 はなし）
+//   send 0.25             <- センドバスへ送る量
+//   filter LowPass        <- フィルターの種類
+//   filter_decay 0.3      <- フィルターエンベロープの長さ
+//   sample kits/kick.wav  <- サンプラーの WAV ファイル
+//   sound Pitch=40 Decay=0.3  <- 楽器の合成パラメータ（変えたときだけ）
+//   mod LFO1 Cutoff 0.5   <- モジュレーションのルート: 送り元 送り先 深さ
+//   effect Utility bypass Gain=2 Width=1.5  <- インサートのエフェクト（値は変えたときだけ）
+//   pattern 0             <- 以降の行はバンク0のパターン（トラックの後に書く）
+//   length 16
+//   steps 0 x...x...x...x...
+//   step 3 4 velocity=100 Pitch=3
+//   step 4 0 velocity=127 note=57 offset=0 length=4 chord=min  <- 伸ばす長さと和音
+//   step 5 2 velocity=127 note=39 offset=0 accent=1 slide=1     <- アクセントとスライド
+//   step 6 0 velocity=127 note=36 offset=0 sound.Pitch=60       <- 楽器の合成パラメータのロック
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::effects::{self, MAX_EXPORT_TAIL};
+use crate::audio::voice::Registry;
+use crate::sequencer::pattern::{
+    EffectSlot, EffectType, FilterMode, InstrumentType, Param, Pattern, Step, Track, BANK_COUNT,
+    CHOKE_GROUPS, CHORDS, MAX_EFFECTS, MAX_FILTER_DECAY, MAX_LENGTH, MAX_NOTE_LENGTH, MAX_TRACKS,
+    MIN_FILTER_DECAY,
+};
+use crate::sequencer::modulation::{
+    Lfo, LfoShape, ModSource, Route, LFO_COUNT, MAX_LFO_RATE, MAX_ROUTES, MIN_LFO_RATE,
+    SYNC_LENGTHS,
+};
+use crate::sequencer::song::{Section, Song, MAX_BPM, MIN_BPM};
+
+const HEADER: &str = "rhythm-box-project 1";
+
+pub struct Project {
+    pub bpm: f32,
+    pub tracks: Vec<Track>,
+    pub patterns: Vec<Pattern>, // バンクごと
+    pub bank: usize,
+    pub song: Song,
+    pub kit: Option<PathBuf>, // ドラムキットのフォルダ（中身ではなく場所を保存する）
+    pub lfos: [Lfo; LFO_COUNT],
+    pub send_bus: Vec<EffectSlot>, // センドバスのエフェクトチェーン
+    pub master: Vec<EffectSlot>,   // マスターのエフェクトチェーン
+    pub export_tail: Option<f32>,  // エクスポートの余韻（秒）
+}
+
+// エフェクト1つ分の行（key の後に種類、bypass、変えた値）
+fn write_effect(out: &mut String, key: &str, effect: &EffectSlot) -> std::fmt::Result {
+    write!(out, "{} {}", key, effect.kind.name())?;
+    if effect.bypass {
+        write!(out, " bypass")?;
+    }
+    if !effect.values.is_empty() {
+        let specs = effects::params(effect.kind);
+        for (spec, value) in specs.iter().zip(effects::values(specs, &effect.values)) {
+            write!(out, " {}={}", spec.name.replace(' ', ""), value)?;
+        }
+    }
+    writeln!(out)
+}
+
+// write_effect の行の key より後を読む
+fn parse_effect(value: &str) -> Result<EffectSlot, &'static str> {
+    let mut fields = value.split_whitespace();
+    let kind = fields.next().ok_or("expected effect type")?;
+    let kind = *EffectType::ALL
+        .iter()
+        .find(|k| k.name() == kind)
+        .ok_or("unknown effect type")?;
+    let mut effect = EffectSlot::new(kind);
+    let specs = effects::params(kind);
+    for field in fields {
+        if field == "bypass" {
+            effect.bypass = true;
+            continue;
+        }
+        let (name, v) = field.split_once('=').ok_or("expected name=value")?;
+        let (idx, spec) = specs
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.name.replace(' ', "") == name)
+            .ok_or("unknown effect parameter")?;
+        let v: f32 = v.parse().map_err(|_| "invalid value")?;
+        if effect.values.is_empty() {
+            effect.values = effects::values(specs, &[]);
+        }
+        effect.values[idx] = v.clamp(spec.min, spec.max);
+    }
+    Ok(effect)
+}
+
+pub fn save(path: &Path, project: &Project) -> Result<(), Box<dyn Error>> {
+    let registry = Registry::new();
+    let mut out = String::new();
+    writeln!(out, "{}", HEADER)?;
+    writeln!(out, "bpm {}", project.bpm)?;
+    writeln!(out, "bank {}", project.bank)?;
+    if let Some(kit) = &project.kit {
+        writeln!(out, "kit {}", kit.display())?;
+    }
+    for section in &project.song.sections {
+        write!(out, "section {} {} {}", section.bank, section.repeats, section.bpm)?;
+        writeln!(out, "{}", if section.ramp { " ramp" } else { "" })?;
+    }
+    for (i, lfo) in project.lfos.iter().enumerate() {
+        if *lfo == Lfo::default() {
+            continue;
+        }
+        writeln!(
+            out,
+            "lfo {} shape={} sync={} steps={} rate={}",
+            i,
+            lfo.shape.name(),
+            lfo.sync as u8,
+            SYNC_LENGTHS[lfo.length].1,
+            lfo.rate
+        )?;
+    }
+    for effect in &project.send_bus {
+        write_effect(&mut out, "send_effect", effect)?;
+    }
+    for effect in &project.master {
+        write_effect(&mut out, "master_effect", effect)?;
+    }
+    if let Some(tail) = project.export_tail {
+        writeln!(out, "export_tail {}", tail)?;
+    }
+
+    for track in &project.tracks {
+        writeln!(out)?;
+        writeln!(out, "track {}", track.name)?;
+        writeln!(out, "instrument {}", track.instrument.name())?;
+        for param in Param::ALL {
+            writeln!(out, "{} {}", param.name(), track.param(param))?;
+        }
+        if track.mute {
+            writeln!(out, "mute")?;
+        }
+        if track.solo {
+            writeln!(out, "solo")?;
+        }
+        writeln!(out, "choke {}", track.choke)?;
+        writeln!(out, "send {}", track.send)?;
+        writeln!(out, "filter {}", track.filter_mode.name())?;
+        writeln!(out, "filter_decay {}", track.filter_decay)?;
+        if let Some(path) = &track.sample {
+            writeln!(out, "sample {}", path.display())?;
+        }
+        if let Some(instrument) = registry.get(track.instrument) {
+            if !track.sound.is_empty() {
+                write!(out, "sound")?;
+                for (spec, value) in instrument.params().iter().zip(instrument.values(&track.sound)) {
+                    // 名前の空白は詰める（"Env Mod" なら EnvMod=）
+                    write!(out, " {}={}", spec.name.replace(' ', ""), value)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        for route in &track.mods {
+            writeln!(out, "mod {} {} {}", route.source.name(), route.target.name(), route.depth)?;
+        }
+        for effect in &track.effects {
+            write_effect(&mut out, "effect", effect)?;
+        }
+    }
+
+    for (bank, pattern) in project.patterns.iter().enumerate() {
+        // 何も変えていないバンクは省略
+        if bank != project.bank && pattern.is_empty(&project.tracks) {
+            continue;
+        }
+        writeln!(out)?;
+        writeln!(out, "pattern {}", bank)?;
+        writeln!(out, "length {}", pattern.length)?;
+
+        for (track_idx, (track, steps)) in project.tracks.iter().zip(&pattern.steps).enumerate() {
+            let grid: String = steps.iter().map(|s| if s.active { 'x' } else { '.' }).collect();
+            writeln!(out, "steps {} {}", track_idx, grid)?;
+
+            // 既定値と違うステップだけ詳細を書く
+            let default_note = track.instrument.default_note();
+            for (idx, step) in steps.iter().enumerate() {
+                if step.velocity == 127
+                    && step.note == default_note
+                    && step.offset == 0.0
+                    && step.length == 1
+                    && step.chord == 0
+                    && !step.accent
+                    && !step.slide
+                    && !step.has_locks()
+                {
+                    continue;
+                }
+                write!(
+                    out,
+                    "step {} {} velocity={} note={} offset={}",
+                    track_idx, idx, step.velocity, step.note, step.offset
+                )?;
+                if step.length != 1 {
+                    write!(out, " length={}", step.length)?;
+                }
+                if step.chord != 0 {
+                    write!(out, " chord={}", CHORDS[step.chord as usize].0)?;
+                }
+                if step.accent {
+                    write!(out, " accent=1")?;
+                }
+                if step.slide {
+                    write!(out, " slide=1")?;
+                }
+                for (param, value) in &step.locks {
+                    write!(out, " {}={}", param.name(), value)?;
+                }
+                if let Some(instrument) = registry.get(track.instrument) {
+                    for &(idx, value) in &step.sound_locks {
+                        if let Some(spec) = instrument.params().get(idx) {
+                            write!(out, " sound.{}={}", spec.name.replace(' ', ""), value)?;
+                        }
+                    }
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+
+    fs::write(path, out)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Project, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let registry = Registry::new();
+    let mut lines = text.lines().enumerate();
+
+    match lines.next() {
+        Some((_, HEADER)) => {}
+        _ => return Err("not a rhythm-box project file".into()),
+    }
+
+    let mut bpm = 120.0;
+    let mut bank = 0;
+    let mut song = Song::default();
+    let mut kit = None;
+    let mut lfos = [Lfo::default(); LFO_COUNT];
+    let mut send_bus = Vec::new();
+    let mut master = Vec::new();
+    let mut export_tail = None;
+    let mut tracks: Vec<Track> = Vec::new();
+    let mut patterns: Vec<Option<Pattern>> = vec![None; BANK_COUNT];
+    let mut current: Option<usize> = None; // 読み込み中のパターン
+
+    for (line_no, line) in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {}", line_no + 1, msg);
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "track" => {
+                if current.is_some() {
+                    return Err(err("tracks must come before patterns").into());
+                }
+                if tracks.len() >= MAX_TRACKS {
+                    return Err(err("too many tracks").into());
+                }
+                tracks.push(Track::new(value, InstrumentType::Kick));
+                continue;
+            }
+            "pattern" => {
+                let idx: usize = value
+                    .parse()
+                    .ok()
+                    .filter(|&i| i < BANK_COUNT)
+                    .ok_or_else(|| err("invalid bank"))?;
+                patterns[idx] = Some(Pattern::new(&tracks, 16));
+                current = Some(idx);
+                continue;
+            }
+            _ => {}
+        }
+
+        if let Some(idx) = current {
+            let pattern = patterns[idx].as_mut().unwrap();
+            match key {
+                "length" => {
+                    let length: usize = value
+                        .parse()
+                        .ok()
+                        .filter(|&l| l > 0 && l <= MAX_LENGTH)
+                        .ok_or_else(|| err("invalid length"))?;
+                    for (row, track) in pattern.steps.iter_mut().zip(&tracks) {
+                        row.resize(length, Step::new(track.instrument.default_note()));
+                    }
+                    pattern.length = length;
+                }
+                "steps" => {
+                    let (t, grid) = value
+                        .split_once(' ')
+                        .ok_or_else(|| err("expected track and steps"))?;
+                    let row = t
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|t| pattern.steps.get_mut(t))
+                        .ok_or_else(|| err("invalid track"))?;
+                    for (step, c) in row.iter_mut().zip(grid.chars()) {
+                        step.active = c == 'x';
+                    }
+                }
+                "step" => {
+                    let mut fields = value.split_whitespace();
+                    let track = fields.next().and_then(|t| t.parse::<usize>().ok());
+                    let row = track
+                        .and_then(|t| pattern.steps.get_mut(t))
+                        .ok_or_else(|| err("invalid track"))?;
+                    let instrument = track.and_then(|t| tracks.get(t)).and_then(|t| registry.get(t.instrument));
+                    let step = fields
+                        .next()
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .and_then(|s| row.get_mut(s))
+                        .ok_or_else(|| err("invalid step index"))?;
+                    for field in fields {
+                        let (name, v) = field
+                            .split_once('=')
+                            .ok_or_else(|| err("expected name=value"))?;
+                        match name {
+                            "velocity" => {
+                                step.velocity = v
+                                    .parse()
+                                    .ok()
+                                    .filter(|&v| v <= 127)
+                                    .ok_or_else(|| err("invalid velocity"))?
+                            }
+                            "note" => {
+                                step.note = v
+                                    .parse()
+                                    .ok()
+                                    .filter(|&n| n <= 127)
+                                    .ok_or_else(|| err("invalid note"))?
+                            }
+                            "offset" => {
+                                let offset: f32 = v.parse().map_err(|_| err("invalid offset"))?;
+                                step.offset = offset.clamp(-0.5, 0.5);
+                            }
+                            "length" => {
+                                step.length = v
+                                    .parse()
+                                    .ok()
+                                    .filter(|l| (1..=MAX_NOTE_LENGTH).contains(l))
+                                    .ok_or_else(|| err("invalid length"))?
+                            }
+                            "accent" => step.accent = v == "1",
+                            "slide" => step.slide = v == "1",
+                            "chord" => {
+                                step.chord = CHORDS
+                                    .iter()
+                                    .position(|(name, _)| *name == v)
+                                    .ok_or_else(|| err("unknown chord"))?
+                                    as u8
+                            }
+                            _ if name.starts_with("sound.") => {
+                                let (idx, spec) = instrument
+                                    .and_then(|instrument| {
+                                        instrument
+                                            .params()
+                                            .iter()
+                                            .enumerate()
+                                            .find(|(_, p)| p.name.replace(' ', "") == name["sound.".len()..])
+                                    })
+                                    .ok_or_else(|| err("unknown sound parameter"))?;
+                                let v: f32 = v.parse().map_err(|_| err("invalid value"))?;
+                                step.set_sound_lock(idx, v.clamp(spec.min, spec.max));
+                            }
+                            _ => {
+                                let param = Param::ALL
+                                    .iter()
+                                    .find(|p| p.name() == name)
+                                    .ok_or_else(|| err("unknown parameter"))?;
+                                let v: f32 = v.parse().map_err(|_| err("invalid value"))?;
+                                step.set_lock(*param, v);
+                            }
+                        }
+                    }
+                }
+                _ => return Err(err("unknown pattern setting").into()),
+            }
+        } else if let Some(track) = tracks.last_mut() {
+            if let Some(param) = Param::ALL.iter().find(|p| p.name() == key) {
+                let v: f32 = value.parse().map_err(|_| err("invalid value"))?;
+                track.set_param(*param, v);
+                continue;
+            }
+            match key {
+                "instrument" => {
+                    track.instrument = *InstrumentType::ALL
+                        .iter()
+                        .find(|i| i.name() == value)
+                        .ok_or_else(|| err("unknown instrument"))?;
+                    track.choke = track.instrument.default_choke();
+                }
+                "sound" => {
+                    let instrument = registry
+                        .get(track.instrument)
+                        .ok_or_else(|| err("instrument has no sound parameters"))?;
+                    let mut values = instrument.values(&[]);
+                    for field in value.split_whitespace() {
+                        let (name, v) = field
+                            .split_once('=')
+                            .ok_or_else(|| err("expected name=value"))?;
+                        let (idx, spec) = instrument
+                            .params()
+                            .iter()
+                            .enumerate()
+                            .find(|(_, p)| p.name.replace(' ', "") == name)
+                            .ok_or_else(|| err("unknown sound parameter"))?;
+                        let v: f32 = v.parse().map_err(|_| err("invalid value"))?;
+                        values[idx] = v.clamp(spec.min, spec.max);
+                    }
+                    track.sound = values;
+                }
+                "choke" => {
+                    track.choke = value
+                        .parse()
+                        .ok()
+                        .filter(|&g| g <= CHOKE_GROUPS)
+                        .ok_or_else(|| err("invalid choke group"))?
+                }
+                "send" => {
+                    let v: f32 = value.parse().map_err(|_| err("invalid send"))?;
+                    track.send = v.clamp(0.0, 1.0);
+                }
+                "filter" => {
+                    track.filter_mode = *FilterMode::ALL
+                        .iter()
+                        .find(|m| m.name() == value)
+                        .ok_or_else(|| err("unknown filter mode"))?
+                }
+                "filter_decay" => {
+                    let v: f32 = value.parse().map_err(|_| err("invalid filter decay"))?;
+                    track.filter_decay = v.clamp(MIN_FILTER_DECAY, MAX_FILTER_DECAY);
+                }
+                "mod" => {
+                    let fields: Vec<&str> = value.split_whitespace().collect();
+                    let [source, target, depth] = fields[..] else {
+                        return Err(err("expected source, target and depth").into());
+                    };
+                    let source = *ModSource::ALL
+                        .iter()
+                        .find(|s| s.name() == source)
+                        .ok_or_else(|| err("unknown modulation source"))?;
+                    let target = *Param::ALL
+                        .iter()
+                        .find(|p| p.name() == target)
+                        .ok_or_else(|| err("unknown modulation target"))?;
+                    let depth: f32 = depth.parse().map_err(|_| err("invalid depth"))?;
+                    if track.mods.len() >= MAX_ROUTES {
+                        return Err(err("too many routes").into());
+                    }
+                    track.mods.push(Route { source, target, depth: depth.clamp(-1.0, 1.0) });
+                }
+                "effect" => {
+                    if track.effects.len() >= MAX_EFFECTS {
+                        return Err(err("too many effects").into());
+                    }
+                    track.effects.push(parse_effect(value).map_err(err)?);
+                }
+                "sample" => track.sample = Some(PathBuf::from(value)),
+                "mute" => track.mute = true,
+                "solo" => track.solo = true,
+                _ => return Err(err("unknown track setting").into()),
+            }
+        } else {
+            match key {
+                "bpm" => {
+                    let v: f32 = value.parse().map_err(|_| err("invalid bpm"))?;
+                    bpm = v.clamp(MIN_BPM, MAX_BPM);
+                }
+                "section" => {
+                    let fields: Vec<&str> = value.split_whitespace().collect();
+                    let (bank, repeats, bpm) = match fields[..] {
+                        [bank, repeats, bpm, ..] => (bank.parse(), repeats.parse(), bpm.parse()),
+                        _ => return Err(err("expected bank, repeats and bpm").into()),
+                    };
+                    let (Ok(bank), Ok(repeats), Ok(bpm)) = (bank, repeats, bpm) else {
+                        return Err(err("invalid section").into());
+                    };
+                    if bank >= BANK_COUNT || repeats == 0 {
+                        return Err(err("invalid section").into());
+                    }
+                    song.sections.push(Section {
+                        bank,
+                        repeats,
+                        bpm: f32::clamp(bpm, MIN_BPM, MAX_BPM),
+                        ramp: fields.get(3) == Some(&"ramp"),
+                    });
+                }
+                "bank" => {
+                    bank = value
+                        .parse()
+                        .ok()
+                        .filter(|&b| b < BANK_COUNT)
+                        .ok_or_else(|| err("invalid bank"))?
+                }
+                "kit" => kit = Some(PathBuf::from(value)),
+                // 以前の版が書いていた履歴の位置（履歴は保存しなくなったので読み飛ばす）
+                "history" => {}
+                "lfo" => {
+                    let mut fields = value.split_whitespace();
+                    let lfo = fields
+                        .next()
+                        .and_then(|i| i.parse::<usize>().ok())
+                        .and_then(|i| lfos.get_mut(i))
+                        .ok_or_else(|| err("invalid lfo"))?;
+                    for field in fields {
+                        let (name, v) = field
+                            .split_once('=')
+                            .ok_or_else(|| err("expected name=value"))?;
+                        match name {
+                            "shape" => {
+                                lfo.shape = *LfoShape::ALL
+                                    .iter()
+                                    .find(|s| s.name() == v)
+                                    .ok_or_else(|| err("unknown lfo shape"))?
+                            }
+                            "sync" => lfo.sync = v == "1",
+                            "steps" => {
+                                let steps: f32 = v.parse().map_err(|_| err("invalid steps"))?;
+                                lfo.length = SYNC_LENGTHS
+                                    .iter()
+                                    .position(|(_, s)| *s == steps)
+                                    .ok_or_else(|| err("unsupported lfo length"))?;
+                            }
+                            "rate" => {
+                                let rate: f32 = v.parse().map_err(|_| err("invalid rate"))?;
+                                lfo.rate = rate.clamp(MIN_LFO_RATE, MAX_LFO_RATE);
+                            }
+                            _ => return Err(err("unknown lfo setting").into()),
+                        }
+                    }
+                }
+                "send_effect" => {
+                    if send_bus.len() >= MAX_EFFECTS {
+                        return Err(err("too many effects").into());
+                    }
+                    send_bus.push(parse_effect(value).map_err(err)?);
+                }
+                "export_tail" => {
+                    let v: f32 = value.parse().map_err(|_| err("invalid export tail"))?;
+                    export_tail = Some(v.clamp(0.0, MAX_EXPORT_TAIL));
+                }
+                "master_effect" => {
+                    if master.len() >= MAX_EFFECTS {
+                        return Err(err("too many effects").into());
+                    }
+                    master.push(parse_effect(value).map_err(err)?);
+                }
+                _ => return Err(err("unknown setting").into()),
+            }
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err("project has no tracks".into());
+    }
+
+    let patterns = patterns
+        .into_iter()
+        .map(|p| p.unwrap_or_else(|| Pattern::new(&tracks, 16)))
+        .collect();
+
+    Ok(Project {
+        bpm,
+        tracks,
+        patterns,
+        bank,
+        song,
+        kit,
+        lfos,
+        send_bus,
+        master,
+        export_tail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // トラック1つと空のバンクだけのプロジェクト
+    fn project(instrument: InstrumentType) -> Project {
+        let tracks = vec![Track::new("Test", instrument)];
+        let patterns = vec![Pattern::new(&tracks, 16); BANK_COUNT];
+        Project {
+            bpm: 120.0,
+            tracks,
+            patterns,
+            bank: 0,
+            song: Song::default(),
+            kit: None,
+            lfos: [Lfo::default(); LFO_COUNT],
+            send_bus: Vec::new(),
+            master: Vec::new(),
+            export_tail: None,
+        }
+    }
+
+    // 一時ファイルに保存して読み込み直す
+    fn round_trip(project: &Project, name: &str) -> Project {
+        let path = std::env::temp_dir().join(format!("rhythm-box-{}-{}.project", name, std::process::id()));
+        save(&path, project).unwrap();
+        let loaded = load(&path);
+        let _ = fs::remove_file(&path);
+        loaded.unwrap()
+    }
+
+    #[test]
+    fn history_position_is_not_saved() {
+        let path = std::env::temp_dir().join(format!("rhythm-box-history-{}.project", std::process::id()));
+        save(&path, &project(InstrumentType::Kick)).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.lines().any(|line| line.starts_with("history")));
+
+        // 以前の版のファイルも読める
+        fs::write(&path, text.replacen("bpm", "history 42\nbpm", 1)).unwrap();
+        let loaded = load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap().bpm, 120.0);
+    }
+
+    // 保存したファイルの行を書き換えて読み込む
+    fn load_edited(name: &str, edit: impl FnOnce(String) -> String) -> Result<Project, String> {
+        let path = std::env::temp_dir().join(format!("rhythm-box-{}-{}.project", name, std::process::id()));
+        let mut project = project(InstrumentType::Bass);
+        project.patterns[0].steps[0][0].velocity = 100;
+        save(&path, &project).unwrap();
+        fs::write(&path, edit(fs::read_to_string(&path).unwrap())).unwrap();
+        let loaded = load(&path);
+        let _ = fs::remove_file(&path);
+        loaded.map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn out_of_range_tracks_and_notes_are_rejected() {
+        assert!(load_edited("valid", |text| text).is_ok());
+
+        // UI で足せる数より多いトラック
+        let too_many = load_edited("tracks", |text| {
+            let extra = "track Extra\ninstrument Kick\n".repeat(MAX_TRACKS);
+            text.replacen("track Test", &format!("{}track Test", extra), 1)
+        });
+        assert!(too_many.err().unwrap().ends_with("too many tracks"));
+
+        // MIDI の範囲を超えたノートとベロシティ
+        let step = |fields: &'static str| {
+            move |text: String| text.replacen("step 0 0 velocity=100", &format!("step 0 0 {}", fields), 1)
+        };
+        let note = load_edited("note", step("note=200 velocity=100"));
+        assert!(note.err().unwrap().ends_with("invalid note"));
+        let velocity = load_edited("velocity", step("velocity=128"));
+        assert!(velocity.err().unwrap().ends_with("invalid velocity"));
+    }
+
+    #[test]
+    fn sound_locks_round_trip() {
+        let mut project = project(InstrumentType::Kick);
+        let step = &mut project.patterns[0].steps[0][2];
+        step.active = true;
+        step.set_sound_lock(0, 120.0);
+        step.set_sound_lock(1, 1000.0); // Sweep の範囲を超えた値は読み込みで収める
+        let loaded = round_trip(&project, "sound-locks");
+        assert_eq!(loaded.patterns[0].steps[0][2].sound_locks, [(0, 120.0), (1, 400.0)]);
+    }
+
+    #[test]
+    fn track_sound_round_trips() {
+        let mut project = project(InstrumentType::Kick);
+        project.tracks[0].sound = vec![55.0, 110.0, 0.8, 2.0];
+        let loaded = round_trip(&project, "sound");
+        assert_eq!(loaded.tracks[0].sound, [55.0, 110.0, 0.8, 2.0]);
+
+        // 既定のままなら書かない
+        let loaded = round_trip(&self::project(InstrumentType::Kick), "no-sound");
+        assert!(loaded.tracks[0].sound.is_empty());
+
+        // 書かれていないパラメータは既定値、範囲外は収める
+        let edited = load_edited("partial-sound", |text| text.replacen("instrument Bass", "instrument Bass\nsound Decay=99", 1));
+        let registry = Registry::new();
+        let bass = registry.get(InstrumentType::Bass).unwrap();
+        let mut expected = bass.values(&[]);
+        let decay = bass.params().iter().position(|p| p.name == "Decay").unwrap();
+        expected[decay] = bass.params()[decay].max;
+        assert_eq!(edited.unwrap().tracks[0].sound, expected);
+
+        let unknown = load_edited("unknown-sound", |text| text.replacen("instrument Bass", "instrument Bass\nsound Wobble=1", 1));
+        assert!(unknown.err().unwrap().ends_with("unknown sound parameter"));
+    }
+}
@@ -0,0 +1,344 @@
+// src/sequencer/history.rs
+// アンドゥ/リドゥ履歴（編集コマンドのスタック）。履歴はセッションの間だけで、プロジェクトには保存しない
+use std::collections::VecDeque;
+
+use super::modulation::{Lfo, LFO_COUNT};
+use super::pattern::{EffectSlot, Param, Pattern, Step, Track};
+use super::song::Song;
+
+const MAX_DEPTH: usize = 100;
+
+// トラックとパターン以外のプロジェクト全体の設定（まとめて履歴に積む）
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub bpm: f32,
+    pub song: Song,
+    pub lfos: [Lfo; LFO_COUNT],
+    pub send_bus: Vec<EffectSlot>, // センドバスのエフェクトチェーン
+    pub master: Vec<EffectSlot>,   // マスターのエフェクトチェーン
+    pub export_tail: Option<f32>,  // エクスポートで書き足す余韻（秒）。None ならエフェクトから求める
+}
+
+// 続けて動かした編集をひとつにまとめるときの対象。同じ対象を続けて動かしたときだけまとめる
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Param(Param),             // トラックの値やステップのロック
+    Row(&'static str, usize), // 編集パネルの行（パネルの名前と行）
+    Bpm,
+}
+
+// 元に戻せる編集操作（前後の状態を持つ）。field があれば同じ対象の続く編集とまとめられる
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Step {
+        bank: usize,
+        track: usize,
+        step: usize,
+        field: Option<Field>,
+        before: Step,
+        after: Step,
+    },
+    // コピー/ペーストや回転などパターン全体に及ぶ操作
+    Pattern {
+        bank: usize,
+        before: Pattern,
+        after: Pattern,
+    },
+    // ミキサーでのトラック設定の変更（Track は大きいので箱に入れる）
+    Track {
+        track: usize,
+        field: Option<Field>,
+        before: Box<Track>,
+        after: Box<Track>,
+    },
+    // トラックの追加・削除・並べ替えなど、全バンクの行が変わる操作
+    Tracks {
+        before: (Vec<Track>, Vec<Pattern>),
+        after: (Vec<Track>, Vec<Pattern>),
+    },
+    // BPM やソング、LFO、センドバスとマスターのエフェクトなどの変更
+    Settings {
+        field: Option<Field>,
+        before: Box<Settings>,
+        after: Box<Settings>,
+    },
+}
+
+impl Edit {
+    // 編集したパターンのバンク
+    pub fn bank(&self) -> Option<usize> {
+        match self {
+            Edit::Step { bank, .. } | Edit::Pattern { bank, .. } => Some(*bank),
+            Edit::Track { .. } | Edit::Tracks { .. } | Edit::Settings { .. } => None,
+        }
+    }
+
+    fn field(&self) -> Option<Field> {
+        match self {
+            Edit::Step { field, .. } | Edit::Track { field, .. } | Edit::Settings { field, .. } => *field,
+            Edit::Pattern { .. } | Edit::Tracks { .. } => None,
+        }
+    }
+
+    // banks は全バンク（編集中のバンクも最新のもの）
+    pub fn apply(&self, tracks: &mut Vec<Track>, banks: &mut [Pattern], settings: &mut Settings) {
+        match self {
+            Edit::Step { bank, track, step, after, .. } => {
+                banks[*bank].steps[*track][*step] = after.clone();
+            }
+            Edit::Pattern { bank, after, .. } => banks[*bank] = after.clone(),
+            Edit::Track { track, after, .. } => tracks[*track] = (**after).clone(),
+            Edit::Tracks { after, .. } => {
+                *tracks = after.0.clone();
+                banks.clone_from_slice(&after.1);
+            }
+            Edit::Settings { after, .. } => *settings = (**after).clone(),
+        }
+    }
+
+    pub fn revert(&self, tracks: &mut Vec<Track>, banks: &mut [Pattern], settings: &mut Settings) {
+        match self {
+            Edit::Step { bank, track, step, before, .. } => {
+                banks[*bank].steps[*track][*step] = before.clone();
+            }
+            Edit::Pattern { bank, before, .. } => banks[*bank] = before.clone(),
+            Edit::Track { track, before, .. } => tracks[*track] = (**before).clone(),
+            Edit::Tracks { before, .. } => {
+                *tracks = before.0.clone();
+                banks.clone_from_slice(&before.1);
+            }
+            Edit::Settings { before, .. } => *settings = (**before).clone(),
+        }
+    }
+
+    // 同じ対象への連続した編集はひとつにまとめる
+    fn merge(&mut self, next: &Edit) -> bool {
+        let same_field = self.field().is_some() && self.field() == next.field();
+        match (self, next) {
+            (
+                Edit::Step { bank, track, step, after, .. },
+                Edit::Step { bank: b, track: t, step: s, after: a, .. },
+            ) if same_field && bank == b && track == t && step == s => {
+                *after = a.clone();
+                true
+            }
+            (Edit::Track { track, after, .. }, Edit::Track { track: t, after: a, .. })
+                if same_field && track == t =>
+            {
+                *after = a.clone();
+                true
+            }
+            (Edit::Settings { after, .. }, Edit::Settings { after: a, .. }) if same_field => {
+                *after = a.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    position: usize,            // 適用済みの編集数
+    saved_at: Option<usize>,    // 最後に保存した時の position
+    mergeable: bool,            // 直前の編集がまとめられるものか
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            position: 0,
+            saved_at: Some(0),
+            mergeable: false,
+        }
+    }
+
+    // 適用済みの編集を記録する。field のある編集は直前の同じ対象の編集にまとめる
+    pub fn push(&mut self, edit: Edit) {
+        if self.mergeable && self.saved_at != Some(self.position) {
+            if let Some(last) = self.undo.back_mut() {
+                if last.merge(&edit) {
+                    return;
+                }
+            }
+        }
+
+        if self.saved_at.is_some_and(|saved| saved > self.position) {
+            // 保存時の状態はもう辿れない
+            self.saved_at = None;
+        }
+        self.redo.clear();
+        self.mergeable = edit.field().is_some();
+        self.undo.push_back(edit);
+        if self.undo.len() > MAX_DEPTH {
+            self.undo.pop_front();
+        }
+        self.position += 1;
+    }
+
+    // 取り消す編集を返す（呼び出し側が revert する）
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit.clone());
+        self.position -= 1;
+        self.mergeable = false;
+        Some(edit)
+    }
+
+    // やり直す編集を返す（呼び出し側が apply する）
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        self.position += 1;
+        self.mergeable = false;
+        Some(edit)
+    }
+
+    // テストで積んだ編集の数を調べる
+    #[cfg(test)]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_at = Some(self.position);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.saved_at != Some(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::pattern::InstrumentType;
+
+    fn settings() -> Settings {
+        Settings {
+            bpm: 120.0,
+            song: Song::default(),
+            lfos: [Lfo::default(); LFO_COUNT],
+            send_bus: Vec::new(),
+            master: Vec::new(),
+            export_tail: None,
+        }
+    }
+
+    // 1トラックの音量を before から after に変える編集
+    fn volume(field: Option<Field>, before: f32, after: f32) -> Edit {
+        let mut track = Track::new("Kick", InstrumentType::Kick);
+        track.volume = before;
+        let mut changed = track.clone();
+        changed.volume = after;
+        Edit::Track { track: 0, field, before: Box::new(track), after: Box::new(changed) }
+    }
+
+    fn bpm(before: f32, after: f32) -> Edit {
+        let mut after_settings = settings();
+        after_settings.bpm = after;
+        let mut before_settings = settings();
+        before_settings.bpm = before;
+        Edit::Settings {
+            field: Some(Field::Bpm),
+            before: Box::new(before_settings),
+            after: Box::new(after_settings),
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_stack() {
+        let mut history = History::new();
+        history.push(volume(None, 1.0, 0.5));
+        history.push(volume(None, 0.5, 0.2));
+        assert_eq!(history.position(), 2);
+
+        let mut tracks = vec![Track::new("Kick", InstrumentType::Kick)];
+        let mut settings = settings();
+        tracks[0].volume = 0.2;
+        history.undo().unwrap().revert(&mut tracks, &mut [], &mut settings);
+        assert_eq!(tracks[0].volume, 0.5);
+        history.undo().unwrap().revert(&mut tracks, &mut [], &mut settings);
+        assert_eq!(tracks[0].volume, 1.0);
+        assert!(history.undo().is_none());
+
+        history.redo().unwrap().apply(&mut tracks, &mut [], &mut settings);
+        assert_eq!(tracks[0].volume, 0.5);
+        assert_eq!(history.position(), 1);
+
+        // 新しい編集でやり直しの分は消える
+        history.push(volume(None, 0.5, 0.9));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn only_the_same_field_is_merged() {
+        let mut history = History::new();
+        let field = Some(Field::Param(Param::Volume));
+        history.push(volume(field, 1.0, 0.95));
+        history.push(volume(field, 0.95, 0.9));
+        assert_eq!(history.position(), 1);
+
+        // 別のパラメータは別の操作として積む
+        history.push(volume(Some(Field::Param(Param::Pan)), 0.9, 0.9));
+        assert_eq!(history.position(), 2);
+
+        // field のない編集はまとめない
+        history.push(volume(None, 0.9, 0.8));
+        history.push(volume(None, 0.8, 0.7));
+        assert_eq!(history.position(), 4);
+
+        // 設定の編集も同じ対象ならまとめ、まとめた編集を戻すと最初の値に戻る
+        history.push(bpm(120.0, 121.0));
+        history.push(bpm(121.0, 122.0));
+        assert_eq!(history.position(), 5);
+        let (mut tracks, mut settings) = (Vec::new(), settings());
+        history.undo().unwrap().revert(&mut tracks, &mut [], &mut settings);
+        assert_eq!(settings.bpm, 120.0);
+    }
+
+    #[test]
+    fn oldest_edits_are_dropped_past_max_depth() {
+        let mut history = History::new();
+        for i in 0..MAX_DEPTH + 10 {
+            history.push(volume(None, i as f32, i as f32 + 1.0));
+        }
+        assert_eq!(history.position(), MAX_DEPTH + 10);
+        let mut undone = 0;
+        while history.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_DEPTH);
+        assert_eq!(history.position(), 10);
+    }
+
+    #[test]
+    fn dirty_follows_the_saved_position() {
+        let mut history = History::new();
+        assert!(!history.is_dirty());
+        history.push(volume(None, 1.0, 0.5));
+        assert!(history.is_dirty());
+        history.mark_saved();
+        assert!(!history.is_dirty());
+
+        history.undo();
+        assert!(history.is_dirty());
+        history.redo();
+        assert!(!history.is_dirty());
+
+        // 保存直後の編集はまとめずに積むので、戻せば保存時に戻る
+        let field = Some(Field::Param(Param::Volume));
+        history.push(volume(field, 0.5, 0.4));
+        history.push(volume(field, 0.4, 0.3));
+        history.undo();
+        history.undo();
+        assert!(history.is_dirty());
+
+        // 保存した状態が辿れなくなったら戻しても dirty のまま
+        history.push(volume(None, 1.0, 0.1));
+        assert!(history.is_dirty());
+    }
+}
@@ -1,2 +1,5 @@
 pub mod pattern;
 pub mod track;
+pub mod history;
+pub mod song;
+pub mod modulation;
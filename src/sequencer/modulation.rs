@@ -0,0 +1,364 @@
+// src/sequencer/modulation.rs
+// LFO とモジュレーションマトリクス
+//
+// LFO はプロジェクト全体で4つ。テンポに同期する（ステップ数で1周）か、Hz で自由に回る。
+// トラックごとのルートで、LFO やステップごとの乱数をトラックのパラメータへ深さを付けて送る。
+// 状態はオーディオスレッドの Modulator が持ち、再生開始で初期化する（エクスポートも同じ音になる）。
+use super::pattern::{Param, Track};
+
+pub const LFO_COUNT: usize = 4;
+pub const MAX_ROUTES: usize = 8; // 1トラックあたり
+pub const MIN_LFO_RATE: f32 = 0.05;
+pub const MAX_LFO_RATE: f32 = 20.0;
+
+// 同期したときの1周の長さ（名前, ステップ数）
+pub const SYNC_LENGTHS: [(&str, f32); 8] = [
+    ("1/16", 1.0),
+    ("1/8", 2.0),
+    ("1/4", 4.0),
+    ("1/2", 8.0),
+    ("1 bar", 16.0),
+    ("2 bars", 32.0),
+    ("4 bars", 64.0),
+    ("8 bars", 128.0),
+];
+
+// 乱数の初期値（再生のたびに同じ並びにする）
+const SEED: u32 = 0x2545_f491;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    Saw,
+    SampleHold,
+}
+
+impl LfoShape {
+    pub const ALL: [LfoShape; 5] = [
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Square,
+        LfoShape::Saw,
+        LfoShape::SampleHold,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Tri",
+            LfoShape::Square => "Square",
+            LfoShape::Saw => "Saw",
+            LfoShape::SampleHold => "S&H",
+        }
+    }
+
+    // 位相 0.0〜1.0 での値 -1.0〜1.0（S&H は1周ごとに引いた held を返す）
+    fn value(&self, phase: f32, held: f32) -> f32 {
+        match self {
+            LfoShape::Sine => (2.0 * std::f32::consts::PI * phase).sin(),
+            // 0 から上がり始める（0.25 で 1、0.75 で -1）
+            LfoShape::Triangle => 4.0 * ((phase - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::SampleHold => held,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    pub sync: bool,
+    pub length: usize, // 同期するときの SYNC_LENGTHS のインデックス
+    pub rate: f32,     // 同期しないときの Hz
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            sync: true,
+            length: 4,
+            rate: 1.0,
+        }
+    }
+}
+
+impl Lfo {
+    // 速さの表示（同期中は長さ、そうでなければ Hz）
+    pub fn rate_name(&self) -> String {
+        if self.sync {
+            SYNC_LENGTHS[self.length].0.to_string()
+        } else {
+            format!("{:.2} Hz", self.rate)
+        }
+    }
+
+    // 1段階分だけ速さを動かす（Hz は指数的に）
+    pub fn nudge_rate(&mut self, dir: i32) {
+        if self.sync {
+            let length = self.length as i32 + dir;
+            self.length = length.clamp(0, SYNC_LENGTHS.len() as i32 - 1) as usize;
+        } else {
+            let rate = self.rate * 2.0_f32.powf(dir as f32 / 4.0);
+            self.rate = rate.clamp(MIN_LFO_RATE, MAX_LFO_RATE);
+        }
+    }
+}
+
+// モジュレーションの送り元
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModSource {
+    Lfo(usize),
+    Random, // ステップごとに引き直す乱数
+}
+
+impl ModSource {
+    pub const ALL: [ModSource; LFO_COUNT + 1] = [
+        ModSource::Lfo(0),
+        ModSource::Lfo(1),
+        ModSource::Lfo(2),
+        ModSource::Lfo(3),
+        ModSource::Random,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModSource::Lfo(i) => ["LFO1", "LFO2", "LFO3", "LFO4"][*i],
+            ModSource::Random => "Random",
+        }
+    }
+}
+
+// モジュレーションマトリクスの1行（送り元 → トラックのパラメータ）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Route {
+    pub source: ModSource,
+    pub target: Param,
+    pub depth: f32, // -1.0〜1.0
+}
+
+impl Route {
+    pub fn new() -> Self {
+        Self {
+            source: ModSource::Lfo(0),
+            target: Param::FilterCutoff,
+            depth: 0.5,
+        }
+    }
+}
+
+// 鳴っている間もサンプルごとに動かすパラメータ（トラックのバスでかける）。
+// それ以外は発音した瞬間の値を使う
+pub fn is_continuous(param: Param) -> bool {
+    matches!(
+        param,
+        Param::Volume | Param::Pan | Param::FilterCutoff | Param::FilterResonance
+    )
+}
+
+// オーディオスレッド側の LFO と乱数の状態
+pub struct Modulator {
+    phases: [f32; LFO_COUNT], // 0.0〜1.0
+    held: [f32; LFO_COUNT],   // S&H の値
+    values: [f32; LFO_COUNT], // 現在の出力 -1.0〜1.0
+    random: Vec<[f32; MAX_ROUTES]>, // トラック・ルートごとのステップの乱数
+    rng: u32,
+}
+
+impl Modulator {
+    pub fn new() -> Self {
+        let mut modulator = Self {
+            phases: [0.0; LFO_COUNT],
+            held: [0.0; LFO_COUNT],
+            values: [0.0; LFO_COUNT],
+            random: Vec::new(),
+            rng: SEED,
+        };
+        modulator.reset();
+        modulator
+    }
+
+    // 再生開始時に位相と乱数を初期化する
+    pub fn reset(&mut self) {
+        self.rng = SEED;
+        self.phases = [0.0; LFO_COUNT];
+        for i in 0..LFO_COUNT {
+            self.held[i] = self.next_random();
+        }
+        for values in &mut self.random {
+            *values = [0.0; MAX_ROUTES];
+        }
+    }
+
+    // xorshift で -1.0〜1.0
+    fn next_random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    // 1サンプル進める。steps はこのサンプルで進んだステップ数（停止中は 0）
+    pub fn advance(&mut self, lfos: &[Lfo; LFO_COUNT], steps: f32, sample_rate: f32) {
+        for (i, lfo) in lfos.iter().enumerate() {
+            let inc = if lfo.sync {
+                steps / SYNC_LENGTHS[lfo.length].1
+            } else {
+                lfo.rate / sample_rate
+            };
+            let phase = self.phases[i] + inc;
+            if phase >= 1.0 {
+                self.held[i] = self.next_random();
+            }
+            self.phases[i] = phase.fract();
+            self.values[i] = lfo.shape.value(self.phases[i], self.held[i]);
+        }
+    }
+
+    // ステップが進んだら乱数を引き直す
+    pub fn next_step(&mut self, tracks: &[Track]) {
+        let mut random = std::mem::take(&mut self.random);
+        random.resize(tracks.len(), [0.0; MAX_ROUTES]);
+        for (values, track) in random.iter_mut().zip(tracks) {
+            for value in values.iter_mut().take(track.mods.len()) {
+                *value = self.next_random();
+            }
+        }
+        self.random = random;
+    }
+
+    // トラックのパラメータにかかるモジュレーションの合計（深さをかけたもの）
+    pub fn amount(&self, track_idx: usize, track: &Track, param: Param) -> f32 {
+        track
+            .mods
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| route.target == param)
+            .map(|(r, route)| {
+                let value = match route.source {
+                    ModSource::Lfo(i) => self.values[i],
+                    ModSource::Random => self
+                        .random
+                        .get(track_idx)
+                        .and_then(|values| values.get(r))
+                        .copied()
+                        .unwrap_or(0.0),
+                };
+                value * route.depth
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::pattern::InstrumentType;
+
+    fn lfos(lfo: Lfo) -> [Lfo; LFO_COUNT] {
+        [lfo; LFO_COUNT]
+    }
+
+    fn track(routes: &[(ModSource, Param, f32)]) -> Track {
+        let mut track = Track::new("Test", InstrumentType::Kick);
+        track.mods = routes
+            .iter()
+            .map(|&(source, target, depth)| Route { source, target, depth })
+            .collect();
+        track
+    }
+
+    #[test]
+    fn synced_lfo_follows_the_steps() {
+        // 1/4（4ステップで1周）の三角波。1サンプルで 1/100 ステップ進める
+        let lfos = lfos(Lfo { shape: LfoShape::Triangle, length: 2, ..Lfo::default() });
+        let mut modulator = Modulator::new();
+        let lfo_track = track(&[(ModSource::Lfo(0), Param::Pan, 1.0)]);
+        let value = |m: &Modulator| m.amount(0, &lfo_track, Param::Pan);
+        for _ in 0..100 {
+            modulator.advance(&lfos, 0.01, 44100.0);
+        }
+        assert!((value(&modulator) - 1.0).abs() < 1e-3);
+
+        // 止まっている間は動かない
+        modulator.advance(&lfos, 0.0, 44100.0);
+        assert!((value(&modulator) - 1.0).abs() < 1e-3);
+
+        for _ in 0..200 {
+            modulator.advance(&lfos, 0.01, 44100.0);
+        }
+        assert!((value(&modulator) + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn free_lfo_runs_in_hertz() {
+        // 2 Hz の矩形波は 0.25 秒で下がる
+        let lfos = lfos(Lfo { shape: LfoShape::Square, sync: false, rate: 2.0, ..Lfo::default() });
+        let mut modulator = Modulator::new();
+        let lfo_track = track(&[(ModSource::Lfo(0), Param::Volume, 0.5)]);
+        modulator.advance(&lfos, 0.0, 1000.0);
+        assert_eq!(modulator.amount(0, &lfo_track, Param::Volume), 0.5);
+        for _ in 0..250 {
+            modulator.advance(&lfos, 0.0, 1000.0);
+        }
+        assert_eq!(modulator.amount(0, &lfo_track, Param::Volume), -0.5);
+    }
+
+    #[test]
+    fn routes_add_up_per_target() {
+        let lfos = lfos(Lfo { shape: LfoShape::Square, ..Lfo::default() });
+        let mut modulator = Modulator::new();
+        modulator.advance(&lfos, 0.01, 44100.0); // どの LFO も 1.0
+        let routes = track(&[
+            (ModSource::Lfo(0), Param::FilterCutoff, 0.5),
+            (ModSource::Lfo(1), Param::FilterCutoff, -0.25),
+            (ModSource::Lfo(2), Param::Pan, 1.0),
+        ]);
+        assert_eq!(modulator.amount(0, &routes, Param::FilterCutoff), 0.25);
+        assert_eq!(modulator.amount(0, &routes, Param::Pan), 1.0);
+        assert_eq!(modulator.amount(0, &routes, Param::Volume), 0.0);
+    }
+
+    #[test]
+    fn random_is_drawn_per_step_and_repeats_after_reset() {
+        let tracks = [track(&[(ModSource::Random, Param::Pitch, 1.0)])];
+        let mut modulator = Modulator::new();
+        let draw = |m: &mut Modulator| {
+            m.next_step(&tracks);
+            m.amount(0, &tracks[0], Param::Pitch)
+        };
+        let first: Vec<f32> = (0..4).map(|_| draw(&mut modulator)).collect();
+        assert!(first.windows(2).all(|w| w[0] != w[1]));
+        assert!(first.iter().all(|v| (-1.0..=1.0).contains(v)));
+
+        modulator.reset();
+        assert_eq!(modulator.amount(0, &tracks[0], Param::Pitch), 0.0);
+        let again: Vec<f32> = (0..4).map(|_| draw(&mut modulator)).collect();
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn rate_nudges_stay_in_range() {
+        let mut lfo = Lfo::default();
+        for _ in 0..20 {
+            lfo.nudge_rate(1);
+        }
+        assert_eq!(lfo.rate_name(), "8 bars");
+        lfo.sync = false;
+        for _ in 0..100 {
+            lfo.nudge_rate(-1);
+        }
+        assert_eq!(lfo.rate, MIN_LFO_RATE);
+        lfo.nudge_rate(4);
+        assert!((lfo.rate - MIN_LFO_RATE * 2.0).abs() < 1e-6);
+    }
+}
@@ -1,33 +1,654 @@
 // src/sequencer/pattern.rs
-#[derive(Clone, Debug)]
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::modulation::Route;
+
+pub const MAX_LENGTH: usize = 64;
+pub const BANK_COUNT: usize = 8;
+pub const MAX_TRACKS: usize = 16;
+pub const CHOKE_GROUPS: u8 = 4;
+pub const MAX_NOTE_LENGTH: u8 = 16;
+pub const MIN_FILTER_DECAY: f32 = 0.01;
+pub const MAX_FILTER_DECAY: f32 = 4.0;
+pub const MAX_EFFECTS: usize = 4; // 1つのチェーンに挿せる数
+
+// ステップで鳴らせる和音（名前とルートからの半音）
+pub const CHORDS: [(&str, &[u8]); 8] = [
+    ("none", &[0]),
+    ("maj", &[0, 4, 7]),
+    ("min", &[0, 3, 7]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("min7", &[0, 3, 7, 10]),
+    ("sus4", &[0, 5, 7]),
+    ("oct", &[0, 12]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Step {
     pub active: bool,
     pub velocity: u8,  // 0-127
     pub note: u8,      // MIDI note number
+    pub offset: f32,   // マイクロタイミング（ステップ幅に対する割合 -0.5〜0.5）
+    pub length: u8,    // 音を伸ばすステップ数（1〜MAX_NOTE_LENGTH）
+    pub chord: u8,     // CHORDS のインデックス（0 は単音）
+    pub accent: bool,
+    pub slide: bool,   // 次のステップまで伸ばしてノートを滑らせる
+    pub locks: Vec<(Param, f32)>,  // パラメータロック（このステップだけの上書き値）
+    pub sound_locks: Vec<(usize, f32)>, // 楽器の合成パラメータのロック（ParamSpec の番号と値）
+}
+
+impl Step {
+    pub fn new(note: u8) -> Self {
+        Self {
+            active: false,
+            velocity: 127,
+            note,
+            offset: 0.0,
+            length: 1,
+            chord: 0,
+            accent: false,
+            slide: false,
+            locks: Vec::new(),
+            sound_locks: Vec::new(),
+        }
+    }
+
+    pub fn lock(&self, param: Param) -> Option<f32> {
+        self.locks.iter().find(|(p, _)| *p == param).map(|(_, v)| *v)
+    }
+
+    pub fn set_lock(&mut self, param: Param, value: f32) {
+        let value = param.clamp(value);
+        match self.locks.iter_mut().find(|(p, _)| *p == param) {
+            Some(lock) => lock.1 = value,
+            None => self.locks.push((param, value)),
+        }
+    }
+
+    pub fn clear_lock(&mut self, param: Param) {
+        self.locks.retain(|(p, _)| *p != param);
+    }
+
+    pub fn sound_lock(&self, idx: usize) -> Option<f32> {
+        self.sound_locks.iter().find(|(i, _)| *i == idx).map(|(_, v)| *v)
+    }
+
+    // 値は呼び出し側で ParamSpec の範囲に収めておく
+    pub fn set_sound_lock(&mut self, idx: usize, value: f32) {
+        match self.sound_locks.iter_mut().find(|(i, _)| *i == idx) {
+            Some(lock) => lock.1 = value,
+            None => self.sound_locks.push((idx, value)),
+        }
+    }
+
+    pub fn clear_sound_lock(&mut self, idx: usize) {
+        self.sound_locks.retain(|(i, _)| *i != idx);
+    }
+
+    pub fn has_locks(&self) -> bool {
+        !self.locks.is_empty() || !self.sound_locks.is_empty()
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Pattern {
     pub steps: Vec<Vec<Step>>,  // [track][step]
     pub length: usize,           // 通常16ステップ
 }
 
-#[derive(Clone, Debug)]
+impl Pattern {
+    pub fn new(tracks: &[Track], length: usize) -> Self {
+        let steps = tracks
+            .iter()
+            .map(|track| vec![Step::new(track.instrument.default_note()); length])
+            .collect();
+        Self { steps, length }
+    }
+
+    // 範囲内のステップを切り出す（[track][step]）
+    pub fn copy_block(&self, tracks: Range<usize>, steps: Range<usize>) -> Vec<Vec<Step>> {
+        tracks.map(|t| self.steps[t][steps.clone()].to_vec()).collect()
+    }
+
+    // (track, step) を左上としてブロックを貼り付ける（はみ出した分は捨てる）
+    pub fn paste_block(&mut self, track: usize, step: usize, block: &[Vec<Step>]) {
+        for (row, src) in self.steps.iter_mut().skip(track).zip(block) {
+            for (dst, s) in row.iter_mut().skip(step).take(self.length - step).zip(src) {
+                *dst = s.clone();
+            }
+        }
+    }
+
+    pub fn clear_block(&mut self, tracks: Range<usize>, steps: Range<usize>) {
+        for t in tracks {
+            for step in &mut self.steps[t][steps.clone()] {
+                *step = Step::new(step.note);
+            }
+        }
+    }
+
+    // 範囲内で回転（dir > 0 で右へ）
+    pub fn rotate(&mut self, track: usize, steps: Range<usize>, dir: i32) {
+        let slice = &mut self.steps[track][steps];
+        if dir > 0 {
+            slice.rotate_right(1);
+        } else {
+            slice.rotate_left(1);
+        }
+    }
+
+    pub fn reverse(&mut self, track: usize, steps: Range<usize>) {
+        self.steps[track][steps].reverse();
+    }
+
+    pub fn invert(&mut self, track: usize, steps: Range<usize>) {
+        for step in &mut self.steps[track][steps] {
+            step.active = !step.active;
+        }
+    }
+
+    // 内容を繰り返して長さを倍にする
+    pub fn double(&mut self) -> bool {
+        if self.length * 2 > MAX_LENGTH {
+            return false;
+        }
+        for row in &mut self.steps {
+            row.extend_from_within(..);
+        }
+        self.length *= 2;
+        true
+    }
+
+    // 後半を捨てて長さを半分にする
+    pub fn halve(&mut self) -> bool {
+        if self.length < 2 {
+            return false;
+        }
+        self.length /= 2;
+        for row in &mut self.steps {
+            row.truncate(self.length);
+        }
+        true
+    }
+
+    // 新しいトラック用の空の行
+    pub fn empty_row(&self, track: &Track) -> Vec<Step> {
+        vec![Step::new(track.instrument.default_note()); self.length]
+    }
+
+    // 新しく作ったときから何も変えていない（長さやノート、ベロシティなども既定のまま）
+    pub fn is_empty(&self, tracks: &[Track]) -> bool {
+        *self == Pattern::new(tracks, 16)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InstrumentType {
     Kick,
     Snare,
     HiHat,
+    OpenHat,
+    Clap,
+    Rim,
+    LowTom,
+    MidTom,
+    HighTom,
+    Cowbell,
+    Crash,
+    Ride,
     Bass,
+    Acid,
     Pad,
     Lead,
+    Fm,
+    Sampler,
+    Slicer,
+}
+
+impl InstrumentType {
+    pub const ALL: [InstrumentType; 19] = [
+        InstrumentType::Kick,
+        InstrumentType::Snare,
+        InstrumentType::HiHat,
+        InstrumentType::OpenHat,
+        InstrumentType::Clap,
+        InstrumentType::Rim,
+        InstrumentType::LowTom,
+        InstrumentType::MidTom,
+        InstrumentType::HighTom,
+        InstrumentType::Cowbell,
+        InstrumentType::Crash,
+        InstrumentType::Ride,
+        InstrumentType::Bass,
+        InstrumentType::Acid,
+        InstrumentType::Pad,
+        InstrumentType::Lead,
+        InstrumentType::Fm,
+        InstrumentType::Sampler,
+        InstrumentType::Slicer,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstrumentType::Kick => "Kick",
+            InstrumentType::Snare => "Snare",
+            InstrumentType::HiHat => "HiHat",
+            InstrumentType::OpenHat => "OpenHat",
+            InstrumentType::Clap => "Clap",
+            InstrumentType::Rim => "Rim",
+            InstrumentType::LowTom => "LowTom",
+            InstrumentType::MidTom => "MidTom",
+            InstrumentType::HighTom => "HighTom",
+            InstrumentType::Cowbell => "Cowbell",
+            InstrumentType::Crash => "Crash",
+            InstrumentType::Ride => "Ride",
+            InstrumentType::Bass => "Bass",
+            InstrumentType::Acid => "Acid",
+            InstrumentType::Pad => "Pad",
+            InstrumentType::Lead => "Lead",
+            InstrumentType::Fm => "FM",
+            InstrumentType::Sampler => "Sampler",
+            InstrumentType::Slicer => "Slicer",
+        }
+    }
+
+    // ハイハットはクローズとオープンが互いに止め合う
+    pub fn default_choke(&self) -> u8 {
+        match self {
+            InstrumentType::HiHat | InstrumentType::OpenHat => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn default_note(&self) -> u8 {
+        match self {
+            InstrumentType::Bass | InstrumentType::Acid => 36, // C1
+            _ => 60,
+        }
+    }
+}
+
+// トラックのフィルターの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 4] = [
+        FilterMode::LowPass,
+        FilterMode::HighPass,
+        FilterMode::BandPass,
+        FilterMode::Notch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterMode::LowPass => "LowPass",
+            FilterMode::HighPass => "HighPass",
+            FilterMode::BandPass => "BandPass",
+            FilterMode::Notch => "Notch",
+        }
+    }
+}
+
+// エフェクトの種類（処理は audio/effects.rs）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectType {
+    Utility,
+    Delay,
+    Reverb,
+    Compressor,
+    Distortion,
+    Bitcrusher,
+    Decimator,
+}
+
+impl EffectType {
+    pub const ALL: [EffectType; 7] = [
+        EffectType::Utility,
+        EffectType::Delay,
+        EffectType::Reverb,
+        EffectType::Compressor,
+        EffectType::Distortion,
+        EffectType::Bitcrusher,
+        EffectType::Decimator,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectType::Utility => "Utility",
+            EffectType::Delay => "Delay",
+            EffectType::Reverb => "Reverb",
+            EffectType::Compressor => "Compressor",
+            EffectType::Distortion => "Distortion",
+            EffectType::Bitcrusher => "Bitcrusher",
+            EffectType::Decimator => "Decimator",
+        }
+    }
+}
+
+// スロットの id の払い出し
+static NEXT_EFFECT_ID: AtomicU32 = AtomicU32::new(0);
+
+// チェーンに挿したエフェクト
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectSlot {
+    pub id: u32, // オーディオスレッドでインスタンスを見分ける（複製しても同じ。保存はしない）
+    pub kind: EffectType,
+    pub values: Vec<f32>, // エフェクトのパラメータ（空なら既定値）
+    pub bypass: bool,
+}
+
+impl EffectSlot {
+    pub fn new(kind: EffectType) -> Self {
+        Self {
+            id: NEXT_EFFECT_ID.fetch_add(1, Ordering::Relaxed),
+            kind,
+            values: Vec::new(),
+            bypass: false,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Track {
     pub name: String,
     pub instrument: InstrumentType,
     pub volume: f32,
     pub pan: f32,        // -1.0 (L) to 1.0 (R)
+    pub filter_mode: FilterMode,
     pub filter_cutoff: f32,
     pub filter_resonance: f32,
-}
\ No newline at end of file
+    pub filter_env: f32,   // 発音ごとにカットオフを動かす量（オクターブ）
+    pub filter_decay: f32, // フィルターエンベロープの長さ（秒）
+    pub pitch: f32,      // 半音単位のオフセット
+    pub decay: f32,      // 減衰時間の倍率
+    pub mute: bool,
+    pub solo: bool,
+    pub choke: u8,       // チョークグループ（0 はなし）
+    pub send: f32,       // センドバスへ送る量（フェーダーの後）
+    pub sound: Vec<f32>, // 楽器の合成パラメータ（空なら楽器の既定値）
+    pub sample: Option<PathBuf>, // サンプラーで鳴らす WAV ファイル
+    pub mods: Vec<Route>,        // モジュレーションのルート（MAX_ROUTES まで）
+    pub effects: Vec<EffectSlot>, // インサートエフェクト（上から順にかける）
+}
+
+impl Track {
+    pub fn new(name: &str, instrument: InstrumentType) -> Self {
+        Self {
+            name: name.to_string(),
+            instrument,
+            volume: 1.0,
+            pan: 0.0,
+            filter_mode: FilterMode::LowPass,
+            filter_cutoff: 20000.0,
+            filter_resonance: 0.0,
+            filter_env: 0.0,
+            filter_decay: 0.3,
+            pitch: 0.0,
+            decay: 1.0,
+            mute: false,
+            solo: false,
+            choke: instrument.default_choke(),
+            send: 0.0,
+            sound: Vec::new(),
+            sample: None,
+            mods: Vec::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn param(&self, param: Param) -> f32 {
+        match param {
+            Param::Volume => self.volume,
+            Param::Pan => self.pan,
+            Param::FilterCutoff => self.filter_cutoff,
+            Param::FilterResonance => self.filter_resonance,
+            Param::FilterEnv => self.filter_env,
+            Param::Pitch => self.pitch,
+            Param::Decay => self.decay,
+        }
+    }
+
+    pub fn set_param(&mut self, param: Param, value: f32) {
+        let value = param.clamp(value);
+        match param {
+            Param::Volume => self.volume = value,
+            Param::Pan => self.pan = value,
+            Param::FilterCutoff => self.filter_cutoff = value,
+            Param::FilterResonance => self.filter_resonance = value,
+            Param::FilterEnv => self.filter_env = value,
+            Param::Pitch => self.pitch = value,
+            Param::Decay => self.decay = value,
+        }
+    }
+
+    // ロックがあればその値、なければトラックの値
+    pub fn resolve(&self, step: &Step, param: Param) -> f32 {
+        step.lock(param).unwrap_or_else(|| self.param(param))
+    }
+}
+
+// ミュート/ソロを考慮して、トラックが聞こえるか
+pub fn audible(tracks: &[Track], idx: usize) -> bool {
+    let track = &tracks[idx];
+    let any_solo = tracks.iter().any(|t| t.solo);
+    !track.mute && (!any_solo || track.solo)
+}
+
+// ステップごとにロックできるサウンドパラメータ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Volume,
+    Pan,
+    FilterCutoff,
+    FilterResonance,
+    FilterEnv,
+    Pitch,
+    Decay,
+}
+
+impl Param {
+    pub const ALL: [Param; 7] = [
+        Param::Volume,
+        Param::Pan,
+        Param::FilterCutoff,
+        Param::FilterResonance,
+        Param::FilterEnv,
+        Param::Pitch,
+        Param::Decay,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Param::Volume => "Volume",
+            Param::Pan => "Pan",
+            Param::FilterCutoff => "Cutoff",
+            Param::FilterResonance => "Resonance",
+            Param::FilterEnv => "Env",
+            Param::Pitch => "Pitch",
+            Param::Decay => "Decay",
+        }
+    }
+
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            Param::Volume => (0.0, 1.0),
+            Param::Pan => (-1.0, 1.0),
+            Param::FilterCutoff => (20.0, 20000.0),
+            Param::FilterResonance => (0.0, 1.0),
+            Param::FilterEnv => (-8.0, 8.0),
+            Param::Pitch => (-24.0, 24.0),
+            Param::Decay => (0.1, 4.0),
+        }
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        let (min, max) = self.range();
+        value.clamp(min, max)
+    }
+
+    // 1段階分だけ値を動かす（カットオフは指数的に）
+    pub fn nudge(&self, value: f32, dir: i32) -> f32 {
+        let dir = dir as f32;
+        let next = match self {
+            Param::Volume | Param::FilterResonance => value + 0.05 * dir,
+            Param::Pan => value + 0.1 * dir,
+            Param::FilterCutoff => value * 2.0_f32.powf(dir / 4.0),
+            Param::FilterEnv => value + 0.5 * dir,
+            Param::Pitch => value + dir,
+            Param::Decay => value + 0.1 * dir,
+        };
+        self.clamp(next)
+    }
+
+    // モジュレーション（-1.0〜1.0 に深さをかけた量）を足した値。
+    // 深さ 1.0 でカットオフは ±5 オクターブ、ピッチは ±12 半音、ディケイは 1/4〜4 倍
+    pub fn modulate(&self, value: f32, amount: f32) -> f32 {
+        if amount == 0.0 {
+            return value;
+        }
+        let next = match self {
+            Param::Volume | Param::Pan | Param::FilterResonance => value + amount,
+            Param::FilterCutoff => value * 2.0_f32.powf(amount * 5.0),
+            Param::FilterEnv => value + amount * 8.0,
+            Param::Pitch => value + amount * 12.0,
+            Param::Decay => value * 4.0_f32.powf(amount),
+        };
+        self.clamp(next)
+    }
+
+    pub fn format(&self, value: f32) -> String {
+        match self {
+            Param::FilterCutoff => format!("{:.0} Hz", value),
+            Param::Pitch => format!("{:+.0} st", value),
+            Param::FilterEnv => format!("{:+.1} oct", value),
+            Param::Decay => format!("x{:.1}", value),
+            _ => format!("{:.2}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks() -> Vec<Track> {
+        vec![Track::new("Kick", InstrumentType::Kick), Track::new("Bass", InstrumentType::Bass)]
+    }
+
+    // 適当に打ち込んだパターン
+    fn pattern() -> Pattern {
+        let mut pattern = Pattern::new(&tracks(), 16);
+        for (i, step) in pattern.steps[0].iter_mut().enumerate() {
+            step.active = i % 3 == 0;
+            step.velocity = 60 + i as u8;
+        }
+        pattern.steps[1][5].note = 40;
+        pattern.steps[1][5].set_lock(Param::Pitch, 3.0);
+        pattern
+    }
+
+    #[test]
+    fn rotate_there_and_back() {
+        let mut p = pattern();
+        p.rotate(0, 2..11, 1);
+        assert_ne!(p, pattern());
+        assert_eq!(p.steps[0][2], pattern().steps[0][10]);
+        p.rotate(0, 2..11, -1);
+        assert_eq!(p, pattern());
+    }
+
+    #[test]
+    fn reverse_twice() {
+        let mut p = pattern();
+        p.reverse(0, 0..16);
+        assert_eq!(p.steps[0][0], pattern().steps[0][15]);
+        p.reverse(0, 0..16);
+        assert_eq!(p, pattern());
+    }
+
+    #[test]
+    fn invert_twice() {
+        let mut p = pattern();
+        p.invert(0, 4..8);
+        assert_ne!(p.steps[0][4].active, pattern().steps[0][4].active);
+        p.invert(0, 4..8);
+        assert_eq!(p, pattern());
+    }
+
+    #[test]
+    fn double_then_halve() {
+        let mut p = pattern();
+        assert!(p.double());
+        assert_eq!(p.length, 32);
+        assert_eq!(p.steps[1][21], pattern().steps[1][5]);
+        assert!(p.halve());
+        assert_eq!(p, pattern());
+
+        // 上限を超えて伸ばしたり、1ステップより短くはしない
+        let mut long = Pattern::new(&tracks(), MAX_LENGTH);
+        assert!(!long.double());
+        let mut short = Pattern::new(&tracks(), 1);
+        assert!(!short.halve());
+    }
+
+    #[test]
+    fn cut_and_paste_restores_the_block() {
+        let mut p = pattern();
+        let block = p.copy_block(0..2, 3..9);
+        p.clear_block(0..2, 3..9);
+        assert_ne!(p, pattern());
+        p.paste_block(0, 3, &block);
+        assert_eq!(p, pattern());
+
+        // はみ出した分は捨てる
+        p.paste_block(1, 14, &block);
+        assert_eq!(p.steps.len(), 2);
+        assert_eq!(p.steps[1].len(), 16);
+        assert_eq!(p.steps[1][14], block[0][0]);
+    }
+
+    #[test]
+    fn any_edit_makes_a_pattern_non_empty() {
+        let tracks = tracks();
+        assert!(Pattern::new(&tracks, 16).is_empty(&tracks));
+        let edits: [fn(&mut Pattern); 7] = [
+            |p| p.length = 8,
+            |p| p.steps[1][0].note = 30,
+            |p| p.steps[1][0].velocity = 90,
+            |p| p.steps[1][0].length = 2,
+            |p| p.steps[1][0].chord = 1,
+            |p| p.steps[1][0].accent = true,
+            |p| p.steps[1][0].slide = true,
+        ];
+        for edit in edits {
+            let mut p = Pattern::new(&tracks, 16);
+            edit(&mut p);
+            assert!(!p.is_empty(&tracks));
+        }
+    }
+
+    #[test]
+    fn solo_overrides_mute_of_other_tracks() {
+        let mut tracks = tracks();
+        tracks.push(Track::new("Snare", InstrumentType::Snare));
+        assert!((0..3).all(|i| audible(&tracks, i)));
+
+        tracks[0].mute = true;
+        assert_eq!((0..3).map(|i| audible(&tracks, i)).collect::<Vec<_>>(), [false, true, true]);
+
+        // ソロがあればソロのトラックだけ。ミュートしたトラックはソロでも聞こえない
+        tracks[1].solo = true;
+        assert_eq!((0..3).map(|i| audible(&tracks, i)).collect::<Vec<_>>(), [false, true, false]);
+        tracks[0].solo = true;
+        assert_eq!((0..3).map(|i| audible(&tracks, i)).collect::<Vec<_>>(), [false, true, false]);
+    }
+}
@@ -0,0 +1,54 @@
+// src/sequencer/song.rs
+// ソングモード：バンクを並べて曲にする
+
+pub const MIN_BPM: f32 = 20.0;
+pub const MAX_BPM: f32 = 300.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub bank: usize,
+    pub repeats: usize,
+    pub bpm: f32,
+    pub ramp: bool, // このセクションの間に次のセクションのテンポへ近づける
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Song {
+    pub sections: Vec<Section>,
+}
+
+impl Song {
+    // セクション内の進み具合 (0.0〜1.0) でのテンポ
+    pub fn tempo_at(&self, section: usize, progress: f32) -> f32 {
+        let current = &self.sections[section];
+        if !current.ramp {
+            return current.bpm;
+        }
+        let next = &self.sections[(section + 1) % self.sections.len()];
+        current.bpm + (next.bpm - current.bpm) * progress.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(bpm: f32, ramp: bool) -> Section {
+        Section { bank: 0, repeats: 1, bpm, ramp }
+    }
+
+    #[test]
+    fn ramp_moves_towards_the_next_section() {
+        let song = Song { sections: vec![section(100.0, true), section(140.0, false), section(80.0, true)] };
+        assert_eq!(song.tempo_at(0, 0.0), 100.0);
+        assert_eq!(song.tempo_at(0, 0.5), 120.0);
+        assert_eq!(song.tempo_at(0, 1.0), 140.0);
+
+        // ランプのないセクションは一定
+        assert_eq!(song.tempo_at(1, 0.75), 140.0);
+
+        // 最後のセクションは曲の頭のテンポへ
+        assert_eq!(song.tempo_at(2, 0.25), 85.0);
+        assert_eq!(song.tempo_at(2, 2.0), 100.0);
+    }
+}